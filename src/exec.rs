@@ -1,28 +1,48 @@
 use std::any::Any;
 use std::sync::Arc;
-use std::io::Write;
-use std::collections::VecDeque;
-
-use template::Template;
-use utils::is_true;
+use std::fmt;
+use std::io;
+use std::iter;
+use std::mem;
+use std::collections::{HashMap, VecDeque};
+
+use template::{MissingKey, Template};
+use parse::{parse, Tree};
+use utils::{indirect, is_true, to_go_string, type_name};
 use node::*;
+use funcs;
 
 use gtmpl_value::{Func, Value};
 
+// Maximum nesting of `{{template}}` calls, unless set via `Template::max_depth`. Unlike Go we
+// can't grow the stack, so this is far below Go's limit to stay within a 2MB thread stack.
+pub(crate) const MAX_EXEC_DEPTH: usize = 100;
+
+// Variable scopes reserved up front. A scope is pushed for each control structure and range
+// iteration, so this covers moderately nested templates without regrowing the stack.
+const INITIAL_SCOPES: usize = 8;
+
+// Name of the template parsed from the text passed to `tpl`.
+pub(crate) const TPL_NAME: &str = "tpl";
+
 struct Variable {
     name: String,
     value: Arc<Any>,
 }
 
-struct State<'a, 'b, T: Write>
+struct State<'a, 'b, T: fmt::Write>
 where
     T: 'b,
 {
     template: &'a Template<'a>,
+    tree: &'a Tree,
     writer: &'b mut T,
     node: Option<&'a Nodes>,
     vars: VecDeque<VecDeque<Variable>>,
     depth: usize,
+    // Templates defined in text rendered by `tpl`. They are looked up before the ones
+    // of the template.
+    tpl_trees: Option<&'a HashMap<String, Tree>>,
 }
 
 /// A Context for the template. Passed to the template exectution.
@@ -33,6 +53,15 @@ pub struct Context {
 #[derive(Clone, Debug)]
 struct Nothing {}
 
+// Signals how execution continues after walking a node. `Break` and `Continue` bubble up to
+// the innermost enclosing range.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Flow {
+    Next,
+    Break,
+    Continue,
+}
+
 impl Context {
     pub fn empty() -> Context {
         Context {
@@ -40,26 +69,80 @@ impl Context {
         }
     }
 
-    pub fn from<T>(value: T) -> Result<Context, String>
+    /// Creates a context from anything that converts into a `Value`. The conversion
+    /// can't fail, so neither can this.
+    ///
+    /// ## Example
+    /// ```rust
+    /// use gtmpl::{Context, Template};
+    ///
+    /// let mut tmpl = Template::default();
+    /// tmpl.parse("{{ . }}").unwrap();
+    /// let output = tmpl.render(&Context::from(42));
+    /// assert_eq!(&output.unwrap(), "42");
+    /// ```
+    pub fn from<T>(value: T) -> Context
     where
         T: Into<Value>,
     {
-        let serialized = Value::from(value);
-        Ok(Context {
-            dot: Arc::new(serialized),
-        })
+        Context {
+            dot: Arc::new(value.into()),
+        }
     }
 
     pub fn from_any(value: Arc<Any>) -> Context {
         Context { dot: value }
     }
+
+    /// Creates a context sharing an already built `Value`. Nothing is cloned or converted,
+    /// so the same value can be rendered repeatedly at the cost of an `Arc::clone`.
+    ///
+    /// ## Example
+    /// ```rust
+    /// use std::sync::Arc;
+    /// use gtmpl::{Context, Template, Value};
+    ///
+    /// let value = Arc::new(Value::from(vec![1, 2, 3]));
+    /// let mut tmpl = Template::default();
+    /// tmpl.parse("{{ len . }}").unwrap();
+    /// for _ in 0..3 {
+    ///     let output = tmpl.render(&Context::from_arc(Arc::clone(&value)));
+    ///     assert_eq!(&output.unwrap(), "3");
+    /// }
+    /// ```
+    pub fn from_arc(value: Arc<Value>) -> Context {
+        Context { dot: value }
+    }
+
+    /// Creates a context from a `serde_json::Value`. `null` becomes `Value::Nil`, objects
+    /// become `Value::Map`s and numbers keep being integers or floats. Requires the
+    /// `serde_json` feature.
+    ///
+    /// ## Example
+    /// ```rust
+    /// # #[cfg(feature = "serde_json")]
+    /// # {
+    /// extern crate serde_json;
+    /// use gtmpl::{Context, Template};
+    ///
+    /// let data: serde_json::Value = serde_json::from_str(r#"{"name": "gtmpl"}"#).unwrap();
+    /// let mut tmpl = Template::default();
+    /// tmpl.parse("Hello {{ .name }}!").unwrap();
+    /// let output = tmpl.render(&Context::from_json(data));
+    /// assert_eq!(&output.unwrap(), "Hello gtmpl!");
+    /// # }
+    /// ```
+    #[cfg(feature = "serde_json")]
+    pub fn from_json(value: ::serde_json::Value) -> Context {
+        Context::from(::json::from_serde_json(value))
+    }
 }
 
 macro_rules! print_val {
     ($val:ident : $out:ident <- $($typ:ty,)*) => {
         $(
             if let Some(v) = $val.downcast_ref::<$typ>() {
-                write!($out.writer, "{}", v).map_err(|e| format!("{}", e))?;
+                write!($out.writer, "{}", v).map_err(write_error)?;
                 return Ok(())
             }
         )*
@@ -67,41 +150,268 @@ macro_rules! print_val {
 }
 
 impl<'a, 'b> Template<'a> {
-    pub fn execute<T: Write>(&self, writer: &'b mut T, data: &Context) -> Result<(), String> {
-        let mut vars: VecDeque<VecDeque<Variable>> = VecDeque::new();
-        let mut dot = VecDeque::new();
-        dot.push_back(Variable {
-            name: "$".to_owned(),
-            value: Arc::clone(&data.dot),
-        });
-        vars.push_back(dot);
+    /// Executes the template with the given context and writes the output to `writer`.
+    /// Output is written as the template is walked, so on error `writer` may have received
+    /// partial output.
+    pub fn execute<T: io::Write>(&self, writer: &'b mut T, data: &Context) -> Result<(), String> {
+        let tree = self.root_tree()?;
+        self.execute_io(tree, writer, data)
+    }
+
+    /// Executes the associated template with the given `name` instead of the main
+    /// template, like `execute` does otherwise.
+    ///
+    /// ## Example
+    /// ```rust
+    /// use gtmpl::{Context, Template};
+    ///
+    /// let mut tmpl = Template::default();
+    /// tmpl.parse(r#"{{ define "hello" }}Hello {{ . }}!{{ end }}"#).unwrap();
+    /// let mut w: Vec<u8> = vec![];
+    /// tmpl.execute_template(&mut w, "hello", &Context::from("World")).unwrap();
+    /// assert_eq!(String::from_utf8(w).unwrap(), "Hello World!");
+    /// ```
+    pub fn execute_template<T: io::Write>(
+        &self,
+        writer: &'b mut T,
+        name: &str,
+        data: &Context,
+    ) -> Result<(), String> {
+        let tree = self.tree_set
+            .get(name)
+            .filter(|tree| tree.root.is_some())
+            .ok_or_else(|| {
+                format!(
+                    "template: no template {:?} associated with template {:?}",
+                    name, self.name
+                )
+            })?;
+        self.execute_io(tree, writer, data)
+    }
+
+    /// Executes the template like `execute`, but writes to a `fmt::Write` such as a `String`.
+    ///
+    /// ## Example
+    /// ```rust
+    /// use gtmpl::{Context, Template};
+    ///
+    /// let mut tmpl = Template::default();
+    /// tmpl.parse("Hello {{ . }}!").unwrap();
+    /// let mut output = String::new();
+    /// tmpl.execute_fmt(&mut output, &Context::from("world")).unwrap();
+    /// assert_eq!(&output, "Hello world!");
+    /// ```
+    pub fn execute_fmt<T: fmt::Write>(
+        &self,
+        writer: &'b mut T,
+        data: &Context,
+    ) -> Result<(), String> {
+        let tree = self.root_tree()?;
+        self.execute_tree(tree, writer, data)
+    }
+
+    /// Executes the template with the given context and returns the output as a `String`.
+    pub fn render(&self, data: &Context) -> Result<String, String> {
+        let mut w = String::new();
+        self.execute_fmt(&mut w, data)?;
+        Ok(w)
+    }
+
+    /// Evaluates a template consisting of a single action and returns the resulting
+    /// value instead of printing it.
+    ///
+    /// ## Example
+    /// ```rust
+    /// use gtmpl::{Context, Template, Value};
+    ///
+    /// let mut tmpl = Template::default();
+    /// tmpl.parse("{{ add . 2 }}").unwrap();
+    /// let value = tmpl.eval(&Context::from(1));
+    /// assert_eq!(value, Ok(Value::from(3)));
+    /// ```
+    pub fn eval(&self, data: &Context) -> Result<Value, String> {
+        let tree = self.root_tree()?;
+        let action = match tree.root {
+            Some(Nodes::List(ref list)) if list.nodes.len() == 1 => match list.nodes[0] {
+                Nodes::Action(ref n) => Some((&list.nodes[0], n)),
+                _ => None,
+            },
+            _ => None,
+        };
+        let (node, action) =
+            action.ok_or_else(|| format!("{} does not consist of a single action", self.name))?;
+        let mut w = String::new();
+        let mut state = State {
+            template: self,
+            tree,
+            writer: &mut w,
+            node: Some(node),
+            vars: initial_vars(data),
+            depth: 0,
+            tpl_trees: None,
+        };
+        let val = state
+            .eval_pipeline(data, &action.pipe)
+            .map_err(|e| state.error(&e))?;
+        indirect(&*val)
+            .cloned()
+            .ok_or_else(|| format!("unable to evaluate value of type {}", type_name(&*val)))
+    }
+
+    fn execute_io<T: io::Write>(
+        &self,
+        tree: &Tree,
+        writer: &mut T,
+        data: &Context,
+    ) -> Result<(), String> {
+        let mut w = IoWriter {
+            inner: writer,
+            error: None,
+        };
+        let res = self.execute_tree(tree, &mut w, data);
+        match (w.error, res) {
+            // The failed write reports its location, the writer keeps the cause.
+            (Some(e), Err(msg)) => Err(format!("{}: {}", msg, e)),
+            (Some(e), Ok(())) => Err(format!(
+                "template: {}: {}: {}",
+                self.name,
+                write_error(fmt::Error),
+                e
+            )),
+            (None, res) => res,
+        }
+    }
 
+    fn execute_tree<T: fmt::Write>(
+        &self,
+        tree: &Tree,
+        writer: &mut T,
+        data: &Context,
+    ) -> Result<(), String> {
         let mut state = State {
             template: self,
+            tree,
             writer,
             node: None,
-            vars,
+            vars: initial_vars(data),
             depth: 0,
+            tpl_trees: None,
         };
 
-        let root = self.tree_ids
-            .get(&1usize)
-            .and_then(|name| self.tree_set.get(name))
-            .and_then(|tree| tree.root.as_ref())
-            .ok_or_else(|| format!("{} is an incomplete or empty template", self.name))?;
-        state.walk(data, root)?;
+        if let Some(ref root) = tree.root {
+            state.walk(data, root).map_err(|e| state.error(&e))?;
+        }
 
         Ok(())
     }
 
-    pub fn render(&self, data: &Context) -> Result<String, String> {
-        let mut w: Vec<u8> = vec![];
-        self.execute(&mut w, data)?;
-        String::from_utf8(w).map_err(|e| format!("unable to contert output into utf8: {}", e))
+    // Renders `text` with the functions, options and templates of this template, starting
+    // at the template nesting `depth` of the caller. Templates defined in the text are kept
+    // apart, along with those of an enclosing `tpl` in `outer`, so the template isn't
+    // copied for every call.
+    fn render_tpl(
+        &self,
+        text: &str,
+        data: &Context,
+        depth: usize,
+        outer: Option<&HashMap<String, Tree>>,
+    ) -> Result<String, String> {
+        if depth >= self.max_depth.unwrap_or(MAX_EXEC_DEPTH) {
+            return Err(String::from("exceeded maximum template depth"));
+        }
+        let parser = parse(
+            TPL_NAME,
+            text,
+            &self.left_delim,
+            &self.right_delim,
+            self.funcs.clone(),
+        )?;
+        let mut trees = outer.cloned().unwrap_or_default();
+        for (name, tree) in parser.tree_set {
+            // Like `parse`, empty definitions don't replace existing templates.
+            let exists = trees.contains_key(&name) || self.tree_set.contains_key(&name);
+            if name != TPL_NAME && exists && tree.is_empty()? {
+                continue;
+            }
+            trees.insert(name, tree);
+        }
+        let tree = trees
+            .get(TPL_NAME)
+            .filter(|tree| tree.root.is_some())
+            .ok_or_else(|| format!("{} is an incomplete or empty template", TPL_NAME))?;
+        let mut w = String::new();
+        {
+            let mut state = State {
+                template: self,
+                tree,
+                writer: &mut w,
+                node: None,
+                vars: initial_vars(data),
+                depth: depth + 1,
+                tpl_trees: Some(&trees),
+            };
+            if let Some(ref root) = tree.root {
+                state.walk(data, root).map_err(|e| state.error(&e))?;
+            }
+        }
+        Ok(w)
+    }
+
+    // Looked up on every execution, as `tree_ids` may change in between.
+    fn root_tree(&self) -> Result<&Tree, String> {
+        self.tree_ids
+            .get(&1)
+            .and_then(|name| self.tree_set.get(name))
+            .filter(|tree| tree.root.is_some())
+            .ok_or_else(|| format!("{} is an incomplete or empty template", self.name))
+    }
+}
+
+// Sets up the variables for an execution, with `$` bound to the data. This is the only
+// allocation an execution needs up front: scopes pushed while walking start out empty and
+// only allocate once a variable is declared in them, so there is little to gain from
+// reusing the stack across executions.
+fn initial_vars(data: &Context) -> VecDeque<VecDeque<Variable>> {
+    let mut vars = VecDeque::with_capacity(INITIAL_SCOPES);
+    let mut dot = VecDeque::with_capacity(1);
+    dot.push_back(Variable {
+        name: "$".to_owned(),
+        value: Arc::clone(&data.dot),
+    });
+    vars.push_back(dot);
+    vars
+}
+
+// Describes a failed write; the error is located like any other execution error.
+fn write_error(_: fmt::Error) -> String {
+    String::from("error writing output")
+}
+
+// Adapts an `io::Write` for `execute_fmt`. `fmt::Error` carries no details, so the
+// underlying error is kept for reporting.
+struct IoWriter<'w, W: 'w + io::Write> {
+    inner: &'w mut W,
+    error: Option<io::Error>,
+}
+
+impl<'w, W: io::Write> fmt::Write for IoWriter<'w, W> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.inner.write_all(s.as_bytes()).map_err(|e| {
+            self.error = Some(e);
+            fmt::Error
+        })
     }
 }
 
-impl<'a, 'b, T: Write> State<'a, 'b, T> {
+impl<'a, 'b, T: fmt::Write> State<'a, 'b, T> {
+    // Prefixes an execution error with the template name and the line of the current node.
+    fn error(&self, msg: &str) -> String {
+        match self.node {
+            Some(node) => format!("template: {}: {}", self.tree.error_context(node), msg),
+            None => format!("template: {}: {}", self.template.name, msg),
+        }
+    }
+
     fn set_kth_last_var_value(&mut self, k: usize, value: Arc<Any>) -> Result<(), String> {
         if let Some(last_vars) = self.vars.back_mut() {
             let i = last_vars.len() - k;
@@ -114,6 +424,18 @@ impl<'a, 'b, T: Write> State<'a, 'b, T> {
         Err(String::from("empty var stack"))
     }
 
+    fn set_var_value(&mut self, key: &str, value: Arc<Any>) -> Result<(), String> {
+        for context in self.vars.iter_mut().rev() {
+            for var in context.iter_mut().rev() {
+                if var.name == key {
+                    var.value = value;
+                    return Ok(());
+                }
+            }
+        }
+        Err(format!("variable {} not found", key))
+    }
+
     fn var_value(&self, key: &str) -> Result<Arc<Any>, String> {
         for context in self.vars.iter().rev() {
             for var in context.iter().rev() {
@@ -125,16 +447,19 @@ impl<'a, 'b, T: Write> State<'a, 'b, T> {
         Err(format!("variable {} not found", key))
     }
 
-    fn walk_list(&mut self, ctx: &Context, node: &'a ListNode) -> Result<(), String> {
+    fn walk_list(&mut self, ctx: &Context, node: &'a ListNode) -> Result<Flow, String> {
         for n in &node.nodes {
-            self.walk(ctx, n)?;
+            let flow = self.walk(ctx, n)?;
+            if flow != Flow::Next {
+                return Ok(flow);
+            }
         }
-        Ok(())
+        Ok(Flow::Next)
     }
 
     // Top level walk function. Steps through the major parts for the template strcuture and
     // writes to the output.
-    fn walk(&mut self, ctx: &Context, node: &'a Nodes) -> Result<(), String> {
+    fn walk(&mut self, ctx: &Context, node: &'a Nodes) -> Result<Flow, String> {
         self.node = Some(node);
         match *node {
             Nodes::Action(ref n) => {
@@ -142,49 +467,71 @@ impl<'a, 'b, T: Write> State<'a, 'b, T> {
                 if n.pipe.decl.is_empty() {
                     self.print_value(&val)?;
                 }
-                Ok(())
             }
-            Nodes::If(_) | Nodes::With(_) => self.walk_if_or_with(node, ctx),
-            Nodes::Range(ref n) => self.walk_range(ctx, n),
-            Nodes::List(ref n) => self.walk_list(ctx, n),
-            Nodes::Text(ref n) => write!(self.writer, "{}", n).map_err(|e| format!("{}", e)),
-            Nodes::Template(ref n) => self.walk_template(ctx, n),
-            _ => Err(format!("unknown node: {}", node)),
+            Nodes::If(_) | Nodes::With(_) | Nodes::Range(_) => {
+                // Variables declared by a control structure are scoped to it.
+                self.vars.push_back(VecDeque::new());
+                let flow = match *node {
+                    Nodes::Range(ref n) => self.walk_range(ctx, n)?,
+                    _ => self.walk_if_or_with(node, ctx)?,
+                };
+                self.vars.pop_back();
+                return Ok(flow);
+            }
+            Nodes::List(ref n) => return self.walk_list(ctx, n),
+            Nodes::Text(ref n) => write!(self.writer, "{}", n).map_err(write_error)?,
+            Nodes::Template(ref n) => self.walk_template(ctx, n)?,
+            Nodes::Break(_) => return Ok(Flow::Break),
+            Nodes::Continue(_) => return Ok(Flow::Continue),
+            _ => return Err(format!("unknown node: {}", node)),
         }
+        Ok(Flow::Next)
     }
 
     fn walk_template(&mut self, ctx: &Context, template: &TemplateNode) -> Result<(), String> {
-        let tree = self.template.tree_set.get(&template.name);
-        if let Some(tree) = tree {
-            if let Some(ref root) = tree.root {
-                let mut vars = VecDeque::new();
-                let mut dot = VecDeque::new();
-                dot.push_back(Variable {
-                    name: "$".to_owned(),
-                    value: Arc::clone(&ctx.dot),
-                });
-                vars.push_back(dot);
-                let mut new_state = State {
-                    template: self.template,
-                    writer: self.writer,
-                    node: None,
-                    vars,
-                    depth: self.depth + 1,
-                };
-                return new_state.walk(ctx, root);
+        if self.depth >= self.template.max_depth.unwrap_or(MAX_EXEC_DEPTH) {
+            return Err(String::from("exceeded maximum template depth"));
+        }
+        let tree = self.tpl_trees
+            .and_then(|trees| trees.get(&template.name))
+            .or_else(|| self.template.tree_set.get(&template.name))
+            .ok_or_else(|| {
+                format!(
+                    "no template {:?} associated with template {:?}",
+                    template.name, self.template.name
+                )
+            })?;
+        if let Some(ref root) = tree.root {
+            let ctx = match template.pipe {
+                Some(ref pipe) => Context::from_any(self.eval_pipeline(ctx, pipe)?),
+                None => Context::from_any(Arc::clone(&ctx.dot)),
+            };
+            let mut new_state = State {
+                template: self.template,
+                tree,
+                writer: self.writer,
+                node: None,
+                vars: initial_vars(&ctx),
+                depth: self.depth + 1,
+                tpl_trees: self.tpl_trees,
+            };
+            if let Err(e) = new_state.walk(&ctx, root) {
+                // Report the location inside the called template.
+                self.tree = new_state.tree;
+                self.node = new_state.node;
+                return Err(e);
             }
         }
-        Err(String::from("work in progress"))
+        Ok(())
     }
 
     fn eval_pipeline(&mut self, ctx: &Context, pipe: &PipeNode) -> Result<Arc<Any>, String> {
-        let mut val: Option<Arc<Any>> = None;
-        for cmd in &pipe.cmds {
-            val = Some(self.eval_command(ctx, cmd, &val)?);
-            // TODO
-        }
-        let val = val.ok_or_else(|| format!("error evaluating pipeline {}", pipe))?;
+        let val = self.eval_pipeline_value(ctx, pipe)?;
         for var in &pipe.decl {
+            if pipe.is_assign {
+                self.set_var_value(&var.ident[0], Arc::clone(&val))?;
+                continue;
+            }
             self.vars
                 .back_mut()
                 .and_then(|v| {
@@ -198,6 +545,16 @@ impl<'a, 'b, T: Write> State<'a, 'b, T> {
         Ok(val)
     }
 
+    // Evaluates the commands of a pipeline without declaring or assigning its variables.
+    fn eval_pipeline_value(&mut self, ctx: &Context, pipe: &PipeNode) -> Result<Arc<Any>, String> {
+        let mut val: Option<Arc<Any>> = None;
+        for cmd in &pipe.cmds {
+            val = Some(self.eval_command(ctx, cmd, &val)?);
+            // TODO
+        }
+        val.ok_or_else(|| format!("error evaluating pipeline {}", pipe))
+    }
+
     fn eval_command(
         &mut self,
         ctx: &Context,
@@ -210,7 +567,7 @@ impl<'a, 'b, T: Write> State<'a, 'b, T> {
 
         match *(*first_word) {
             Nodes::Field(ref n) => return self.eval_field_node(ctx, n, &cmd.args, val),
-            Nodes::Variable(ref n) => return self.eval_variable_node(n, &cmd.args, val),
+            Nodes::Variable(ref n) => return self.eval_variable_node(ctx, n, &cmd.args, val),
             Nodes::Pipe(ref n) => return self.eval_pipeline(ctx, n),
             Nodes::Chain(ref n) => return self.eval_chain_node(ctx, n, &cmd.args, val),
             Nodes::Identifier(ref n) => return self.eval_function(ctx, n, &cmd.args, val),
@@ -221,6 +578,7 @@ impl<'a, 'b, T: Write> State<'a, 'b, T> {
             Nodes::Bool(ref n) => Ok(Arc::clone(&n.value) as Arc<Any>),
             Nodes::Dot(_) => Ok(Arc::clone(&ctx.dot)),
             Nodes::Number(ref n) => Ok(Arc::clone(&n.value) as Arc<Any>),
+            Nodes::String(ref n) => Ok(Arc::clone(&n.value) as Arc<Any>),
             _ => Err(format!("cannot evaluate command {}", first_word)),
         }
     }
@@ -237,9 +595,55 @@ impl<'a, 'b, T: Write> State<'a, 'b, T> {
             .funcs
             .get(name.as_str())
             .ok_or_else(|| format!("{} is not a defined function", name))?;
+        if !self.template.custom_funcs.contains(name.as_str()) {
+            match name.as_str() {
+                "and" => return self.eval_and_or(ctx, true, args, fin),
+                "or" => return self.eval_and_or(ctx, false, args, fin),
+                "tpl" => return self.eval_tpl(ctx, args, fin),
+                _ => {}
+            }
+        }
         self.eval_call(ctx, function, args, fin)
     }
 
+    // Unlike the standalone `funcs::tpl`, this gives the rendered text access to the
+    // functions and templates of the executing template.
+    fn eval_tpl(
+        &mut self,
+        ctx: &Context,
+        args: &[Nodes],
+        fin: &Option<Arc<Any>>,
+    ) -> Result<Arc<Any>, String> {
+        let arg_vals = self.eval_args(ctx, args, fin)?;
+        let (text, data) = funcs::tpl_args(&arg_vals)?;
+        self.template
+            .render_tpl(text, &data, self.depth, self.tpl_trees)
+            .map(|s| Arc::new(Value::from(s)) as Arc<Any>)
+            .map_err(|e| format!("error calling tpl: {}", e))
+    }
+
+    // The builtins `and` and `or` only evaluate their arguments until the result is known.
+    fn eval_and_or(
+        &mut self,
+        ctx: &Context,
+        and: bool,
+        args: &[Nodes],
+        fin: &Option<Arc<Any>>,
+    ) -> Result<Arc<Any>, String> {
+        let mut val = None;
+        for arg in &args[1..] {
+            let v = self.eval_arg(ctx, arg)?;
+            if is_true(&v) != and {
+                return Ok(v);
+            }
+            val = Some(v);
+        }
+        if let Some(ref f) = *fin {
+            return Ok(Arc::clone(f));
+        }
+        val.ok_or_else(|| format!("{} needs at least one argument", args[0]))
+    }
+
     fn eval_call(
         &mut self,
         ctx: &Context,
@@ -247,6 +651,17 @@ impl<'a, 'b, T: Write> State<'a, 'b, T> {
         args: &[Nodes],
         fin: &Option<Arc<Any>>,
     ) -> Result<Arc<Any>, String> {
+        let arg_vals = self.eval_args(ctx, args, fin)?;
+        function(&arg_vals)
+    }
+
+    // Evaluates the arguments of a function call, the final value of a pipeline last.
+    fn eval_args(
+        &mut self,
+        ctx: &Context,
+        args: &[Nodes],
+        fin: &Option<Arc<Any>>,
+    ) -> Result<Vec<Arc<Any>>, String> {
         let mut arg_vals = vec![];
         for arg in &args[1..] {
             let val = self.eval_arg(ctx, arg)?;
@@ -255,8 +670,7 @@ impl<'a, 'b, T: Write> State<'a, 'b, T> {
         if let Some(ref f) = *fin {
             arg_vals.push(Arc::clone(f));
         }
-
-        function(&arg_vals)
+        Ok(arg_vals)
     }
 
     fn eval_chain_node(
@@ -273,15 +687,15 @@ impl<'a, 'b, T: Write> State<'a, 'b, T> {
             return Err(format!("inderection throug explicit nul in {}", chain));
         }
         let pipe = self.eval_arg(ctx, &*chain.node)?;
-        self.eval_field_chain(&pipe, &chain.field, args, fin)
+        self.eval_field_chain(ctx, &pipe, &chain.field, args, fin)
     }
 
     fn eval_arg(&mut self, ctx: &Context, node: &Nodes) -> Result<Arc<Any>, String> {
         match *node {
             Nodes::Dot(_) => Ok(Arc::clone(&ctx.dot)),
-            //Nodes::Nil
+            Nodes::Nil(_) => Ok(Arc::new(Value::Nil)),
             Nodes::Field(ref n) => self.eval_field_node(ctx, n, &[], &None), // args?
-            Nodes::Variable(ref n) => self.eval_variable_node(n, &[], &None),
+            Nodes::Variable(ref n) => self.eval_variable_node(ctx, n, &[], &None),
             Nodes::Pipe(ref n) => self.eval_pipeline(ctx, n),
             // Nodes::Identifier
             Nodes::Chain(ref n) => self.eval_chain_node(ctx, n, &[], &None),
@@ -299,11 +713,12 @@ impl<'a, 'b, T: Write> State<'a, 'b, T> {
         args: &[Nodes],
         fin: &Option<Arc<Any>>,
     ) -> Result<Arc<Any>, String> {
-        self.eval_field_chain(&ctx.dot, &field.ident, args, fin)
+        self.eval_field_chain(ctx, &ctx.dot, &field.ident, args, fin)
     }
 
     fn eval_field_chain(
         &mut self,
+        ctx: &Context,
         receiver: &Arc<Any>,
         ident: &[String],
         args: &[Nodes],
@@ -313,24 +728,45 @@ impl<'a, 'b, T: Write> State<'a, 'b, T> {
         if n < 1 {
             return Err(String::from("field chain without fields :/"));
         }
-        // TODO clean shit up
-        let mut r: Arc<Any> = Arc::new(0);
-        for (i, id) in ident.iter().enumerate().take(n - 1) {
-            r = self.eval_field(if i == 0 { receiver } else { &r }, id, &[], &None)?;
+        let mut r = Arc::clone(receiver);
+        for id in &ident[..n - 1] {
+            r = self.eval_field(ctx, &r, id, &[], &None)?;
+            // A function in the middle of a chain is invoked like a method without arguments.
+            let method = indirect(&*r).and_then(|val| match *val {
+                Value::Function(ref f) => Some(f.f),
+                _ => None,
+            });
+            if let Some(f) = method {
+                r = f(&[])?;
+            }
         }
-        self.eval_field(if n == 1 { receiver } else { &r }, &ident[n - 1], args, fin)
+        self.eval_field(ctx, &r, &ident[n - 1], args, fin)
     }
 
     fn eval_field(
         &mut self,
+        ctx: &Context,
         receiver: &Arc<Any>,
         field_name: &str,
         args: &[Nodes],
         fin: &Option<Arc<Any>>,
     ) -> Result<Arc<Any>, String> {
         let has_args = args.len() > 1 || fin.is_some();
-        if let Some(val) = receiver.downcast_ref::<Value>() {
+        if let Some(val) = indirect(&**receiver) {
+            // A field holding a function is invoked like a method if arguments are given.
             if has_args {
+                let method = match *val {
+                    Value::Object(ref o) | Value::Map(ref o) => {
+                        o.get(field_name).and_then(|v| match *v {
+                            Value::Function(ref f) => Some(f.f),
+                            _ => None,
+                        })
+                    }
+                    _ => None,
+                };
+                if let Some(f) = method {
+                    return self.eval_call(ctx, &f, args, fin);
+                }
                 return Err(format!(
                     "{} has arguments but cannot be invoked as function",
                     field_name
@@ -340,18 +776,43 @@ impl<'a, 'b, T: Write> State<'a, 'b, T> {
                 Value::Object(ref o) => o.get(field_name)
                     .map(|v| Arc::new(v.clone()) as Arc<Any>)
                     .ok_or_else(|| format!("no field {} for {}", field_name, val)),
-                Value::Map(ref o) => Ok(o.get(field_name)
-                    .map(|v| Arc::new(v.clone()) as Arc<Any>)
-                    .unwrap_or_else(|| Arc::new(Value::NoValue) as Arc<Any>)),
-                _ => Err(String::from("only maps and objects have fields")),
+                Value::Map(ref o) => match o.get(field_name) {
+                    Some(v) => Ok(Arc::new(v.clone())),
+                    None => match self.template.missing_key {
+                        MissingKey::Invalid => Ok(Arc::new(Value::NoValue)),
+                        MissingKey::Zero => Ok(Arc::new(zero_value(o))),
+                        MissingKey::Error => Err(format!("map has no entry for key {}", field_name)),
+                    },
+                },
+                Value::Array(ref a) => {
+                    let i = field_name.parse::<usize>().map_err(|_| {
+                        format!("can't index array with non-numeric field {}", field_name)
+                    })?;
+                    a.get(i)
+                        .map(|v| Arc::new(v.clone()) as Arc<Any>)
+                        .ok_or_else(|| format!("index out of range: {}", i))
+                }
+                // A missing map key further up the chain keeps the result empty.
+                Value::NoValue => match self.template.missing_key {
+                    MissingKey::Error => {
+                        Err(format!("nil data; no entry for key {}", field_name))
+                    }
+                    _ => Ok(Arc::new(Value::NoValue)),
+                },
+                _ => Err(format!("can't evaluate field {} in {}", field_name, val)),
             };
         }
 
-        Err(String::from("only basic fields are supported"))
+        Err(format!(
+            "can't evaluate field {} in value of type {}",
+            field_name,
+            type_name(&**receiver)
+        ))
     }
 
     fn eval_variable_node(
         &mut self,
+        ctx: &Context,
         variable: &VariableNode,
         args: &[Nodes],
         fin: &Option<Arc<Any>>,
@@ -361,37 +822,40 @@ impl<'a, 'b, T: Write> State<'a, 'b, T> {
             not_a_function(args, fin)?;
             return Ok(val);
         }
-        self.eval_field_chain(&val, &variable.ident[1..], args, fin)
+        self.eval_field_chain(ctx, &val, &variable.ident[1..], args, fin)
     }
 
     // Walks an `if` or `with` node. They behave the same, except that `wtih` sets dot.
-    fn walk_if_or_with(&mut self, node: &'a Nodes, ctx: &Context) -> Result<(), String> {
-        let pipe = match *node {
-            Nodes::If(ref n) | Nodes::With(ref n) => &n.pipe,
-            _ => return Err(format!("expected if or with node, got {}", node)),
-        };
-        let val = self.eval_pipeline(ctx, pipe)?;
-        let truth = is_true(&val);
-        if truth {
-            match *node {
-                Nodes::If(ref n) => self.walk_list(ctx, &n.list)?,
-                Nodes::With(ref n) => {
+    fn walk_if_or_with(&mut self, node: &'a Nodes, ctx: &Context) -> Result<Flow, String> {
+        let mut node = node;
+        loop {
+            let n = match *node {
+                Nodes::If(ref n) | Nodes::With(ref n) => n,
+                _ => return Err(format!("expected if or with node, got {}", node)),
+            };
+            let val = self.eval_pipeline(ctx, &n.pipe)?;
+            // `with` skips falsy values just like `if`, see the crate docs on truthiness.
+            if is_true(&val) {
+                if let Nodes::With(_) = *node {
                     let ctx = Context { dot: val };
-                    self.walk_list(&ctx, &n.list)?;
+                    return self.walk_list(&ctx, &n.list);
                 }
-                _ => {}
+                return self.walk_list(ctx, &n.list);
             }
-        } else {
-            match *node {
-                Nodes::If(ref n) | Nodes::With(ref n) => {
-                    if let Some(ref otherwise) = n.else_list {
-                        self.walk_list(ctx, otherwise)?;
-                    }
+            let otherwise = match n.else_list {
+                Some(ref otherwise) => otherwise,
+                None => return Ok(Flow::Next),
+            };
+            // The links of an `else if` or `else with` chain are walked in a loop, so a
+            // long chain doesn't grow the stack.
+            match otherwise.nodes[..] {
+                [ref chained @ Nodes::If(_)] | [ref chained @ Nodes::With(_)] => {
+                    self.node = Some(chained);
+                    node = chained;
                 }
-                _ => {}
+                _ => return self.walk_list(ctx, otherwise),
             }
         }
-        Ok(())
     }
 
     fn one_iteration(
@@ -399,38 +863,107 @@ impl<'a, 'b, T: Write> State<'a, 'b, T> {
         key: Value,
         val: Arc<Any>,
         range: &'a RangeNode,
-    ) -> Result<(), String> {
-        if !range.pipe.decl.is_empty() {
-            self.set_kth_last_var_value(1, Arc::clone(&val))?;
-        }
-        if range.pipe.decl.len() > 1 {
-            self.set_kth_last_var_value(2, Arc::new(key))?;
+    ) -> Result<Flow, String> {
+        if range.pipe.is_assign {
+            if range.pipe.decl.len() > 1 {
+                self.set_var_value(&range.pipe.decl[0].ident[0], Arc::new(key))?;
+                self.set_var_value(&range.pipe.decl[1].ident[0], Arc::clone(&val))?;
+            } else if let Some(var) = range.pipe.decl.first() {
+                self.set_var_value(&var.ident[0], Arc::clone(&val))?;
+            }
+        } else {
+            if !range.pipe.decl.is_empty() {
+                self.set_kth_last_var_value(1, Arc::clone(&val))?;
+            }
+            if range.pipe.decl.len() > 1 {
+                self.set_kth_last_var_value(2, Arc::new(key))?;
+            }
         }
-        let vars = VecDeque::new();
-        self.vars.push_back(vars);
+        self.vars.push_back(VecDeque::new());
         let ctx = Context { dot: val };
-        self.walk_list(&ctx, &range.list)?;
+        let flow = self.walk_list(&ctx, &range.list)?;
         self.vars.pop_back();
-        Ok(())
+        Ok(flow)
     }
 
-    fn walk_range(&mut self, ctx: &Context, range: &'a RangeNode) -> Result<(), String> {
-        let val = self.eval_pipeline(ctx, &range.pipe)?;
-        if let Some(value) = val.downcast_ref::<Value>() {
-            match *value {
-                Value::Object(ref map) | Value::Map(ref map) => for (k, v) in map.clone() {
-                    self.one_iteration(Value::from(k), Arc::new(v), range)?;
-                },
-                Value::Array(ref vec) => for (k, v) in vec.iter().enumerate() {
-                    self.one_iteration(Value::from(k), Arc::new(v.clone()), range)?;
-                },
-                _ => return Err(format!("invalid range: {:?}", value)),
+    fn walk_range(&mut self, ctx: &Context, range: &'a RangeNode) -> Result<Flow, String> {
+        // Assigned variables only take the value of each iteration, so an empty range
+        // leaves them untouched.
+        let mut val = if range.pipe.is_assign {
+            self.eval_pipeline_value(ctx, &range.pipe)?
+        } else {
+            self.eval_pipeline(ctx, &range.pipe)?
+        };
+        // An array nobody else holds, e.g. a freshly looked up field, gives up its elements
+        // instead of having each one cloned.
+        if let Some(&mut Value::Array(ref mut vec)) =
+            Arc::get_mut(&mut val).and_then(|v| v.downcast_mut::<Value>())
+        {
+            let items = mem::take(vec)
+                .into_iter()
+                .enumerate()
+                .map(|(k, v)| (Value::from(k), Arc::new(v) as Arc<Any>));
+            return self.range_items(ctx, range, items);
+        }
+        match indirect(&*val) {
+            Some(&Value::Object(ref map)) | Some(&Value::Map(ref map)) => {
+                let mut keys: Vec<&String> = map.keys().collect();
+                keys.sort();
+                let items = keys.into_iter()
+                    .map(|k| (Value::from(k.as_str()), Arc::new(map[k].clone()) as Arc<Any>));
+                self.range_items(ctx, range, items)
+            }
+            Some(&Value::Array(ref vec)) => {
+                let items = vec.iter()
+                    .enumerate()
+                    .map(|(k, v)| (Value::from(k), Arc::new(v.clone()) as Arc<Any>));
+                self.range_items(ctx, range, items)
+            }
+            Some(&Value::Number(ref n)) => {
+                if range.pipe.decl.len() > 1 {
+                    return Err(format!(
+                        "can't use {} to iterate over more than one variable",
+                        n
+                    ));
+                }
+                let count = n.as_i64()
+                    .filter(|_| n.as_f64().map(|f| f.fract() == 0.0).unwrap_or(true))
+                    .ok_or_else(|| format!("can't iterate over {}", n))?;
+                let items =
+                    (0..count).map(|i| (Value::from(i), Arc::new(Value::from(i)) as Arc<Any>));
+                self.range_items(ctx, range, items)
+            }
+            // Like a nil slice in Go, nothing iterates over nil.
+            Some(&Value::Nil) | Some(&Value::NoValue) | None => {
+                self.range_items(ctx, range, iter::empty())
+            }
+            Some(value) => Err(format!("invalid range: {:?}", value)),
+        }
+    }
+
+    // Walks the body of `range` once per key and value, or the else branch if there are none.
+    fn range_items<I>(
+        &mut self,
+        ctx: &Context,
+        range: &'a RangeNode,
+        items: I,
+    ) -> Result<Flow, String>
+    where
+        I: Iterator<Item = (Value, Arc<Any>)>,
+    {
+        let mut empty = true;
+        for (k, v) in items {
+            empty = false;
+            if self.one_iteration(k, v, range)? == Flow::Break {
+                break;
             }
         }
-        if let Some(ref else_list) = range.else_list {
-            self.walk_list(ctx, else_list)?;
+        if empty {
+            if let Some(ref else_list) = range.else_list {
+                return self.walk_list(ctx, else_list);
+            }
         }
-        Ok(())
+        Ok(Flow::Next)
     }
 
     fn print_value(&mut self, val: &Arc<Any>) -> Result<(), String> {
@@ -450,11 +983,38 @@ impl<'a, 'b, T: Write> State<'a, 'b, T> {
                     isize,
                     usize,
         };
-        if let Some(v) = val.downcast_ref::<Value>() {
-            write!(self.writer, "{}", v).map_err(|e| format!("{}", e))?;
+        if let Some(v) = indirect(&**val) {
+            match *v {
+                Value::Array(_) | Value::Map(_) | Value::Object(_) | Value::NoValue => {
+                    write!(self.writer, "{}", to_go_string(v))
+                }
+                Value::Function(_) => return Err(String::from("cannot print a function value")),
+                _ => write!(self.writer, "{}", v),
+            }.map_err(write_error)?;
             return Ok(());
         }
-        Err(String::from("unable to format value"))
+        if val.is::<Func>() {
+            return Err(String::from("cannot print a function value"));
+        }
+        Err(format!("unable to format value of type {}", type_name(&**val)))
+    }
+}
+
+// Returns the zero value for the kind of the given value.
+// Maps have no element type, so the zero value is the one shared by all values of
+// the map. Empty maps and maps with values of different kinds fall back to "".
+fn zero_value(map: &HashMap<String, Value>) -> Value {
+    let mut zeros = map.values().map(|val| match *val {
+        Value::String(_) => Value::from(""),
+        Value::Number(_) => Value::from(0),
+        Value::Bool(_) => Value::from(false),
+        Value::Array(_) => Value::Array(vec![]),
+        Value::Map(_) | Value::Object(_) => Value::Map(HashMap::new()),
+        _ => Value::Nil,
+    });
+    match zeros.next() {
+        Some(ref zero) if zeros.all(|z| z == *zero) => zero.clone(),
+        _ => Value::from(""),
     }
 }
 
@@ -469,10 +1029,11 @@ fn not_a_function(args: &[Nodes], val: &Option<Arc<Any>>) -> Result<(), String>
 mod tests_mocked {
     use super::*;
     use std::collections::HashMap;
+    use gtmpl_value::{FromValue, Function};
 
     #[test]
     fn simple_template() {
-        let data = Context::from(1).unwrap();
+        let data = Context::from(1);
         let mut w: Vec<u8> = vec![];
         let mut t = Template::default();
         assert!(t.parse(r#"{{ if false }} 2000 {{ end }}"#).is_ok());
@@ -480,7 +1041,7 @@ mod tests_mocked {
         assert!(out.is_ok());
         assert_eq!(String::from_utf8(w).unwrap(), "");
 
-        let data = Context::from(1).unwrap();
+        let data = Context::from(1);
         let mut w: Vec<u8> = vec![];
         let mut t = Template::default();
         assert!(t.parse(r#"{{ if true }} 2000 {{ end }}"#).is_ok());
@@ -488,7 +1049,7 @@ mod tests_mocked {
         assert!(out.is_ok());
         assert_eq!(String::from_utf8(w).unwrap(), " 2000 ");
 
-        let data = Context::from(1).unwrap();
+        let data = Context::from(1);
         let mut w: Vec<u8> = vec![];
         let mut t = Template::default();
         assert!(t.parse(r#"{{ if true -}} 2000 {{- end }}"#).is_ok());
@@ -496,7 +1057,7 @@ mod tests_mocked {
         assert!(out.is_ok());
         assert_eq!(String::from_utf8(w).unwrap(), "2000");
 
-        let data = Context::from(1).unwrap();
+        let data = Context::from(1);
         let mut w: Vec<u8> = vec![];
         let mut t = Template::default();
         assert!(
@@ -510,7 +1071,7 @@ mod tests_mocked {
 
     #[test]
     fn test_dot() {
-        let data = Context::from(1).unwrap();
+        let data = Context::from(1);
         let mut w: Vec<u8> = vec![];
         let mut t = Template::default();
         assert!(
@@ -521,7 +1082,7 @@ mod tests_mocked {
         assert!(out.is_ok());
         assert_eq!(String::from_utf8(w).unwrap(), "2000");
 
-        let data = Context::from(false).unwrap();
+        let data = Context::from(false);
         let mut w: Vec<u8> = vec![];
         let mut t = Template::default();
         assert!(
@@ -533,9 +1094,36 @@ mod tests_mocked {
         assert_eq!(String::from_utf8(w).unwrap(), "3000");
     }
 
+    #[test]
+    fn test_string_literals() {
+        let mut t = Template::default();
+        assert!(t.parse(r#"{{ "a\tb" }}|{{ "\"q\"\n" }}|{{ "\u263a" }}"#).is_ok());
+        assert_eq!(
+            t.render(&Context::empty()),
+            Ok(String::from("a\tb|\"q\"\n|\u{263a}"))
+        );
+
+        let mut t = Template::default();
+        assert!(t.parse(r#"{{ `a\tb` }}|{{ `"q"\n` }}|{{ len `\t` }}"#).is_ok());
+        assert_eq!(
+            t.render(&Context::empty()),
+            Ok(String::from(r#"a\tb|"q"\n|2"#))
+        );
+
+        let mut t = Template::default();
+        assert!(
+            t.parse(r#"{{ define "a\\b" }}raw{{ end }}{{ template `a\b` }}"#)
+                .is_ok()
+        );
+        assert_eq!(t.render(&Context::empty()), Ok(String::from("raw")));
+
+        let mut t = Template::default();
+        assert!(t.parse(r#"{{ "a\qb" }}"#).is_err());
+    }
+
     #[test]
     fn test_sub() {
-        let data = Context::from(1u8).unwrap();
+        let data = Context::from(1u8);
         let mut w: Vec<u8> = vec![];
         let mut t = Template::default();
         assert!(t.parse(r#"{{.}}"#).is_ok());
@@ -548,7 +1136,7 @@ mod tests_mocked {
             foo: u8,
         }
         let foo = Foo { foo: 1 };
-        let data = Context::from(foo).unwrap();
+        let data = Context::from(foo);
         let mut w: Vec<u8> = vec![];
         let mut t = Template::default();
         assert!(t.parse(r#"{{.foo}}"#).is_ok());
@@ -564,7 +1152,7 @@ mod tests_mocked {
             foo: u8,
         }
         let foo = Foo { foo: 1 };
-        let data = Context::from(foo).unwrap();
+        let data = Context::from(foo);
         let mut w: Vec<u8> = vec![];
         let mut t = Template::default();
         assert!(t.parse(r#"{{.foobar}}"#).is_ok());
@@ -572,320 +1160,2166 @@ mod tests_mocked {
         assert!(out.is_err());
 
         let map: HashMap<String, u64> = [("foo".to_owned(), 23u64)].iter().cloned().collect();
-        let data = Context::from(map).unwrap();
+        let data = Context::from(map);
         let mut w: Vec<u8> = vec![];
         let mut t = Template::default();
         assert!(t.parse(r#"{{.foo2}}"#).is_ok());
         let out = t.execute(&mut w, &data);
         assert!(out.is_ok());
-        assert_eq!(String::from_utf8(w).unwrap(), Value::NoValue.to_string());
+        assert_eq!(String::from_utf8(w).unwrap(), "<no value>");
     }
 
     #[test]
-    fn test_dollar_dot() {
-        #[derive(Gtmpl, Clone)]
-        struct Foo {
-            foo: u8,
-        }
-        let data = Context::from(Foo { foo: 1u8 }).unwrap();
+    fn test_nil() {
+        let map: HashMap<String, Value> = [
+            ("x".to_owned(), Value::Nil),
+            ("y".to_owned(), Value::from("foo")),
+        ].iter()
+            .cloned()
+            .collect();
+        let data = Context::from(map);
         let mut w: Vec<u8> = vec![];
         let mut t = Template::default();
-        println!("{:?}", t.parse(r#"{{$.foo}}"#));
-        assert!(t.parse(r#"{{$.foo}}"#).is_ok());
+        assert!(t.parse(r#"{{ if .x }}1{{ else }}2{{ end }}"#).is_ok());
+        let out = t.execute(&mut w, &data);
+        assert!(out.is_ok());
+        assert_eq!(String::from_utf8(w).unwrap(), "2");
+
+        let mut w: Vec<u8> = vec![];
+        let mut t = Template::default();
+        assert!(
+            t.parse(r#"{{ eq .x nil }} {{ eq .y nil }} {{ ne .y nil }} {{ eq .z nil }}"#)
+                .is_ok()
+        );
+        let out = t.execute(&mut w, &data);
+        assert!(out.is_ok());
+        assert_eq!(String::from_utf8(w).unwrap(), "true false true true");
+
+        let mut w: Vec<u8> = vec![];
+        let mut t = Template::default();
+        assert!(t.parse(r#"{{ if not nil }}1{{ end }}"#).is_ok());
         let out = t.execute(&mut w, &data);
         assert!(out.is_ok());
         assert_eq!(String::from_utf8(w).unwrap(), "1");
     }
 
     #[test]
-    fn test_dot_value() {
+    fn test_array_field() {
         #[derive(Gtmpl, Clone)]
-        struct Foo {
-            foo: u8,
+        struct Item {
+            name: String,
         }
         #[derive(Gtmpl)]
-        struct Bar {
-            bar: Foo,
+        struct Items {
+            items: Vec<Item>,
+            nested: Vec<Vec<u8>>,
         }
-        let foo = Foo { foo: 1 };
-        let data = Context::from(foo).unwrap();
+        let items = Items {
+            items: vec![
+                Item {
+                    name: "foo".to_owned(),
+                },
+                Item {
+                    name: "bar".to_owned(),
+                },
+            ],
+            nested: vec![vec![1, 2], vec![3, 4]],
+        };
+        let data = Context::from(items);
         let mut w: Vec<u8> = vec![];
         let mut t = Template::default();
         assert!(
-            t.parse(r#"{{ if .foo -}} 2000 {{- else -}} 3000 {{- end }}"#)
+            t.parse(r#"{{ .items.0.name }} {{ .items.1.name }} {{ .nested.1.0 }}"#)
                 .is_ok()
         );
         let out = t.execute(&mut w, &data);
         assert!(out.is_ok());
-        assert_eq!(String::from_utf8(w).unwrap(), "2000");
+        assert_eq!(String::from_utf8(w).unwrap(), "foo bar 3");
 
-        let foo = Foo { foo: 0 };
-        let data = Context::from(foo).unwrap();
         let mut w: Vec<u8> = vec![];
         let mut t = Template::default();
-        assert!(
-            t.parse(r#"{{ if .foo -}} 2000 {{- else -}} 3000 {{- end }}"#)
-                .is_ok()
-        );
+        assert!(t.parse(r#"{{ $x := .items }}{{ $x.1.name }}"#).is_ok());
         let out = t.execute(&mut w, &data);
         assert!(out.is_ok());
-        assert_eq!(String::from_utf8(w).unwrap(), "3000");
+        assert_eq!(String::from_utf8(w).unwrap(), "bar");
 
-        let bar = Bar {
-            bar: Foo { foo: 1 },
-        };
-        let data = Context::from(bar).unwrap();
         let mut w: Vec<u8> = vec![];
         let mut t = Template::default();
-        assert!(
-            t.parse(r#"{{ if .bar.foo -}} 2000 {{- else -}} 3000 {{- end }}"#)
-                .is_ok()
-        );
+        assert!(t.parse(r#"{{ .items.2.name }}"#).is_ok());
+        let out = t.execute(&mut w, &data);
+        assert!(out.is_err());
+    }
+
+    #[test]
+    fn test_comment() {
+        let data = Context::from(1);
+        let mut w: Vec<u8> = vec![];
+        let mut t = Template::default();
+        assert!(t.parse(r#"{{/* only a comment */}}"#).is_ok());
         let out = t.execute(&mut w, &data);
         assert!(out.is_ok());
-        assert_eq!(String::from_utf8(w).unwrap(), "2000");
+        assert_eq!(String::from_utf8(w).unwrap(), "");
 
-        let bar = Bar {
-            bar: Foo { foo: 0 },
-        };
-        let data = Context::from(bar).unwrap();
         let mut w: Vec<u8> = vec![];
         let mut t = Template::default();
         assert!(
-            t.parse(r#"{{ if .bar.foo -}} 2000 {{- else -}} 3000 {{- end }}"#)
+            t.parse("{{ if . -}} 2000 {{- /* comment\n{{ .foo }} */ -}} \n{{ end }}")
                 .is_ok()
         );
         let out = t.execute(&mut w, &data);
         assert!(out.is_ok());
-        assert_eq!(String::from_utf8(w).unwrap(), "3000");
+        assert_eq!(String::from_utf8(w).unwrap(), "2000");
     }
 
     #[test]
-    fn test_with() {
-        #[derive(Gtmpl)]
-        struct Foo {
-            foo: u16,
-        }
-        let foo = Foo { foo: 1000 };
-        let data = Context::from(foo).unwrap();
+    fn test_error_location() {
+        let data = Context::from(1);
         let mut w: Vec<u8> = vec![];
-        let mut t = Template::default();
+        let mut t = Template::with_name("foo");
+        assert!(t.parse("first\nsecond\n{{ .bar }}").is_ok());
+        let out = t.execute(&mut w, &data);
+        assert!(out.is_err());
+        assert!(out.unwrap_err().starts_with("template: foo:3: "));
+
+        let mut w: Vec<u8> = vec![];
+        let mut t = Template::with_name("foo");
         assert!(
-            t.parse(r#"{{ with .foo -}} {{.}} {{- else -}} 3000 {{- end }}"#)
+            t.parse("{{ define \"bar\" }}\n\n{{ len . }}{{ end }}\n{{ template \"bar\" }}")
                 .is_ok()
         );
         let out = t.execute(&mut w, &data);
-        assert!(out.is_ok());
-        assert_eq!(String::from_utf8(w).unwrap(), "1000");
+        assert!(out.is_err());
+        assert!(out.unwrap_err().starts_with("template: foo:3: "));
     }
 
-    fn to_sorted_string(buf: Vec<u8>) -> String {
-        let mut chars: Vec<char> = String::from_utf8(buf).unwrap().chars().collect();
-        chars.sort();
-        chars.iter().cloned().collect::<String>()
+    #[test]
+    fn test_unicode_field() {
+        let mut inner = HashMap::new();
+        inner.insert("größe".to_owned(), 42);
+        let mut map = HashMap::new();
+        map.insert("café".to_owned(), inner);
+        let data = Context::from(map);
+        let mut t = Template::default();
+        assert!(
+            t.parse(r#"{{ .café.größe }} {{ $ä := .café }}{{ $ä.größe }} {{ .café.ß }}"#)
+                .is_ok()
+        );
+        assert_eq!(t.render(&data), Ok(String::from("42 42 <no value>")));
     }
 
     #[test]
-    fn test_range() {
+    fn test_variable_field_chain() {
+        #[derive(Gtmpl, Clone)]
+        struct Bar {
+            x: u8,
+        }
+        #[derive(Gtmpl, Clone)]
+        struct Foo {
+            bar: Bar,
+        }
+        let data = Context::from(Foo { bar: Bar { x: 1 } });
+        let mut w: Vec<u8> = vec![];
+        let mut t = Template::default();
+        assert!(t.parse(r#"{{ $foo := . }}{{ $foo.bar.x }}"#).is_ok());
+        let out = t.execute(&mut w, &data);
+        assert!(out.is_ok());
+        assert_eq!(String::from_utf8(w).unwrap(), "1");
+
+        let mut w: Vec<u8> = vec![];
+        let mut t = Template::default();
+        assert!(t.parse(r#"{{ $foo := . }}{{ $foo.bar.baz }}"#).is_ok());
+        let out = t.execute(&mut w, &data);
+        assert!(out.unwrap_err().contains("no field baz"));
+
+        let mut w: Vec<u8> = vec![];
+        let mut t = Template::default();
+        assert!(t.parse(r#"{{ $foo := . }}{{ $foo.bar.x.y }}"#).is_ok());
+        let out = t.execute(&mut w, &data);
+        assert!(out.unwrap_err().contains("can't evaluate field y"));
+
+        let mut inner = HashMap::new();
+        inner.insert("b".to_owned(), 2);
         let mut map = HashMap::new();
-        map.insert("a".to_owned(), 1);
-        map.insert("b".to_owned(), 2);
-        let data = Context::from(map).unwrap();
+        map.insert("a".to_owned(), inner);
+        let data = Context::from(map);
         let mut w: Vec<u8> = vec![];
         let mut t = Template::default();
-        assert!(t.parse(r#"{{ range . -}} {{.}} {{- end }}"#).is_ok());
+        assert!(t.parse(r#"{{ $foo := . }}{{ $foo.a.b }} {{ $foo.a.c.d }}"#).is_ok());
         let out = t.execute(&mut w, &data);
         assert!(out.is_ok());
-        assert_eq!(to_sorted_string(w), "12");
+        assert_eq!(String::from_utf8(w).unwrap(), "2 <no value>");
 
-        let vec = vec!["foo", "bar", "2000"];
-        let data = Context::from(vec).unwrap();
         let mut w: Vec<u8> = vec![];
         let mut t = Template::default();
-        assert!(t.parse(r#"{{ range . -}} {{.}} {{- end }}"#).is_ok());
+        assert!(t.option("missingkey=error").is_ok());
+        assert!(t.parse(r#"{{ $foo := . }}{{ $foo.a.c.d }}"#).is_ok());
+        let out = t.execute(&mut w, &data);
+        assert!(out.unwrap_err().contains("no entry for key c"));
+    }
+
+    #[test]
+    fn test_missingkey() {
+        let map: HashMap<String, u64> = [("foo".to_owned(), 23u64)].iter().cloned().collect();
+        let data = Context::from(map);
+        let mut w: Vec<u8> = vec![];
+        let mut t = Template::default();
+        assert!(t.option("missingkey=default").is_ok());
+        assert!(t.parse(r#"{{ .foo }} {{ .foo2 }}"#).is_ok());
         let out = t.execute(&mut w, &data);
-        println!("{:?}", out);
         assert!(out.is_ok());
-        assert_eq!(String::from_utf8(w).unwrap(), "foobar2000");
+        assert_eq!(String::from_utf8(w).unwrap(), "23 <no value>");
+
+        let mut t = Template::default();
+        assert!(t.parse(r#"{{ .foo2 }}|{{ print .foo2 }}|{{ list .foo2 1 }}"#).is_ok());
+        assert_eq!(
+            t.render(&data),
+            Ok(String::from("<no value>|<no value>|[<no value> 1]"))
+        );
+
+        let mut w: Vec<u8> = vec![];
+        let mut t = Template::default();
+        assert!(t.option("missingkey=zero").is_ok());
+        assert!(t.parse(r#"{{ .foo }} {{ .foo2 }}"#).is_ok());
+        let out = t.execute(&mut w, &data);
+        assert!(out.is_ok());
+        assert_eq!(String::from_utf8(w).unwrap(), "23 0");
+
+        let map: HashMap<String, String> = [("foo".to_owned(), "bar".to_owned())]
+            .iter()
+            .cloned()
+            .collect();
+        let str_data = Context::from(map);
+        let mut w: Vec<u8> = vec![];
+        let out = t.execute(&mut w, &str_data);
+        assert!(out.is_ok());
+        assert_eq!(String::from_utf8(w).unwrap(), "bar ");
+
+        let mut mixed: HashMap<String, Value> = HashMap::new();
+        mixed.insert("foo".to_owned(), Value::from(23));
+        mixed.insert("bar".to_owned(), Value::from("bar"));
+        mixed.insert("baz".to_owned(), Value::from(vec![1]));
+        let mixed_data = Context::from(Value::Map(mixed));
+        for _ in 0..10 {
+            assert_eq!(t.render(&mixed_data), Ok(String::from("23 ")));
+        }
+
+        let empty_data = Context::from(Value::Map(HashMap::new()));
+        let mut t = Template::default();
+        assert!(t.option("missingkey=zero").is_ok());
+        assert!(t.parse(r#"{{ .foo }}|{{ if .foo }}yes{{ else }}no{{ end }}"#).is_ok());
+        assert_eq!(t.render(&empty_data), Ok(String::from("|no")));
+
+        let mut w: Vec<u8> = vec![];
+        let mut t = Template::default();
+        assert!(t.option("missingkey=error").is_ok());
+        assert!(t.parse(r#"{{ .foo }} {{ .foo2 }}"#).is_ok());
+        let out = t.execute(&mut w, &data);
+        assert!(out.is_err());
+
+        let mut w: Vec<u8> = vec![];
+        let mut t = Template::default();
+        assert!(t.option("missingkey=error").is_ok());
+        assert!(t.parse(r#"{{ .foo }}"#).is_ok());
+        let out = t.execute(&mut w, &data);
+        assert!(out.is_ok());
+        assert_eq!(String::from_utf8(w).unwrap(), "23");
     }
 
     #[test]
-    fn test_proper_range() {
-        let mut map = HashMap::new();
-        map.insert("a".to_owned(), 1);
-        map.insert("b".to_owned(), 2);
-        let data = Context::from(map).unwrap();
+    fn test_dollar_dot() {
+        #[derive(Gtmpl, Clone)]
+        struct Foo {
+            foo: u8,
+        }
+        let data = Context::from(Foo { foo: 1u8 });
+        let mut w: Vec<u8> = vec![];
+        let mut t = Template::default();
+        println!("{:?}", t.parse(r#"{{$.foo}}"#));
+        assert!(t.parse(r#"{{$.foo}}"#).is_ok());
+        let out = t.execute(&mut w, &data);
+        assert!(out.is_ok());
+        assert_eq!(String::from_utf8(w).unwrap(), "1");
+    }
+
+    #[test]
+    fn test_dot_value() {
+        #[derive(Gtmpl, Clone)]
+        struct Foo {
+            foo: u8,
+        }
+        #[derive(Gtmpl)]
+        struct Bar {
+            bar: Foo,
+        }
+        let foo = Foo { foo: 1 };
+        let data = Context::from(foo);
         let mut w: Vec<u8> = vec![];
         let mut t = Template::default();
         assert!(
-            t.parse(r#"{{ range $k, $v := . -}} {{ $v }} {{- end }}"#)
+            t.parse(r#"{{ if .foo -}} 2000 {{- else -}} 3000 {{- end }}"#)
                 .is_ok()
         );
         let out = t.execute(&mut w, &data);
         assert!(out.is_ok());
-        assert_eq!(to_sorted_string(w), "12");
+        assert_eq!(String::from_utf8(w).unwrap(), "2000");
 
-        let mut map = HashMap::new();
-        map.insert("a".to_owned(), "b");
-        map.insert("c".to_owned(), "d");
-        let data = Context::from(map).unwrap();
+        let foo = Foo { foo: 0 };
+        let data = Context::from(foo);
         let mut w: Vec<u8> = vec![];
         let mut t = Template::default();
         assert!(
-            t.parse(r#"{{ range $k, $v := . -}} {{ $k }}{{ $v }} {{- end }}"#)
+            t.parse(r#"{{ if .foo -}} 2000 {{- else -}} 3000 {{- end }}"#)
                 .is_ok()
         );
         let out = t.execute(&mut w, &data);
         assert!(out.is_ok());
-        assert_eq!(to_sorted_string(w), "abcd");
+        assert_eq!(String::from_utf8(w).unwrap(), "3000");
 
-        let mut map = HashMap::new();
-        map.insert("a".to_owned(), 1);
-        map.insert("b".to_owned(), 2);
-        let data = Context::from(map).unwrap();
+        let bar = Bar {
+            bar: Foo { foo: 1 },
+        };
+        let data = Context::from(bar);
         let mut w: Vec<u8> = vec![];
         let mut t = Template::default();
         assert!(
-            t.parse(r#"{{ range $k, $v := . -}} {{ $k }}{{ $v }} {{- end }}"#)
+            t.parse(r#"{{ if .bar.foo -}} 2000 {{- else -}} 3000 {{- end }}"#)
                 .is_ok()
         );
         let out = t.execute(&mut w, &data);
         assert!(out.is_ok());
-        assert_eq!(to_sorted_string(w), "12ab");
+        assert_eq!(String::from_utf8(w).unwrap(), "2000");
 
-        let mut map = HashMap::new();
-        map.insert("a".to_owned(), 1);
-        map.insert("b".to_owned(), 2);
-        #[derive(Gtmpl)]
-        struct Foo {
-            foo: HashMap<String, i32>,
-        }
-        let foo = Foo { foo: map };
-        let data = Context::from(foo).unwrap();
+        let bar = Bar {
+            bar: Foo { foo: 0 },
+        };
+        let data = Context::from(bar);
         let mut w: Vec<u8> = vec![];
         let mut t = Template::default();
         assert!(
-            t.parse(r#"{{ range $k, $v := .foo -}} {{ $v }} {{- end }}"#)
+            t.parse(r#"{{ if .bar.foo -}} 2000 {{- else -}} 3000 {{- end }}"#)
                 .is_ok()
         );
         let out = t.execute(&mut w, &data);
         assert!(out.is_ok());
-        assert_eq!(to_sorted_string(w), "12");
+        assert_eq!(String::from_utf8(w).unwrap(), "3000");
+    }
 
-        let mut map = HashMap::new();
-        #[derive(Gtmpl, Clone)]
-        struct Bar {
-            bar: i32,
+    #[test]
+    fn test_with() {
+        #[derive(Gtmpl)]
+        struct Foo {
+            foo: u16,
         }
-        map.insert("a".to_owned(), Bar { bar: 1 });
-        map.insert("b".to_owned(), Bar { bar: 2 });
-        let data = Context::from(map).unwrap();
+        let foo = Foo { foo: 1000 };
+        let data = Context::from(foo);
         let mut w: Vec<u8> = vec![];
         let mut t = Template::default();
         assert!(
-            t.parse(r#"{{ range $k, $v := . -}} {{ $v.bar }} {{- end }}"#)
+            t.parse(r#"{{ with .foo -}} {{.}} {{- else -}} 3000 {{- end }}"#)
                 .is_ok()
         );
         let out = t.execute(&mut w, &data);
         assert!(out.is_ok());
-        assert_eq!(to_sorted_string(w), "12");
+        assert_eq!(String::from_utf8(w).unwrap(), "1000");
+
+        let mut map: HashMap<String, Value> = HashMap::new();
+        map.insert("zero".to_owned(), Value::from(0));
+        map.insert("float".to_owned(), Value::from(0.0));
+        map.insert("empty".to_owned(), Value::from(""));
+        map.insert("slice".to_owned(), Value::Array(vec![]));
+        map.insert("map".to_owned(), Value::Map(HashMap::new()));
+        map.insert("nil".to_owned(), Value::Nil);
+        map.insert("full".to_owned(), Value::from(vec![1, 2]));
+        let data = Context::from(map);
+        let keys = ["zero", "float", "empty", "slice", "map", "nil", "missing", "full"];
+        let text = keys.iter()
+            .map(|k| format!("{{{{ with .{} }}}}{{{{ . }}}}{{{{ else }}}}-{{{{ end }}}}", k))
+            .collect::<String>();
+        let mut t = Template::default();
+        assert!(t.parse(&text).is_ok());
+        assert_eq!(t.render(&data), Ok(String::from("-------[1 2]")));
+
+        let mut t = Template::default();
+        assert!(t.parse(r#"{{ $zero := .zero }}{{ $zero }}{{ with .zero }}x{{ end }}"#).is_ok());
+        assert_eq!(t.render(&data), Ok(String::from("0")));
     }
 
     #[test]
-    fn test_len() {
+    fn test_else_if() {
+        let tmpl = r#"{{ if eq . 1 }}one{{ else if eq . 2 }}two{{ else }}many{{ end }}"#;
+        let mut t = Template::default();
+        assert!(t.parse(tmpl).is_ok());
+        for (i, expected) in &[(1, "one"), (2, "two"), (3, "many")] {
+            let data = Context::from(*i);
+            let mut w: Vec<u8> = vec![];
+            let out = t.execute(&mut w, &data);
+            assert!(out.is_ok());
+            assert_eq!(&String::from_utf8(w).unwrap(), expected);
+        }
+
+        let tmpl = r#"{{ if eq . 1 }}one{{ else if eq . 2 }}two{{ else if eq . 3 }}three{{ end }}"#;
+        let mut t = Template::default();
+        assert!(t.parse(tmpl).is_ok());
+        for (i, expected) in &[(3, "three"), (4, "")] {
+            let data = Context::from(*i);
+            let mut w: Vec<u8> = vec![];
+            let out = t.execute(&mut w, &data);
+            assert!(out.is_ok());
+            assert_eq!(&String::from_utf8(w).unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn test_else_with() {
+        #[derive(Gtmpl)]
+        struct Foo {
+            a: String,
+            b: String,
+        }
+        let foo = Foo {
+            a: String::new(),
+            b: String::from("bar"),
+        };
+        let data = Context::from(foo);
         let mut w: Vec<u8> = vec![];
         let mut t = Template::default();
-        assert!(t.parse(r#"my len is {{ len . }}"#).is_ok());
-        let data = Context::from(vec![1, 2, 3]).unwrap();
+        assert!(
+            t.parse(r#"{{ with .a }}a {{ . }}{{ else with .b }}b {{ . }}{{ else }}none{{ end }}"#)
+                .is_ok()
+        );
         let out = t.execute(&mut w, &data);
         assert!(out.is_ok());
-        assert_eq!(String::from_utf8(w).unwrap(), "my len is 3");
+        assert_eq!(String::from_utf8(w).unwrap(), "b bar");
 
+        let foo = Foo {
+            a: String::new(),
+            b: String::new(),
+        };
+        let data = Context::from(foo);
         let mut w: Vec<u8> = vec![];
-        let mut t = Template::default();
-        assert!(t.parse(r#"{{ len . }}"#).is_ok());
-        let data = Context::from("hello".to_owned()).unwrap();
         let out = t.execute(&mut w, &data);
         assert!(out.is_ok());
-        assert_eq!(String::from_utf8(w).unwrap(), "5");
+        assert_eq!(String::from_utf8(w).unwrap(), "none");
+
+        let mut t = Template::default();
+        assert!(t.parse(r#"{{ if .a }}{{ else with .b }}{{ end }}"#).is_err());
     }
 
     #[test]
-    fn test_pipeline_function() {
+    fn test_range() {
+        let mut map = HashMap::new();
+        map.insert("a".to_owned(), 1);
+        map.insert("b".to_owned(), 2);
+        let data = Context::from(map);
         let mut w: Vec<u8> = vec![];
         let mut t = Template::default();
-        assert!(t.parse(r#"{{ if ( 1 | eq . ) -}} 2000 {{- end }}"#).is_ok());
-        let data = Context::from(1).unwrap();
+        assert!(t.parse(r#"{{ range . -}} {{.}} {{- end }}"#).is_ok());
         let out = t.execute(&mut w, &data);
         assert!(out.is_ok());
-        assert_eq!(String::from_utf8(w).unwrap(), "2000");
-    }
+        assert_eq!(String::from_utf8(w).unwrap(), "12");
+
+        let map: HashMap<String, u32> = ["d", "b", "e", "a", "c"]
+            .iter()
+            .enumerate()
+            .map(|(i, k)| (k.to_string(), i as u32))
+            .collect();
+        let data = Context::from(map);
+        let mut w: Vec<u8> = vec![];
+        let mut t = Template::default();
+        assert!(
+            t.parse(r#"{{ range $k, $v := . -}} {{ $k }}{{ $v }} {{- end }}"#)
+                .is_ok()
+        );
+        let out = t.execute(&mut w, &data);
+        assert!(out.is_ok());
+        assert_eq!(String::from_utf8(w).unwrap(), "a3b1c4d0e2");
 
-    #[test]
-    fn test_function() {
+        let vec = vec!["foo", "bar", "2000"];
+        let data = Context::from(vec);
         let mut w: Vec<u8> = vec![];
         let mut t = Template::default();
-        assert!(t.parse(r#"{{ if eq . . -}} 2000 {{- end }}"#).is_ok());
-        let data = Context::from(1).unwrap();
+        assert!(t.parse(r#"{{ range . -}} {{.}} {{- end }}"#).is_ok());
         let out = t.execute(&mut w, &data);
+        println!("{:?}", out);
         assert!(out.is_ok());
-        assert_eq!(String::from_utf8(w).unwrap(), "2000");
+        assert_eq!(String::from_utf8(w).unwrap(), "foobar2000");
     }
 
     #[test]
-    fn test_eq() {
-        let mut w: Vec<u8> = vec![];
+    fn test_range_large() {
+        let vec: Vec<u64> = (0..10_000).collect();
+        let expected: String = vec.iter().map(|i| format!("{},", i)).collect();
+        let mut map = HashMap::new();
+        map.insert("items".to_owned(), vec.clone());
+        let data = Context::from(map);
+
         let mut t = Template::default();
-        assert!(t.parse(r#"{{ if eq "a" "a" -}} 2000 {{- end }}"#).is_ok());
-        let data = Context::from(1).unwrap();
+        assert!(t.parse(r#"{{ range .items }}{{ . }},{{ end }}"#).is_ok());
+        assert_eq!(t.render(&data), Ok(expected.clone()));
+
+        let mut t = Template::default();
+        assert!(
+            t.parse(r#"{{ $x := .items }}{{ range $x }}{{ . }},{{ end }}{{ len $x }}"#)
+                .is_ok()
+        );
+        assert_eq!(t.render(&data), Ok(format!("{}10000", expected)));
+
+        let mut t = Template::default();
+        assert!(
+            t.parse(r#"{{ range $i, $e := .items }}{{ if eq $i 3 }}{{ break }}{{ end }}{{ $e }}{{ end }}"#)
+                .is_ok()
+        );
+        assert_eq!(t.render(&data), Ok(String::from("012")));
+
+        let mut t = Template::default();
+        assert!(t.parse(r#"{{ range . }}{{ . }},{{ end }}"#).is_ok());
+        assert_eq!(t.render(&Context::from(vec)), Ok(expected));
+    }
+
+    #[test]
+    fn test_range_else() {
+        let mut t = Template::default();
+        assert!(t.parse(r#"{{ range . }}{{ . }}{{ else }}empty{{ end }}"#).is_ok());
+
+        let data = Context::from(vec![1, 2]);
+        let mut w: Vec<u8> = vec![];
         let out = t.execute(&mut w, &data);
         assert!(out.is_ok());
-        assert_eq!(String::from_utf8(w).unwrap(), "2000");
+        assert_eq!(String::from_utf8(w).unwrap(), "12");
 
+        let data = Context::from(Value::Array(vec![]));
         let mut w: Vec<u8> = vec![];
-        let mut t = Template::default();
-        assert!(t.parse(r#"{{ if eq "a" "b" -}} 2000 {{- end }}"#).is_ok());
-        let data = Context::from(1).unwrap();
         let out = t.execute(&mut w, &data);
         assert!(out.is_ok());
-        assert_eq!(String::from_utf8(w).unwrap(), "");
+        assert_eq!(String::from_utf8(w).unwrap(), "empty");
+
+        let data = Context::from(Value::Map(HashMap::new()));
+        let mut w: Vec<u8> = vec![];
+        let out = t.execute(&mut w, &data);
+        assert!(out.is_ok());
+        assert_eq!(String::from_utf8(w).unwrap(), "empty");
+    }
 
+    #[test]
+    fn test_range_nil() {
+        let map: HashMap<String, u64> = [("foo".to_owned(), 23u64)].iter().cloned().collect();
+        let data = Context::from(map);
         let mut w: Vec<u8> = vec![];
         let mut t = Template::default();
-        assert!(t.parse(r#"{{ if eq true true -}} 2000 {{- end }}"#).is_ok());
-        let data = Context::from(1).unwrap();
+        assert!(t.parse(r#"{{ range .missing }}x{{ else }}empty{{ end }}"#).is_ok());
         let out = t.execute(&mut w, &data);
         assert!(out.is_ok());
-        assert_eq!(String::from_utf8(w).unwrap(), "2000");
+        assert_eq!(String::from_utf8(w).unwrap(), "empty");
 
+        let data = Context::from(Value::Nil);
         let mut w: Vec<u8> = vec![];
         let mut t = Template::default();
-        assert!(
-            t.parse(r#"{{ if eq true false -}} 2000 {{- end }}"#)
-                .is_ok()
-        );
-        let data = Context::from(1).unwrap();
+        assert!(t.parse(r#"{{ range . }}x{{ end }}"#).is_ok());
         let out = t.execute(&mut w, &data);
         assert!(out.is_ok());
         assert_eq!(String::from_utf8(w).unwrap(), "");
+    }
 
+    #[test]
+    fn test_range_int() {
+        let data = Context::from(3);
         let mut w: Vec<u8> = vec![];
         let mut t = Template::default();
-        assert!(
-            t.parse(r#"{{ if eq 23.42 23.42 -}} 2000 {{- end }}"#)
-                .is_ok()
-        );
-        let data = Context::from(1).unwrap();
+        assert!(t.parse(r#"{{ range $i := 3 }}{{ $i }}{{ end }}"#).is_ok());
         let out = t.execute(&mut w, &data);
         assert!(out.is_ok());
-        assert_eq!(String::from_utf8(w).unwrap(), "2000");
+        assert_eq!(String::from_utf8(w).unwrap(), "012");
 
         let mut w: Vec<u8> = vec![];
         let mut t = Template::default();
-        assert!(t.parse(r#"{{ if eq 1 . -}} 2000 {{- end }}"#).is_ok());
-        let data = Context::from(1).unwrap();
+        assert!(t.parse(r#"{{ range . }}{{ . }}{{ end }}"#).is_ok());
+        let out = t.execute(&mut w, &data);
+        assert!(out.is_ok());
+        assert_eq!(String::from_utf8(w).unwrap(), "012");
+
+        let mut w: Vec<u8> = vec![];
+        let mut t = Template::default();
+        assert!(t.parse(r#"{{ range 0 }}{{ . }}{{ else }}none{{ end }}"#).is_ok());
+        let out = t.execute(&mut w, &data);
+        assert!(out.is_ok());
+        assert_eq!(String::from_utf8(w).unwrap(), "none");
+
+        let mut w: Vec<u8> = vec![];
+        let mut t = Template::default();
+        assert!(t.parse(r#"{{ range -2 }}{{ . }}{{ else }}none{{ end }}"#).is_ok());
+        let out = t.execute(&mut w, &data);
+        assert!(out.is_ok());
+        assert_eq!(String::from_utf8(w).unwrap(), "none");
+
+        let mut w: Vec<u8> = vec![];
+        let mut t = Template::default();
+        assert!(t.parse(r#"{{ range $i, $j := 3 }}{{ $i }}{{ end }}"#).is_ok());
+        let out = t.execute(&mut w, &data);
+        assert!(out.is_err());
+
+        let mut w: Vec<u8> = vec![];
+        let mut t = Template::default();
+        assert!(t.parse(r#"{{ range 2.5 }}{{ . }}{{ end }}"#).is_ok());
+        let out = t.execute(&mut w, &data);
+        assert!(out.is_err());
+    }
+
+    #[test]
+    fn test_range_list() {
+        let data = Context::from(2);
+        let mut t = Template::default();
+        assert!(
+            t.parse(r#"{{ range $i, $e := (list 1 . "c") }}{{ $i }}:{{ $e }} {{ end }}"#)
+                .is_ok()
+        );
+        assert_eq!(t.render(&data), Ok(String::from("0:1 1:2 2:c ")));
+
+        let mut t = Template::default();
+        assert!(t.parse(r#"{{ range list }}{{ . }}{{ else }}none{{ end }}"#).is_ok());
+        assert_eq!(t.render(&data), Ok(String::from("none")));
+
+        let mut t = Template::default();
+        assert!(t.parse(r#"{{ $l := list 1 2 3 }}{{ len $l }} {{ index $l 1 }}"#).is_ok());
+        assert_eq!(t.render(&data), Ok(String::from("3 2")));
+    }
+
+    #[test]
+    fn test_range_outer_vars() {
+        let data = Context::from(vec!["a", "b"]);
+        let mut t = Template::default();
+        assert!(
+            t.parse(
+                r#"{{ $k := "k" }}{{ $v := "v" }}{{ range $k, $v := . }}{{ $k }}{{ $v }} {{ end }}{{ $k }}{{ $v }}"#
+            ).is_ok()
+        );
+        assert_eq!(t.render(&data), Ok(String::from("0a 1b kv")));
+
+        let mut t = Template::default();
+        assert!(
+            t.parse(r#"{{ $v := "v" }}{{ range $v := . }}{{ $x := 1 }}{{ $v }}{{ end }}{{ $v }}"#)
+                .is_ok()
+        );
+        assert_eq!(t.render(&data), Ok(String::from("abv")));
+
+        let mut t = Template::default();
+        assert!(
+            t.parse(
+                r#"{{ $i := 0 }}{{ $v := "v" }}{{ range $i, $v = . }}{{ $x := $v }}{{ end }}{{ $i }}{{ $v }}"#
+            ).is_ok()
+        );
+        assert_eq!(t.render(&data), Ok(String::from("1b")));
+
+        let mut t = Template::default();
+        assert!(
+            t.parse(
+                r#"{{ $v := "v" }}{{ range $i, $e := . }}{{ range $v := list $e $i }}{{ end }}{{ $v }}{{ end }}{{ $v }}"#
+            ).is_ok()
+        );
+        assert_eq!(t.render(&data), Ok(String::from("vvv")));
+    }
+
+    #[test]
+    fn test_range_break() {
+        let data = Context::from(5);
+        let mut w: Vec<u8> = vec![];
+        let mut t = Template::default();
+        assert!(
+            t.parse(r#"{{ range . }}{{ if eq . 3 }}{{ break }}{{ end }}{{ . }}{{ end }}"#)
+                .is_ok()
+        );
+        let out = t.execute(&mut w, &data);
+        assert!(out.is_ok());
+        assert_eq!(String::from_utf8(w).unwrap(), "012");
+
+        let mut w: Vec<u8> = vec![];
+        let mut t = Template::default();
+        assert!(
+            t.parse(r#"{{ range 3 }}{{ range . }}{{ break }}{{ end }}{{ . }}{{ end }}"#)
+                .is_ok()
+        );
+        let out = t.execute(&mut w, &data);
+        assert!(out.is_ok());
+        assert_eq!(String::from_utf8(w).unwrap(), "012");
+
+        let mut t = Template::default();
+        assert!(t.parse(r#"{{ break }}"#).is_err());
+        let mut t = Template::default();
+        assert!(t.parse(r#"{{ range . }}{{ else }}{{ break }}{{ end }}"#).is_err());
+
+        // A block is a template of its own, so it can't break out of a surrounding range.
+        let mut t = Template::default();
+        let err = t.parse(r#"{{ range . }}{{ block "x" . }}{{ break }}{{ end }}{{ end }}"#)
+            .unwrap_err();
+        assert!(err.contains("{{break}} outside {{range}}"), err);
+        let mut t = Template::default();
+        let err = t.parse(r#"{{ range . }}{{ block "x" . }}{{ continue }}{{ end }}{{ end }}"#)
+            .unwrap_err();
+        assert!(err.contains("{{continue}} outside {{range}}"), err);
+
+        // A range inside the block still can, and the outer range keeps working after it.
+        let mut w: Vec<u8> = vec![];
+        let mut t = Template::default();
+        assert!(
+            t.parse(
+                r#"{{ range . }}{{ block "x" . }}{{ range . }}{{ break }}{{ end }}{{ end }}{{ if eq . 2 }}{{ break }}{{ end }}{{ . }}{{ end }}"#
+            ).is_ok()
+        );
+        let out = t.execute(&mut w, &data);
+        assert!(out.is_ok());
+        assert_eq!(String::from_utf8(w).unwrap(), "01");
+    }
+
+    #[test]
+    fn test_range_continue() {
+        let data = Context::from(5);
+        let mut w: Vec<u8> = vec![];
+        let mut t = Template::default();
+        assert!(
+            t.parse(
+                r#"{{ range . }}{{ if or (eq . 1) (eq . 3) }}{{ continue }}{{ end }}{{ . }}{{ end }}"#
+            ).is_ok()
+        );
+        let out = t.execute(&mut w, &data);
+        assert!(out.is_ok());
+        assert_eq!(String::from_utf8(w).unwrap(), "024");
+
+        let mut w: Vec<u8> = vec![];
+        let mut t = Template::default();
+        assert!(
+            t.parse(r#"{{ range 2 }}{{ range 2 }}{{ continue }}x{{ end }}{{ . }}{{ end }}"#)
+                .is_ok()
+        );
+        let out = t.execute(&mut w, &data);
+        assert!(out.is_ok());
+        assert_eq!(String::from_utf8(w).unwrap(), "01");
+
+        let mut t = Template::default();
+        assert!(t.parse(r#"{{ if . }}{{ continue }}{{ end }}"#).is_err());
+    }
+
+    #[test]
+    fn test_range_array_index() {
+        let data = Context::from(vec!["a", "b", "c"]);
+        let mut w: Vec<u8> = vec![];
+        let mut t = Template::default();
+        assert!(
+            t.parse(
+                r#"{{ range $i, $v := . }}{{ if eq $i 1 }}{{ $v }}{{ end }}{{ index $ $i }}{{ end }}"#
+            ).is_ok()
+        );
+        let out = t.execute(&mut w, &data);
+        assert!(out.is_ok());
+        assert_eq!(String::from_utf8(w).unwrap(), "abbc");
+
+        let mut w: Vec<u8> = vec![];
+        let mut t = Template::default();
+        assert!(t.parse(r#"{{ range $i, $v := . }}{{ lt $i 2 }}{{ end }}"#).is_ok());
+        let out = t.execute(&mut w, &data);
+        assert!(out.is_ok());
+        assert_eq!(String::from_utf8(w).unwrap(), "truetruefalse");
+    }
+
+    #[test]
+    fn test_range_nested() {
+        let data = Context::from(vec![vec![1, 2], vec![], vec![3]]);
+        let mut w: Vec<u8> = vec![];
+        let mut t = Template::default();
+        assert!(
+            t.parse(
+                r#"{{ range $i, $e := . }}{{ $i }}:{{ range $j, $f := $e }}{{ $j }}={{ $f }},{{ else }}-{{ end }};{{ end }}"#
+            ).is_ok()
+        );
+        let out = t.execute(&mut w, &data);
+        assert!(out.is_ok());
+        assert_eq!(String::from_utf8(w).unwrap(), "0:0=1,1=2,;1:-;2:0=3,;");
+
+        let mut w: Vec<u8> = vec![];
+        let mut t = Template::default();
+        assert!(
+            t.parse(r#"{{ range . }}[{{ range . }}{{ . }}{{ end }}]{{ len . }}{{ end }}"#)
+                .is_ok()
+        );
+        let out = t.execute(&mut w, &data);
+        assert!(out.is_ok());
+        assert_eq!(String::from_utf8(w).unwrap(), "[12]2[]0[3]1");
+    }
+
+    #[test]
+    fn test_proper_range() {
+        let mut map = HashMap::new();
+        map.insert("a".to_owned(), 1);
+        map.insert("b".to_owned(), 2);
+        let data = Context::from(map);
+        let mut w: Vec<u8> = vec![];
+        let mut t = Template::default();
+        assert!(
+            t.parse(r#"{{ range $k, $v := . -}} {{ $v }} {{- end }}"#)
+                .is_ok()
+        );
+        let out = t.execute(&mut w, &data);
+        assert!(out.is_ok());
+        assert_eq!(String::from_utf8(w).unwrap(), "12");
+
+        let mut map = HashMap::new();
+        map.insert("a".to_owned(), "b");
+        map.insert("c".to_owned(), "d");
+        let data = Context::from(map);
+        let mut w: Vec<u8> = vec![];
+        let mut t = Template::default();
+        assert!(
+            t.parse(r#"{{ range $k, $v := . -}} {{ $k }}{{ $v }} {{- end }}"#)
+                .is_ok()
+        );
+        let out = t.execute(&mut w, &data);
+        assert!(out.is_ok());
+        assert_eq!(String::from_utf8(w).unwrap(), "abcd");
+
+        let mut map = HashMap::new();
+        map.insert("a".to_owned(), 1);
+        map.insert("b".to_owned(), 2);
+        let data = Context::from(map);
+        let mut w: Vec<u8> = vec![];
+        let mut t = Template::default();
+        assert!(
+            t.parse(r#"{{ range $k, $v := . -}} {{ $k }}{{ $v }} {{- end }}"#)
+                .is_ok()
+        );
+        let out = t.execute(&mut w, &data);
+        assert!(out.is_ok());
+        assert_eq!(String::from_utf8(w).unwrap(), "a1b2");
+
+        let mut map = HashMap::new();
+        map.insert("a".to_owned(), 1);
+        map.insert("b".to_owned(), 2);
+        #[derive(Gtmpl)]
+        struct Foo {
+            foo: HashMap<String, i32>,
+        }
+        let foo = Foo { foo: map };
+        let data = Context::from(foo);
+        let mut w: Vec<u8> = vec![];
+        let mut t = Template::default();
+        assert!(
+            t.parse(r#"{{ range $k, $v := .foo -}} {{ $v }} {{- end }}"#)
+                .is_ok()
+        );
+        let out = t.execute(&mut w, &data);
+        assert!(out.is_ok());
+        assert_eq!(String::from_utf8(w).unwrap(), "12");
+
+        let mut map = HashMap::new();
+        #[derive(Gtmpl, Clone)]
+        struct Bar {
+            bar: i32,
+        }
+        map.insert("a".to_owned(), Bar { bar: 1 });
+        map.insert("b".to_owned(), Bar { bar: 2 });
+        let data = Context::from(map);
+        let mut w: Vec<u8> = vec![];
+        let mut t = Template::default();
+        assert!(
+            t.parse(r#"{{ range $k, $v := . -}} {{ $v.bar }} {{- end }}"#)
+                .is_ok()
+        );
+        let out = t.execute(&mut w, &data);
+        assert!(out.is_ok());
+        assert_eq!(String::from_utf8(w).unwrap(), "12");
+    }
+
+    #[test]
+    fn test_if_with_scope() {
+        for text in &[
+            r#"{{ if true }}{{ $x := 1 }}{{ end }}{{ $x }}"#,
+            r#"{{ with 1 }}{{ $x := . }}{{ else }}{{ end }}{{ $x }}"#,
+        ] {
+            let mut t = Template::default();
+            let err = t.parse(text).unwrap_err();
+            assert!(err.ends_with("undefined variable $x"), "{}", err);
+        }
+
+        let mut t = Template::default();
+        assert!(
+            t.parse(
+                r#"{{ $x := 0 }}{{ with 1 }}{{ $x := . }}{{ $x }}{{ end }}{{ if true }}{{ $x := 2 }}{{ $x }}{{ end }}{{ $x }}"#
+            ).is_ok()
+        );
+        assert_eq!(t.render(&Context::empty()), Ok(String::from("120")));
+    }
+
+    #[test]
+    fn test_assign() {
+        let data = Context::from(1);
+        let mut w: Vec<u8> = vec![];
+        let mut t = Template::default();
+        assert!(
+            t.parse(r#"{{ $x := 1 }}{{ if . }}{{ $x = 2 }}{{ end }}{{ $x }}"#)
+                .is_ok()
+        );
+        let out = t.execute(&mut w, &data);
+        assert!(out.is_ok());
+        assert_eq!(String::from_utf8(w).unwrap(), "2");
+
+        let mut w: Vec<u8> = vec![];
+        let mut t = Template::default();
+        assert!(
+            t.parse(r#"{{ $x := 1 }}{{ if . }}{{ $x := 2 }}{{ $x }}{{ end }}{{ $x }}"#)
+                .is_ok()
+        );
+        let out = t.execute(&mut w, &data);
+        assert!(out.is_ok());
+        assert_eq!(String::from_utf8(w).unwrap(), "21");
+
+        let mut w: Vec<u8> = vec![];
+        let mut t = Template::default();
+        assert!(
+            t.parse(r#"{{ $s := print "" }}{{ range 3 }}{{ $s = print $s . }}{{ end }}{{ $s }}"#)
+                .is_ok()
+        );
+        let out = t.execute(&mut w, &data);
+        assert!(out.is_ok());
+        assert_eq!(String::from_utf8(w).unwrap(), "012");
+
+        let mut w: Vec<u8> = vec![];
+        let mut t = Template::default();
+        assert!(
+            t.parse(r#"{{ $i := 0 }}{{ range $i = 3 }}{{ end }}{{ $i }}"#)
+                .is_ok()
+        );
+        let out = t.execute(&mut w, &data);
+        assert!(out.is_ok());
+        assert_eq!(String::from_utf8(w).unwrap(), "2");
+
+        let mut map = HashMap::new();
+        map.insert("e".to_owned(), Vec::<i64>::new());
+        let mut t = Template::default();
+        assert!(
+            t.parse(r#"{{ $v := 5 }}{{ range $v = .e }}{{ end }}{{ $v }}"#)
+                .is_ok()
+        );
+        assert_eq!(t.render(&Context::from(map)), Ok(String::from("5")));
+
+        let mut t = Template::default();
+        assert!(t.parse(r#"{{ $x = 1 }}"#).is_err());
+
+        for text in &[
+            r#"{{ $ := 1 }}"#,
+            r#"{{ $ = 1 }}"#,
+            r#"{{ range $ := . }}{{ end }}"#,
+            r#"{{ range $i, $ := . }}{{ end }}"#,
+        ] {
+            let mut t = Template::default();
+            let err = t.parse(text).unwrap_err();
+            assert!(err.ends_with("can't declare or assign to $"), "{}", err);
+        }
+    }
+
+    #[test]
+    fn test_negative_number() {
+        let data = Context::from(1);
+        let mut w: Vec<u8> = vec![];
+        let mut t = Template::default();
+        assert!(t.parse(r#"{{-3}} {{ print 10 -3 }} {{ lt -3 . }} {{ gt -2.5 -3 }}"#).is_ok());
+        let out = t.execute(&mut w, &data);
+        assert!(out.is_ok());
+        assert_eq!(String::from_utf8(w).unwrap(), "-3 10 -3 true true");
+
+        let mut w: Vec<u8> = vec![];
+        let mut t = Template::default();
+        assert!(t.parse(r#"a {{- -3 -}} b"#).is_ok());
+        let out = t.execute(&mut w, &data);
+        assert!(out.is_ok());
+        assert_eq!(String::from_utf8(w).unwrap(), "a-3b");
+
+        let mut t = Template::default();
+        assert!(t.parse(r#"{{ -1.5 }} {{ -0.25 }} {{ print -1.5 -1 }} {{ -1.0 }}"#).is_ok());
+        assert_eq!(t.render(&data), Ok(String::from("-1.5 -0.25 -1.5 -1 -1")));
+    }
+
+    #[test]
+    fn test_arithmetic() {
+        let data = Context::from(3);
+        let mut w: Vec<u8> = vec![];
+        let mut t = Template::default();
+        assert!(
+            t.parse(r#"{{ add 10 -3 }} {{ . | mul 2 }} {{ . | sub 10 }} {{ add . 0.5 }}"#)
+                .is_ok()
+        );
+        let out = t.execute(&mut w, &data);
+        assert!(out.is_ok());
+        assert_eq!(String::from_utf8(w).unwrap(), "7 6 7 3.5");
+
+        let mut w: Vec<u8> = vec![];
+        let mut t = Template::default();
+        assert!(t.parse(r#"{{ div . 0 }}"#).is_ok());
+        let out = t.execute(&mut w, &data);
+        assert!(out.is_err());
+    }
+
+    #[test]
+    fn test_from_json() {
+        let mut map = HashMap::new();
+        map.insert(
+            "raw".to_owned(),
+            r#"{"name": "foo", "tags": ["a", "b"], "nested": {"n": 1}}"#.to_owned(),
+        );
+        let data = Context::from(map);
+        let mut w: Vec<u8> = vec![];
+        let mut t = Template::default();
+        assert!(
+            t.parse(r#"{{ with fromJson .raw }}{{ .name }} {{ .nested.n }} {{ .tags }}{{ end }}"#)
+                .is_ok()
+        );
+        let out = t.execute(&mut w, &data);
+        assert!(out.is_ok());
+        assert_eq!(String::from_utf8(w).unwrap(), "foo 1 [a b]");
+
+        let mut w: Vec<u8> = vec![];
+        let mut t = Template::default();
+        assert!(t.parse(r#"{{ fromJson "{" }}"#).is_ok());
+        let out = t.execute(&mut w, &data);
+        assert!(out.is_err());
+    }
+
+    #[test]
+    fn test_len() {
+        let mut w: Vec<u8> = vec![];
+        let mut t = Template::default();
+        assert!(t.parse(r#"my len is {{ len . }}"#).is_ok());
+        let data = Context::from(vec![1, 2, 3]);
+        let out = t.execute(&mut w, &data);
+        assert!(out.is_ok());
+        assert_eq!(String::from_utf8(w).unwrap(), "my len is 3");
+
+        let mut w: Vec<u8> = vec![];
+        let mut t = Template::default();
+        assert!(t.parse(r#"{{ len . }}"#).is_ok());
+        let data = Context::from("hello".to_owned());
+        let out = t.execute(&mut w, &data);
+        assert!(out.is_ok());
+        assert_eq!(String::from_utf8(w).unwrap(), "5");
+
+        let mut map = HashMap::new();
+        map.insert("a".to_owned(), 1);
+        map.insert("b".to_owned(), 2);
+        let mut w: Vec<u8> = vec![];
+        let data = Context::from(map);
+        let out = t.execute(&mut w, &data);
+        assert!(out.is_ok());
+        assert_eq!(String::from_utf8(w).unwrap(), "2");
+
+        #[derive(Gtmpl)]
+        struct Foo {
+            foo: u8,
+            bar: u8,
+            baz: u8,
+        }
+        let foo = Foo {
+            foo: 1,
+            bar: 2,
+            baz: 3,
+        };
+        let mut w: Vec<u8> = vec![];
+        let data = Context::from(foo);
+        let out = t.execute(&mut w, &data);
+        assert!(out.is_ok());
+        assert_eq!(String::from_utf8(w).unwrap(), "3");
+
+        let mut w: Vec<u8> = vec![];
+        let data = Context::from(23);
+        let out = t.execute(&mut w, &data);
+        assert!(out.is_err());
+    }
+
+    #[test]
+    fn test_print_collections() {
+        let mut t = Template::default();
+        assert!(t.parse(r#"{{ . }}"#).is_ok());
+
+        let data = Context::from(vec![1, 2, 3]);
+        let mut w: Vec<u8> = vec![];
+        let out = t.execute(&mut w, &data);
+        assert!(out.is_ok());
+        assert_eq!(String::from_utf8(w).unwrap(), "[1 2 3]");
+
+        let mut map = HashMap::new();
+        map.insert("b".to_owned(), 2);
+        map.insert("a".to_owned(), 1);
+        let data = Context::from(map);
+        let mut w: Vec<u8> = vec![];
+        let out = t.execute(&mut w, &data);
+        assert!(out.is_ok());
+        assert_eq!(String::from_utf8(w).unwrap(), "map[a:1 b:2]");
+    }
+
+    #[test]
+    fn test_print() {
+        let mut w: Vec<u8> = vec![];
+        let mut t = Template::default();
+        assert!(t.parse(r#"{{ print 1 2 3 }}"#).is_ok());
+        let data = Context::from(1);
+        let out = t.execute(&mut w, &data);
+        assert!(out.is_ok());
+        assert_eq!(String::from_utf8(w).unwrap(), "1 2 3");
+
+        let mut w: Vec<u8> = vec![];
+        let mut t = Template::default();
+        assert!(t.parse(r#"{{ println 1 2 . }}"#).is_ok());
+        let data = Context::from("foo");
+        let out = t.execute(&mut w, &data);
+        assert!(out.is_ok());
+        assert_eq!(String::from_utf8(w).unwrap(), "1 2 foo\n");
+    }
+
+    #[test]
+    fn test_and_or() {
+        let mut w: Vec<u8> = vec![];
+        let mut t = Template::default();
+        assert!(t.parse(r#"{{ and 1 0 2 }} {{ and 1 "foo" }}"#).is_ok());
+        let data = Context::from(1);
+        let out = t.execute(&mut w, &data);
+        assert!(out.is_ok());
+        assert_eq!(String::from_utf8(w).unwrap(), "0 foo");
+
+        let mut w: Vec<u8> = vec![];
+        let mut t = Template::default();
+        assert!(t.parse(r#"{{ or 0 "" "foo" 1 }} {{ or 0 false }}"#).is_ok());
+        let data = Context::from(1);
+        let out = t.execute(&mut w, &data);
+        assert!(out.is_ok());
+        assert_eq!(String::from_utf8(w).unwrap(), "foo false");
+
+        let mut w: Vec<u8> = vec![];
+        let mut t = Template::default();
+        assert!(t.parse(r#"{{ 2 | and 1 }} {{ 3 | or 0 }}"#).is_ok());
+        let data = Context::from(1);
+        let out = t.execute(&mut w, &data);
+        assert!(out.is_ok());
+        assert_eq!(String::from_utf8(w).unwrap(), "2 3");
+    }
+
+    #[test]
+    fn test_and_or_short_circuit() {
+        let mut w: Vec<u8> = vec![];
+        let mut t = Template::default();
+        assert!(
+            t.parse(r#"{{ and false (index . 23) }} {{ or true (index . 23) }}"#)
+                .is_ok()
+        );
+        let data = Context::from(vec![1, 2]);
+        let out = t.execute(&mut w, &data);
+        assert!(out.is_ok());
+        assert_eq!(String::from_utf8(w).unwrap(), "false true");
+
+        let mut w: Vec<u8> = vec![];
+        let mut t = Template::default();
+        assert!(t.parse(r#"{{ and true (index . 23) }}"#).is_ok());
+        let data = Context::from(vec![1, 2]);
+        let out = t.execute(&mut w, &data);
+        assert!(out.is_err());
+
+        // Overridden builtins are called like any other function, with all arguments
+        // evaluated, while the builtin keeps working under another name.
+        fn join(args: &[Arc<Any>]) -> Result<Arc<Any>, String> {
+            let s = args.iter()
+                .filter_map(|arg| arg.downcast_ref::<Value>())
+                .map(to_go_string)
+                .collect::<Vec<_>>();
+            Ok(Arc::new(Value::from(s.join("&"))))
+        }
+        let mut t = Template::default();
+        t.add_func("and", join).add_func("both", funcs::and);
+        assert!(t.parse(r#"{{ and false 1 }} {{ both 1 false }}"#).is_ok());
+        assert_eq!(t.render(&Context::empty()), Ok(String::from("false&1 false")));
+
+        let mut t = Template::default();
+        t.add_func("and", join);
+        assert!(t.parse(r#"{{ and false (index . 23) }}"#).is_ok());
+        assert!(t.render(&data).is_err());
+    }
+
+    #[test]
+    fn test_pipeline_function() {
+        let mut w: Vec<u8> = vec![];
+        let mut t = Template::default();
+        assert!(t.parse(r#"{{ if ( 1 | eq . ) -}} 2000 {{- end }}"#).is_ok());
+        let data = Context::from(1);
+        let out = t.execute(&mut w, &data);
+        assert!(out.is_ok());
+        assert_eq!(String::from_utf8(w).unwrap(), "2000");
+    }
+
+    #[test]
+    fn test_pipeline_extra_argument() {
+        let data = Context::from("a");
+        for (text, out) in &[
+            (r#"{{ . | upper }}"#, Ok(String::from("A"))),
+            (r#"{{ upper . }}"#, Ok(String::from("A"))),
+            (r#"{{ (upper .) | lower }}"#, Ok(String::from("a"))),
+            (
+                r#"{{ . | upper "b" }}"#,
+                Err(String::from("template: :1: upper requires one argument")),
+            ),
+            (
+                r#"{{ 1 | lt 2 3 }}"#,
+                Err(String::from("template: :1: wrong number of args for lt: want 2 got 3")),
+            ),
+            (r#"{{ 1 | lt 2 }}"#, Ok(String::from("false"))),
+        ] {
+            let mut t = Template::default();
+            assert!(t.parse(text).is_ok());
+            assert_eq!(&t.render(&data), out, "{}", text);
+        }
+    }
+
+    #[test]
+    fn test_pipeline_argument() {
+        let mut w: Vec<u8> = vec![];
+        let mut t = Template::default();
+        assert!(t.parse(r#"{{ printf "%d" (add 1 2) }}"#).is_ok());
+        let data = Context::from(1);
+        let out = t.execute(&mut w, &data);
+        assert!(out.is_ok());
+        assert_eq!(String::from_utf8(w).unwrap(), "3");
+
+        let mut w: Vec<u8> = vec![];
+        let mut t = Template::default();
+        assert!(
+            t.parse(
+                r#"{{ printf "%d %s %d" (add . (mul 2 3)) (print "x" | upper) (len (split "," "a,b")) }}"#
+            ).is_ok()
+        );
+        let out = t.execute(&mut w, &data);
+        assert!(out.is_ok());
+        assert_eq!(String::from_utf8(w).unwrap(), "7 X 2");
+
+        let mut w: Vec<u8> = vec![];
+        let mut t = Template::default();
+        assert!(t.parse(r#"{{ $x := 2 }}{{ index (split "," "a,b,c") $x }}"#).is_ok());
+        let out = t.execute(&mut w, &data);
+        assert!(out.is_ok());
+        assert_eq!(String::from_utf8(w).unwrap(), "c");
+
+        let mut w: Vec<u8> = vec![];
+        let mut t = Template::default();
+        assert!(t.parse(r#"{{ printf "%d" (div 1 0) }}"#).is_ok());
+        let out = t.execute(&mut w, &data);
+        assert!(out.is_err());
+    }
+
+    #[test]
+    fn test_literal_keywords() {
+        // The lexer turns `true`, `false` and `nil` into literals, so they never reach the
+        // function lookup.
+        let mut t = Template::default();
+        assert!(
+            t.parse(
+                r#"{{ if true }}1{{ end }}{{ if false }}2{{ else }}3{{ end }}{{ if not nil }}4{{ end }}{{ false }}"#
+            ).is_ok()
+        );
+        let out = t.render(&Context::empty());
+        assert_eq!(out, Ok(String::from("134false")));
+
+        let mut t = Template::default();
+        assert!(t.parse(r#"{{ ture }}"#).is_err());
+    }
+
+    #[test]
+    fn test_function() {
+        let mut w: Vec<u8> = vec![];
+        let mut t = Template::default();
+        assert!(t.parse(r#"{{ if eq . . -}} 2000 {{- end }}"#).is_ok());
+        let data = Context::from(1);
+        let out = t.execute(&mut w, &data);
+        assert!(out.is_ok());
+        assert_eq!(String::from_utf8(w).unwrap(), "2000");
+    }
+
+    #[test]
+    fn test_call() {
+        fn add(args: &[Arc<Any>]) -> Result<Arc<Any>, String> {
+            let sum = args.iter()
+                .map(|a| {
+                    a.downcast_ref::<Value>()
+                        .and_then(i64::from_value)
+                        .ok_or_else(|| String::from("add requires numbers"))
+                })
+                .sum::<Result<i64, String>>()?;
+            Ok(Arc::new(Value::from(sum)))
+        }
+        let mut map: HashMap<String, Value> = HashMap::new();
+        map.insert("add".to_owned(), Value::Function(Function { f: add }));
+        map.insert("x".to_owned(), Value::from(23));
+        let data = Context::from(map);
+
+        let mut w: Vec<u8> = vec![];
+        let mut t = Template::default();
+        assert!(t.parse(r#"{{ call .add 1 2 }} {{ call .add .x 19 }}"#).is_ok());
+        let out = t.execute(&mut w, &data);
+        assert!(out.is_ok());
+        assert_eq!(String::from_utf8(w).unwrap(), "3 42");
+
+        let mut w: Vec<u8> = vec![];
+        let mut t = Template::default();
+        assert!(t.parse(r#"{{ 1 | call .add 2 }}"#).is_ok());
+        let out = t.execute(&mut w, &data);
+        assert!(out.is_ok());
+        assert_eq!(String::from_utf8(w).unwrap(), "3");
+
+        let mut w: Vec<u8> = vec![];
+        let mut t = Template::default();
+        assert!(t.parse(r#"{{ call .add 1 "foo" }}"#).is_ok());
+        let out = t.execute(&mut w, &data);
+        assert_eq!(out, Err(String::from("template: :1: add requires numbers")));
+
+        let mut w: Vec<u8> = vec![];
+        let mut t = Template::default();
+        assert!(t.parse(r#"{{ call .x 1 }}"#).is_ok());
+        let out = t.execute(&mut w, &data);
+        assert!(out.is_err());
+    }
+
+    #[test]
+    fn test_method() {
+        fn add(args: &[Arc<Any>]) -> Result<Arc<Any>, String> {
+            let sum = args.iter()
+                .map(|a| {
+                    a.downcast_ref::<Value>()
+                        .and_then(i64::from_value)
+                        .ok_or_else(|| String::from("add requires numbers"))
+                })
+                .sum::<Result<i64, String>>()?;
+            Ok(Arc::new(Value::from(sum)))
+        }
+        let mut obj: HashMap<String, Value> = HashMap::new();
+        obj.insert("add".to_owned(), Value::Function(Function { f: add }));
+        obj.insert("x".to_owned(), Value::from(23));
+        let mut map: HashMap<String, Value> = HashMap::new();
+        map.insert("obj".to_owned(), Value::Object(obj));
+        map.insert("add".to_owned(), Value::Function(Function { f: add }));
+        let data = Context::from(map);
+
+        let mut w: Vec<u8> = vec![];
+        let mut t = Template::default();
+        assert!(
+            t.parse(r#"{{ .obj.add 1 2 }} {{ .add .obj.x 19 }} {{ $o := .obj }}{{ $o.add 3 4 }}"#)
+                .is_ok()
+        );
+        let out = t.execute(&mut w, &data);
+        assert!(out.is_ok());
+        assert_eq!(String::from_utf8(w).unwrap(), "3 42 7");
+
+        let mut w: Vec<u8> = vec![];
+        let mut t = Template::default();
+        assert!(t.parse(r#"{{ 1 | .obj.add 2 }} {{ call .obj.add 5 }}"#).is_ok());
+        let out = t.execute(&mut w, &data);
+        assert!(out.is_ok());
+        assert_eq!(String::from_utf8(w).unwrap(), "3 5");
+
+        let mut w: Vec<u8> = vec![];
+        let mut t = Template::default();
+        assert!(t.parse(r#"{{ .obj.x 1 }}"#).is_ok());
+        let out = t.execute(&mut w, &data);
+        assert!(out.is_err());
+    }
+
+    #[test]
+    fn test_eq() {
+        let mut w: Vec<u8> = vec![];
+        let mut t = Template::default();
+        assert!(t.parse(r#"{{ if eq "a" "a" -}} 2000 {{- end }}"#).is_ok());
+        let data = Context::from(1);
+        let out = t.execute(&mut w, &data);
+        assert!(out.is_ok());
+        assert_eq!(String::from_utf8(w).unwrap(), "2000");
+
+        let mut w: Vec<u8> = vec![];
+        let mut t = Template::default();
+        assert!(t.parse(r#"{{ if eq "a" "b" -}} 2000 {{- end }}"#).is_ok());
+        let data = Context::from(1);
+        let out = t.execute(&mut w, &data);
+        assert!(out.is_ok());
+        assert_eq!(String::from_utf8(w).unwrap(), "");
+
+        let mut w: Vec<u8> = vec![];
+        let mut t = Template::default();
+        assert!(t.parse(r#"{{ if eq true true -}} 2000 {{- end }}"#).is_ok());
+        let data = Context::from(1);
+        let out = t.execute(&mut w, &data);
+        assert!(out.is_ok());
+        assert_eq!(String::from_utf8(w).unwrap(), "2000");
+
+        let mut w: Vec<u8> = vec![];
+        let mut t = Template::default();
+        assert!(
+            t.parse(r#"{{ if eq true false -}} 2000 {{- end }}"#)
+                .is_ok()
+        );
+        let data = Context::from(1);
+        let out = t.execute(&mut w, &data);
+        assert!(out.is_ok());
+        assert_eq!(String::from_utf8(w).unwrap(), "");
+
+        let mut w: Vec<u8> = vec![];
+        let mut t = Template::default();
+        assert!(
+            t.parse(r#"{{ if eq 23.42 23.42 -}} 2000 {{- end }}"#)
+                .is_ok()
+        );
+        let data = Context::from(1);
+        let out = t.execute(&mut w, &data);
+        assert!(out.is_ok());
+        assert_eq!(String::from_utf8(w).unwrap(), "2000");
+
+        let mut w: Vec<u8> = vec![];
+        let mut t = Template::default();
+        assert!(t.parse(r#"{{ if eq 1 . -}} 2000 {{- end }}"#).is_ok());
+        let data = Context::from(1);
+        let out = t.execute(&mut w, &data);
+        assert!(out.is_ok());
+        assert_eq!(String::from_utf8(w).unwrap(), "2000");
+    }
+
+    #[test]
+    fn test_ne() {
+        let mut w: Vec<u8> = vec![];
+        let mut t = Template::default();
+        assert!(t.parse(r#"{{ if ne "a" "b" -}} 2000 {{- end }}"#).is_ok());
+        let data = Context::from(1);
+        let out = t.execute(&mut w, &data);
+        assert!(out.is_ok());
+        assert_eq!(String::from_utf8(w).unwrap(), "2000");
+
+
+        let mut w: Vec<u8> = vec![];
+        let mut t = Template::default();
+        assert!(t.parse(r#"{{ if ne "a" "a" -}} 2000 {{- end }}"#).is_ok());
+        let data = Context::from(1);
+        let out = t.execute(&mut w, &data);
+        assert!(out.is_ok());
+        assert_eq!(String::from_utf8(w).unwrap(), "");
+
+
+        let mut w: Vec<u8> = vec![];
+        let mut t = Template::default();
+        assert!(t.parse(r#"{{ if ne 23.42 23.42 -}} 2000 {{- end }}"#).is_ok());
+        let data = Context::from(1);
+        let out = t.execute(&mut w, &data);
+        assert!(out.is_ok());
+        assert_eq!(String::from_utf8(w).unwrap(), "");
+
+
+        let mut w: Vec<u8> = vec![];
+        let mut t = Template::default();
+        assert!(t.parse(r#"{{ if ne 1 2.5 -}} 2000 {{- end }}"#).is_ok());
+        let data = Context::from(1);
+        let out = t.execute(&mut w, &data);
+        assert!(out.is_ok());
+        assert_eq!(String::from_utf8(w).unwrap(), "2000");
+
+
+        let mut w: Vec<u8> = vec![];
+        let mut t = Template::default();
+        assert!(t.parse(r#"{{ if ne 2 . -}} 2000 {{- end }}"#).is_ok());
+        let data = Context::from(1);
+        let out = t.execute(&mut w, &data);
+        assert!(out.is_ok());
+        assert_eq!(String::from_utf8(w).unwrap(), "2000");
+    }
+
+    #[test]
+    fn test_lt() {
+        let mut w: Vec<u8> = vec![];
+        let mut t = Template::default();
+        assert!(t.parse(r#"{{ if lt "a" "b" -}} 2000 {{- end }}"#).is_ok());
+        let data = Context::from(1);
+        let out = t.execute(&mut w, &data);
+        assert!(out.is_ok());
+        assert_eq!(String::from_utf8(w).unwrap(), "2000");
+
+
+        let mut w: Vec<u8> = vec![];
+        let mut t = Template::default();
+        assert!(t.parse(r#"{{ if lt "b" "a" -}} 2000 {{- end }}"#).is_ok());
+        let data = Context::from(1);
+        let out = t.execute(&mut w, &data);
+        assert!(out.is_ok());
+        assert_eq!(String::from_utf8(w).unwrap(), "");
+
+
+        let mut w: Vec<u8> = vec![];
+        let mut t = Template::default();
+        assert!(t.parse(r#"{{ if lt 1 2.5 -}} 2000 {{- end }}"#).is_ok());
+        let data = Context::from(1);
+        let out = t.execute(&mut w, &data);
+        assert!(out.is_ok());
+        assert_eq!(String::from_utf8(w).unwrap(), "2000");
+
+
+        let mut w: Vec<u8> = vec![];
+        let mut t = Template::default();
+        assert!(t.parse(r#"{{ if lt -1 0 -}} 2000 {{- end }}"#).is_ok());
+        let data = Context::from(1);
+        let out = t.execute(&mut w, &data);
+        assert!(out.is_ok());
+        assert_eq!(String::from_utf8(w).unwrap(), "2000");
+
+
+        let mut w: Vec<u8> = vec![];
+        let mut t = Template::default();
+        assert!(t.parse(r#"{{ if lt 0 . -}} 2000 {{- end }}"#).is_ok());
+        let data = Context::from(1);
+        let out = t.execute(&mut w, &data);
+        assert!(out.is_ok());
+        assert_eq!(String::from_utf8(w).unwrap(), "2000");
+    }
+
+    #[test]
+    fn test_le() {
+        let mut w: Vec<u8> = vec![];
+        let mut t = Template::default();
+        assert!(t.parse(r#"{{ if le "a" "a" -}} 2000 {{- end }}"#).is_ok());
+        let data = Context::from(1);
+        let out = t.execute(&mut w, &data);
+        assert!(out.is_ok());
+        assert_eq!(String::from_utf8(w).unwrap(), "2000");
+
+
+        let mut w: Vec<u8> = vec![];
+        let mut t = Template::default();
+        assert!(t.parse(r#"{{ if le "b" "a" -}} 2000 {{- end }}"#).is_ok());
+        let data = Context::from(1);
+        let out = t.execute(&mut w, &data);
+        assert!(out.is_ok());
+        assert_eq!(String::from_utf8(w).unwrap(), "");
+
+
+        let mut w: Vec<u8> = vec![];
+        let mut t = Template::default();
+        assert!(t.parse(r#"{{ if le 2.5 2 -}} 2000 {{- end }}"#).is_ok());
+        let data = Context::from(1);
+        let out = t.execute(&mut w, &data);
+        assert!(out.is_ok());
+        assert_eq!(String::from_utf8(w).unwrap(), "");
+
+
+        let mut w: Vec<u8> = vec![];
+        let mut t = Template::default();
+        assert!(t.parse(r#"{{ if le 1 1 -}} 2000 {{- end }}"#).is_ok());
+        let data = Context::from(1);
+        let out = t.execute(&mut w, &data);
+        assert!(out.is_ok());
+        assert_eq!(String::from_utf8(w).unwrap(), "2000");
+
+
+        let mut w: Vec<u8> = vec![];
+        let mut t = Template::default();
+        assert!(t.parse(r#"{{ if le 1 . -}} 2000 {{- end }}"#).is_ok());
+        let data = Context::from(1);
+        let out = t.execute(&mut w, &data);
+        assert!(out.is_ok());
+        assert_eq!(String::from_utf8(w).unwrap(), "2000");
+    }
+
+    #[test]
+    fn test_gt() {
+        let mut w: Vec<u8> = vec![];
+        let mut t = Template::default();
+        assert!(t.parse(r#"{{ if gt "b" "a" -}} 2000 {{- end }}"#).is_ok());
+        let data = Context::from(1);
+        let out = t.execute(&mut w, &data);
+        assert!(out.is_ok());
+        assert_eq!(String::from_utf8(w).unwrap(), "2000");
+
+
+        let mut w: Vec<u8> = vec![];
+        let mut t = Template::default();
+        assert!(t.parse(r#"{{ if gt "a" "b" -}} 2000 {{- end }}"#).is_ok());
+        let data = Context::from(1);
+        let out = t.execute(&mut w, &data);
+        assert!(out.is_ok());
+        assert_eq!(String::from_utf8(w).unwrap(), "");
+
+
+        let mut w: Vec<u8> = vec![];
+        let mut t = Template::default();
+        assert!(t.parse(r#"{{ if gt 2.5 2 -}} 2000 {{- end }}"#).is_ok());
+        let data = Context::from(1);
+        let out = t.execute(&mut w, &data);
+        assert!(out.is_ok());
+        assert_eq!(String::from_utf8(w).unwrap(), "2000");
+
+
+        let mut w: Vec<u8> = vec![];
+        let mut t = Template::default();
+        assert!(t.parse(r#"{{ if gt 1 -1 -}} 2000 {{- end }}"#).is_ok());
+        let data = Context::from(1);
+        let out = t.execute(&mut w, &data);
+        assert!(out.is_ok());
+        assert_eq!(String::from_utf8(w).unwrap(), "2000");
+
+
+        let mut w: Vec<u8> = vec![];
+        let mut t = Template::default();
+        assert!(t.parse(r#"{{ if gt 1 . -}} 2000 {{- end }}"#).is_ok());
+        let data = Context::from(1);
+        let out = t.execute(&mut w, &data);
+        assert!(out.is_ok());
+        assert_eq!(String::from_utf8(w).unwrap(), "");
+    }
+
+    #[test]
+    fn test_ge() {
+        let mut w: Vec<u8> = vec![];
+        let mut t = Template::default();
+        assert!(t.parse(r#"{{ if ge "a" "a" -}} 2000 {{- end }}"#).is_ok());
+        let data = Context::from(1);
+        let out = t.execute(&mut w, &data);
+        assert!(out.is_ok());
+        assert_eq!(String::from_utf8(w).unwrap(), "2000");
+
+
+        let mut w: Vec<u8> = vec![];
+        let mut t = Template::default();
+        assert!(t.parse(r#"{{ if ge "a" "b" -}} 2000 {{- end }}"#).is_ok());
+        let data = Context::from(1);
+        let out = t.execute(&mut w, &data);
+        assert!(out.is_ok());
+        assert_eq!(String::from_utf8(w).unwrap(), "");
+
+
+        let mut w: Vec<u8> = vec![];
+        let mut t = Template::default();
+        assert!(t.parse(r#"{{ if ge 2 2.5 -}} 2000 {{- end }}"#).is_ok());
+        let data = Context::from(1);
+        let out = t.execute(&mut w, &data);
+        assert!(out.is_ok());
+        assert_eq!(String::from_utf8(w).unwrap(), "");
+
+
+        let mut w: Vec<u8> = vec![];
+        let mut t = Template::default();
+        assert!(t.parse(r#"{{ if ge 3 3 -}} 2000 {{- end }}"#).is_ok());
+        let data = Context::from(1);
+        let out = t.execute(&mut w, &data);
+        assert!(out.is_ok());
+        assert_eq!(String::from_utf8(w).unwrap(), "2000");
+
+
+        let mut w: Vec<u8> = vec![];
+        let mut t = Template::default();
+        assert!(t.parse(r#"{{ if ge 1 . -}} 2000 {{- end }}"#).is_ok());
+        let data = Context::from(1);
         let out = t.execute(&mut w, &data);
         assert!(out.is_ok());
         assert_eq!(String::from_utf8(w).unwrap(), "2000");
     }
+
+    #[test]
+    fn test_cmp_incompatible() {
+        for op in &["ne", "lt", "le", "gt", "ge"] {
+            let tmpl = format!(r#"{{{{ if {} "a" 1 -}}}} 2000 {{{{- end }}}}"#, op);
+            let mut w: Vec<u8> = vec![];
+            let mut t = Template::default();
+            assert!(t.parse(&tmpl).is_ok());
+            let data = Context::from(1);
+            let out = t.execute(&mut w, &data);
+            assert!(out.is_err());
+        }
+    }
+
+    #[test]
+    fn test_execute_streams() {
+        use std::io;
+
+        // Records every write, so we can tell how the output arrived.
+        struct Recorder {
+            writes: Vec<String>,
+        }
+        impl io::Write for Recorder {
+            fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+                self.writes.push(String::from_utf8(buf.to_vec()).unwrap());
+                Ok(buf.len())
+            }
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let items: Vec<u64> = (0..1000).collect();
+        let data = Context::from(items);
+        let mut w = Recorder { writes: vec![] };
+        let mut t = Template::default();
+        assert!(t.parse(r#"start{{ range . }}{{ . }}{{ end }}"#).is_ok());
+        let out = t.execute(&mut w, &data);
+        assert!(out.is_ok());
+        assert_eq!(w.writes.len(), 1001);
+        assert_eq!(w.writes[0], "start");
+        for (i, write) in w.writes[1..].iter().enumerate() {
+            assert_eq!(write, &i.to_string());
+        }
+    }
+
+    #[test]
+    fn test_indirect_field() {
+        #[allow(clippy::arc_with_non_send_sync)]
+        fn wrap(args: &[Arc<Any>]) -> Result<Arc<Any>, String> {
+            let inner: Arc<Any> = Arc::clone(&args[0]);
+            Ok(Arc::new(inner))
+        }
+
+        let mut map = HashMap::new();
+        map.insert("name".to_owned(), "foo".to_owned());
+        let value = Arc::new(Value::from(map));
+        let mut t = Template::default();
+        t.add_func("wrap", wrap);
+        assert!(t.parse(r#"{{ .name }} {{ with wrap . }}{{ .name }}{{ end }} {{ wrap . }}"#).is_ok());
+        let data = Context::from_any(Arc::new(Arc::clone(&value)));
+        assert_eq!(t.render(&data), Ok(String::from("foo foo map[name:foo]")));
+
+        let mut t = Template::default();
+        assert!(t.parse(r#"{{ .name }}"#).is_ok());
+        let out = t.render(&Context::from_any(Arc::new(1u8)));
+        assert_eq!(
+            out,
+            Err(String::from("template: :1: can't evaluate field name in value of type u8"))
+        );
+    }
+
+    #[test]
+    fn test_chain_on_pipe() {
+        fn config(args: &[Arc<Any>]) -> Result<Arc<Any>, String> {
+            let mut inner = HashMap::new();
+            inner.insert("port".to_owned(), Value::from(8080));
+            let mut map = HashMap::new();
+            map.insert("name".to_owned(), Value::from(args.len()));
+            map.insert("server".to_owned(), Value::Map(inner));
+            Ok(Arc::new(Value::Map(map)))
+        }
+
+        let data = Context::from(1);
+        let mut t = Template::default();
+        t.add_func("config", config);
+        assert!(
+            t.parse(
+                r#"{{ (config .).name }} {{ (config . .).server.port }} {{ eq (config).server.port 8080 }}"#
+            ).is_ok()
+        );
+        assert_eq!(t.render(&data), Ok(String::from("1 8080 true")));
+
+        let mut t = Template::default();
+        t.add_func("config", config);
+        assert!(t.parse(r#"{{ (. | config).name }}{{ (config).missing }}"#).is_ok());
+        assert_eq!(t.render(&data), Ok(String::from("1<no value>")));
+
+        let mut t = Template::default();
+        t.add_func("config", config);
+        assert!(t.parse(r#"{{ (config).name.foo }}"#).is_ok());
+        assert!(t.render(&data).is_err());
+    }
+
+    #[test]
+    fn test_method_chain() {
+        fn point(_args: &[Arc<Any>]) -> Result<Arc<Any>, String> {
+            let mut p: HashMap<String, Value> = HashMap::new();
+            p.insert("x".to_owned(), Value::from(1));
+            p.insert("y".to_owned(), Value::from(2));
+            Ok(Arc::new(Value::Object(p)))
+        }
+        fn fail(_args: &[Arc<Any>]) -> Result<Arc<Any>, String> {
+            Err(String::from("no point"))
+        }
+        let mut obj: HashMap<String, Value> = HashMap::new();
+        obj.insert("point".to_owned(), Value::Function(Function { f: point }));
+        obj.insert("fail".to_owned(), Value::Function(Function { f: fail }));
+        let mut map: HashMap<String, Value> = HashMap::new();
+        map.insert("obj".to_owned(), Value::Object(obj));
+        let data = Context::from(map);
+
+        let mut t = Template::default();
+        assert!(
+            t.parse(r#"{{ .obj.point.x }} {{ $o := .obj }}{{ $o.point.y }} {{ (.obj).point.x }}"#)
+                .is_ok()
+        );
+        assert_eq!(t.render(&data), Ok(String::from("1 2 1")));
+
+        let mut t = Template::default();
+        assert!(t.parse(r#"{{ .obj.fail.x }}"#).is_ok());
+        assert_eq!(t.render(&data), Err(String::from("template: :1: no point")));
+    }
+
+    #[test]
+    fn test_print_function() {
+        fn f(_args: &[Arc<Any>]) -> Result<Arc<Any>, String> {
+            Ok(Arc::new(Value::from(1)))
+        }
+
+        let mut map = HashMap::new();
+        map.insert("f".to_owned(), Value::Function(Function { f }));
+        let data = Context::from(map);
+        let mut t = Template::default();
+        assert!(t.parse(r#"{{ call .f }}"#).is_ok());
+        assert_eq!(t.render(&data), Ok(String::from("1")));
+
+        let mut t = Template::default();
+        assert!(t.parse(r#"{{ .f }}"#).is_ok());
+        assert_eq!(
+            t.render(&data),
+            Err(String::from("template: :1: cannot print a function value"))
+        );
+
+        let mut t = Template::default();
+        assert!(t.parse(r#"{{ . }}"#).is_ok());
+        let out = t.render(&Context::from_any(Arc::new(f as Func)));
+        assert_eq!(out, Err(String::from("template: :1: cannot print a function value")));
+    }
+
+    #[test]
+    fn test_trim_blocks() {
+        let mut t = Template::default();
+        assert!(
+            t.parse("<ul>\r\n{{- range . }}\r\n\t<li>{{ . }}</li>\r\n\n{{- end }}\r\n</ul>")
+                .is_ok()
+        );
+        let out = t.render(&Context::from(vec![1, 2]));
+        assert_eq!(out, Ok(String::from("<ul>\r\n\t<li>1</li>\r\n\t<li>2</li>\r\n</ul>")));
+    }
+
+    #[test]
+    fn test_eval() {
+        let mut t = Template::default();
+        assert!(t.parse(r#"{{add 1 2}}"#).is_ok());
+        assert_eq!(t.eval(&Context::empty()), Ok(Value::from(3)));
+
+        let mut t = Template::default();
+        assert!(t.parse(r#"{{ split "," . | len }}"#).is_ok());
+        assert_eq!(t.eval(&Context::from("a,b,c")), Ok(Value::from(3)));
+
+        let mut t = Template::default();
+        assert!(t.parse(r#"{{ . }}"#).is_ok());
+        assert_eq!(t.eval(&Context::from(vec![1])), Ok(Value::from(vec![1])));
+
+        let mut t = Template::default();
+        assert!(t.parse(r#"{{ div 1 0 }}"#).is_ok());
+        assert_eq!(
+            t.eval(&Context::empty()),
+            Err(String::from("template: :1: div by zero"))
+        );
+
+        for text in &["{{ 1 }}{{ 2 }}", "a{{ 1 }}", "{{ if true }}1{{ end }}", ""] {
+            let mut t = Template::default();
+            assert!(t.parse(text).is_ok());
+            assert!(t.eval(&Context::empty()).is_err());
+        }
+    }
+
+    #[test]
+    fn test_execute_template() {
+        let mut t = Template::with_name("root");
+        assert!(
+            t.parse(
+                r#"{{ define "a" }}a: {{ . }}{{ end }}{{ define "b" }}b: {{ template "a" $ }}{{ end }}root"#
+            ).is_ok()
+        );
+        let data = Context::from(1);
+
+        let mut w: Vec<u8> = vec![];
+        assert!(t.execute_template(&mut w, "a", &data).is_ok());
+        assert_eq!(String::from_utf8(w).unwrap(), "a: 1");
+
+        let mut w: Vec<u8> = vec![];
+        assert!(t.execute_template(&mut w, "b", &data).is_ok());
+        assert_eq!(String::from_utf8(w).unwrap(), "b: a: 1");
+
+        let mut w: Vec<u8> = vec![];
+        assert!(t.execute_template(&mut w, "root", &data).is_ok());
+        assert_eq!(String::from_utf8(w).unwrap(), "root");
+
+        let mut w: Vec<u8> = vec![];
+        let out = t.execute_template(&mut w, "c", &data);
+        assert_eq!(
+            out,
+            Err(String::from(
+                r#"template: no template "c" associated with template "root""#
+            ))
+        );
+    }
+
+    #[test]
+    fn test_execute_repeatedly() {
+        let mut t = Template::default();
+        assert!(
+            t.parse(
+                r#"{{ define "item" }}{{ $x := . }}<{{ $x }}>{{ end }}{{ $n := len . }}{{ range $i, $e := . }}{{ if lt $i $n }}{{ template "item" $e }}{{ end }}{{ end }}"#
+            ).is_ok()
+        );
+        let mut w: Vec<u8> = Vec::with_capacity(16);
+        for i in 0..10_000u64 {
+            let data = Context::from(vec![i, i + 1]);
+            w.clear();
+            assert!(t.execute(&mut w, &data).is_ok());
+            assert_eq!(w, format!("<{}><{}>", i, i + 1).as_bytes());
+        }
+    }
+
+    #[test]
+    fn test_tpl() {
+        fn shout(args: &[Arc<Any>]) -> Result<Arc<Any>, String> {
+            let s = args[0].downcast_ref::<Value>().map(to_go_string).unwrap_or_default();
+            Ok(Arc::new(Value::from(format!("{}!", s))))
+        }
+
+        let mut map = HashMap::new();
+        map.insert("tmpl".to_owned(), Value::from("{{ .name | shout }} {{ template \"x\" . }}"));
+        map.insert("name".to_owned(), Value::from("foo"));
+        map.insert("empty".to_owned(), Value::from(""));
+        map.insert("self".to_owned(), Value::from("{{ tpl .self . }}"));
+        let data = Context::from(map);
+
+        let mut t = Template::default();
+        t.add_func("shout", shout);
+        assert!(
+            t.parse(r#"{{ define "x" }}<{{ .name }}>{{ end }}{{ tpl .tmpl . }}|{{ tpl .empty . }}|{{ tpl "{{ . }}" 1 }}"#)
+                .is_ok()
+        );
+        assert_eq!(t.render(&data), Ok(String::from("foo! <foo>||1")));
+
+        let mut t = Template::default();
+        assert!(t.parse(r#"{{ $x := 1 }}{{ tpl "{{ $x }}" . }}"#).is_ok());
+        let err = t.render(&data).unwrap_err();
+        assert!(err.starts_with("template: :1: error calling tpl: template: tpl:"), err);
+
+        let mut t = Template::default();
+        t.max_depth(10);
+        assert!(t.parse(r#"{{ tpl .self . }}"#).is_ok());
+        let err = t.render(&data).unwrap_err();
+        assert!(err.ends_with("exceeded maximum template depth"), err);
+
+        let mut t = Template::default();
+        assert!(t.parse(r#"{{ tpl .self . }}"#).is_ok());
+        let err = t.render(&data).unwrap_err();
+        assert!(err.ends_with("exceeded maximum template depth"), err);
+
+        let mut t = Template::default();
+        assert!(t.parse(r#"{{ tpl 1 . }}"#).is_ok());
+        let err = t.render(&data).unwrap_err();
+        assert!(err.ends_with("tpl requires a template string, got number"), err);
+
+        // Templates defined by `tpl` shadow the ones of the template, unless they are
+        // empty, and are visible to nested calls but not afterwards.
+        let mut map = HashMap::new();
+        map.insert(
+            "defs".to_owned(),
+            Value::from(r#"{{ define "x" }}[x]{{ end }}{{ define "y" }}{{ end }}{{ template "x" }}{{ template "y" }}{{ tpl .nested . }}"#),
+        );
+        map.insert("nested".to_owned(), Value::from(r#"{{ template "x" }}"#));
+        let data = Context::from(map);
+        let mut t = Template::default();
+        assert!(
+            t.parse(r#"{{ define "x" }}x{{ end }}{{ define "y" }}y{{ end }}{{ tpl .defs . }}|{{ template "x" }}"#)
+                .is_ok()
+        );
+        assert_eq!(t.render(&data), Ok(String::from("[x]y[x]|x")));
+    }
+
+    #[test]
+    fn test_deep_nesting() {
+        let text = "{{ if . }}x{{ end }}".repeat(5000);
+        let mut t = Template::default();
+        assert!(t.parse(&text).is_ok());
+        assert_eq!(t.render(&Context::from(true)).map(|s| s.len()), Ok(5000));
+
+        let text = format!(
+            "{}{{{{ . }}}}{}",
+            "{{ if . }}{{ with . }}".repeat(50),
+            "{{ end }}{{ end }}".repeat(50)
+        );
+        let mut t = Template::default();
+        assert!(t.parse(&text).is_ok());
+        assert_eq!(t.render(&Context::from(1)), Ok(String::from("1")));
+
+        let text = format!("{}x{}", "{{ if . }}".repeat(5000), "{{ end }}".repeat(5000));
+        let mut t = Template::default();
+        let err = t.parse(&text).unwrap_err();
+        assert!(err.ends_with("exceeded maximum nesting depth"), err);
+
+        let text = format!(
+            "{{{{ if eq . 0 }}}}0{}{{{{ end }}}}",
+            (1..250)
+                .map(|i| format!("{{{{ else if eq . {} }}}}{}", i, i))
+                .collect::<String>()
+        );
+        let mut t = Template::default();
+        assert!(t.parse(&text).is_ok());
+        assert_eq!(t.render(&Context::from(249)), Ok(String::from("249")));
+
+        let text = format!(
+            "{}x{}",
+            "{{ with . }}{{ else with . }}{{ else with . }}{{ else with . }}".repeat(90),
+            "{{ end }}".repeat(90)
+        );
+        let mut t = Template::default();
+        let err = t.parse(&text).unwrap_err();
+        assert!(err.ends_with("exceeded maximum else with chain length"), err);
+
+        let text = format!(
+            "{}x{}",
+            "{{ if . }}{{ else }}".repeat(5000),
+            "{{ end }}".repeat(5000)
+        );
+        let mut t = Template::default();
+        let err = t.parse(&text).unwrap_err();
+        assert!(err.ends_with("exceeded maximum nesting depth"), err);
+
+        let text = format!("{{{{ {}1{} }}}}", "(".repeat(5000), ")".repeat(5000));
+        let mut t = Template::default();
+        let err = t.parse(&text).unwrap_err();
+        assert!(err.ends_with("exceeded maximum nesting depth"), err);
+
+        let text = format!("{{{{ {}1{} }}}}", "(".repeat(100), ")".repeat(100));
+        let mut t = Template::default();
+        assert!(t.parse(&text).is_ok());
+        assert_eq!(t.render(&Context::empty()), Ok(String::from("1")));
+    }
+
+    #[test]
+    fn test_max_depth() {
+        let mut t = Template::default();
+        assert!(
+            t.parse(r#"{{ define "a" }}{{ template "a" }}{{ end }}{{ template "a" }}"#)
+                .is_ok()
+        );
+        assert_eq!(
+            t.render(&Context::empty()),
+            Err(String::from("template: :1: exceeded maximum template depth"))
+        );
+
+        let mut t = Template::default();
+        t.max_depth(3);
+        assert!(
+            t.parse(
+                r#"{{ define "a" }}{{ if lt . 3 }}{{ . }}{{ template "a" add . 1 }}{{ end }}{{ end }}{{ template "a" . }}"#
+            ).is_ok()
+        );
+        assert_eq!(t.render(&Context::from(1)), Ok(String::from("12")));
+        assert!(t.render(&Context::from(0)).is_err());
+    }
+
+    #[test]
+    fn test_render_repeatedly() {
+        let mut t = Template::with_name("root");
+        assert!(
+            t.parse(r#"{{ define "item" }}<{{ . }}>{{ end }}{{ range . }}{{ template "item" . }}{{ end }}"#)
+                .is_ok()
+        );
+        let data = Context::from(vec![1, 2, 3]);
+        for _ in 0..10_000 {
+            assert_eq!(t.render(&data), Ok(String::from("<1><2><3>")));
+        }
+
+        // Parsing more templates keeps the root in place.
+        assert!(t.parse(r#"{{ define "item" }}[{{ . }}]{{ end }}"#).is_ok());
+        assert_eq!(t.render(&data), Ok(String::from("[1][2][3]")));
+
+        // The root follows changes to the public tree ids.
+        t.tree_ids.insert(1, String::from("item"));
+        assert_eq!(t.render(&data), Ok(String::from("[[1 2 3]]")));
+    }
+
+    #[test]
+    fn test_context_from_arc() {
+        let items: Vec<u64> = (0..1000).collect();
+        let value = Arc::new(Value::from(items));
+        let mut t = Template::default();
+        assert!(t.parse(r#"{{ len . }} {{ index . 999 }}"#).is_ok());
+        for _ in 0..100 {
+            let data = Context::from_arc(Arc::clone(&value));
+            assert_eq!(Arc::strong_count(&value), 2);
+            let out = t.render(&data);
+            assert_eq!(out, Ok(String::from("1000 999")));
+        }
+        assert_eq!(Arc::strong_count(&value), 1);
+    }
+
+    #[test]
+    fn test_context_from() {
+        let mut t = Template::default();
+        assert!(t.parse(r#"{{ . }}"#).is_ok());
+        assert_eq!(t.render(&Context::from(1)), Ok(String::from("1")));
+        assert_eq!(t.render(&Context::from("foo")), Ok(String::from("foo")));
+        assert_eq!(t.render(&Context::from(vec![1, 2])), Ok(String::from("[1 2]")));
+        assert_eq!(t.render(&Context::from(Value::Nil)), Ok(String::from("nil")));
+    }
+
+    #[cfg(feature = "serde_json")]
+    #[test]
+    fn test_context_from_json() {
+        let data: ::serde_json::Value = ::serde_json::from_str(
+            r#"{"name": "foo", "n": -2, "f": 1.5, "none": null, "items": [{"id": 1}, {"id": 2}]}"#,
+        ).unwrap();
+        let mut t = Template::default();
+        assert!(
+            t.parse(
+                r#"{{ .name }} {{ add .n 1 }} {{ .f }} {{ .none }} {{ range .items }}{{ .id }}{{ end }}"#
+            ).is_ok()
+        );
+        let out = t.render(&Context::from_json(data));
+        assert_eq!(out, Ok(String::from("foo -1 1.5 nil 12")));
+    }
+
+    #[test]
+    fn test_execute_fmt() {
+        let mut t = Template::default();
+        assert!(t.parse(r#"{{ range . }}{{ . }},{{ end }}"#).is_ok());
+        let mut w = String::from("> ");
+        let out = t.execute_fmt(&mut w, &Context::from(vec!["ä", "b"]));
+        assert!(out.is_ok());
+        assert_eq!(w, "> ä,b,");
+
+        let mut w = String::new();
+        assert!(t.parse(r#"a{{ len 1 }}"#).is_ok());
+        let out = t.execute_fmt(&mut w, &Context::empty());
+        assert!(out.is_err());
+        assert_eq!(w, "a");
+    }
+
+    #[test]
+    fn test_execute_io_error() {
+        struct Broken;
+        impl io::Write for Broken {
+            fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+                Err(io::Error::from(io::ErrorKind::BrokenPipe))
+            }
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let mut t = Template::default();
+        assert!(t.parse(r#"hello"#).is_ok());
+        let out = t.execute(&mut Broken, &Context::empty());
+        assert_eq!(
+            out,
+            Err(String::from("template: :1: error writing output: broken pipe"))
+        );
+
+        let mut t = Template::with_name("foo");
+        assert!(t.parse(r#"{{ if true }}{{ . }}{{ end }}"#).is_ok());
+        let out = t.execute(&mut Broken, &Context::from(1));
+        assert_eq!(
+            out,
+            Err(String::from("template: foo:1: error writing output: broken pipe"))
+        );
+
+        struct BrokenFmt;
+        impl fmt::Write for BrokenFmt {
+            fn write_str(&mut self, _: &str) -> fmt::Result {
+                Err(fmt::Error)
+            }
+        }
+
+        let mut t = Template::default();
+        assert!(t.parse("\n{{ 1 }}").is_ok());
+        let out = t.execute_fmt(&mut BrokenFmt, &Context::empty());
+        assert_eq!(out, Err(String::from("template: :1: error writing output")));
+    }
 }
@@ -1,16 +1,24 @@
 //! Builtin functions.
 use std::any::Any;
 use std::cmp::Ordering;
+use std::convert::TryFrom;
 use std::fmt::Write;
+use std::mem;
 use std::sync::Arc;
 
-use gtmpl_value::{Func, Value};
+use gtmpl_value::{Func, Number, Value};
+use itertools::Itertools;
 
 extern crate percent_encoding;
-use self::percent_encoding::{utf8_percent_encode, DEFAULT_ENCODE_SET};
+use self::percent_encoding::{utf8_percent_encode, EncodeSet};
 
-use utils::is_true;
+extern crate base64;
+
+use utils::{is_true, kind_name, quote_str, to_go_string};
 use printf::sprintf;
+use json;
+use exec::{Context, TPL_NAME};
+use template::Template;
 
 pub static BUILTINS: &[(&'static str, Func)] = &[
     ("eq", eq as Func),
@@ -23,12 +31,60 @@ pub static BUILTINS: &[(&'static str, Func)] = &[
     ("and", and as Func),
     ("or", or as Func),
     ("not", not as Func),
+    ("default", default as Func),
+    ("empty", empty as Func),
+    ("coalesce", coalesce as Func),
+    ("ternary", ternary as Func),
     ("urlquery", urlquery as Func),
+    ("html", html as Func),
+    ("js", js as Func),
+    ("b64enc", b64enc as Func),
+    ("b64dec", b64dec as Func),
+    ("quote", quote as Func),
+    ("toString", to_string as Func),
+    ("atoi", int as Func),
+    ("int", int as Func),
+    ("float", float as Func),
+    ("upper", upper as Func),
+    ("lower", lower as Func),
+    ("title", title as Func),
+    ("trim", trim as Func),
+    ("trimPrefix", trim_prefix as Func),
+    ("trimSuffix", trim_suffix as Func),
+    ("trimAll", trim_all as Func),
+    ("replace", replace as Func),
+    ("repeat", repeat as Func),
+    ("indent", indent as Func),
+    ("nindent", nindent as Func),
+    ("contains", contains as Func),
+    ("hasPrefix", has_prefix as Func),
+    ("hasSuffix", has_suffix as Func),
+    ("join", join as Func),
+    ("split", split as Func),
+    ("toJson", to_json as Func),
+    ("toPrettyJson", to_pretty_json as Func),
+    ("fromJson", from_json as Func),
+    ("add", add as Func),
+    ("sub", sub as Func),
+    ("mul", mul as Func),
+    ("div", div as Func),
+    ("mod", modulo as Func),
     ("print", print as Func),
     ("println", println as Func),
     ("printf", printf as Func),
     ("index", index as Func),
+    ("slice", slice as Func),
+    ("list", list as Func),
+    ("until", until as Func),
+    ("untilStep", until_step as Func),
+    ("reverse", reverse as Func),
+    ("first", first as Func),
+    ("last", last as Func),
+    ("rest", rest as Func),
+    ("has", has as Func),
+    ("hasKey", has_key as Func),
     ("call", call as Func),
+    ("tpl", tpl as Func),
 ];
 
 macro_rules! varc(
@@ -45,6 +101,13 @@ macro_rules! gtmpl_fn {
     ) => {
         $(#[$outer])*
         pub fn $name(args: &[Arc<Any>]) -> Result<Arc<Any>, String> {
+            if !args.is_empty() {
+                return Err(format!(
+                    "wrong number of args for {}: want 0 got {}",
+                    stringify!($name),
+                    args.len()
+                ));
+            }
             fn inner() -> Result<$otyp, String> {
                 $($body)*
             }
@@ -60,8 +123,12 @@ macro_rules! gtmpl_fn {
         pub fn $name(
             args: &[::std::sync::Arc<::std::any::Any>]
         ) -> Result<::std::sync::Arc<::std::any::Any>, String> {
-            if args.is_empty() {
-                return Err(String::from("at least one argument required"));
+            if args.len() != 1 {
+                return Err(format!(
+                    "wrong number of args for {}: want 1 got {}",
+                    stringify!($name),
+                    args.len()
+                ));
             }
             let x = &args[0];
             let $arg0 = x.downcast_ref::<$crate::Value>()
@@ -86,8 +153,14 @@ macro_rules! gtmpl_fn {
         ) -> Result<::std::sync::Arc<::std::any::Any>, String> {
             #[allow(unused_mut)]
             let mut args = args;
-            if args.is_empty() {
-                return Err(String::from("at least one argument required"));
+            let want = 1 $(+ { let _ = stringify!($arg); 1 })*;
+            if args.len() != want {
+                return Err(format!(
+                    "wrong number of args for {}: want {} got {}",
+                    stringify!($name),
+                    want,
+                    args.len()
+                ));
             }
             let x = &args[0];
             let $arg0 = x.downcast_ref::<$crate::Value>()
@@ -119,7 +192,11 @@ macro_rules! gn {
         $(#[$outer])*
         pub fn $name(args: &[Arc<Any>]) -> Result<Arc<Any>, String> {
             if args.len() != 2 {
-                return Err(String::from("two arguments required"));
+                return Err(format!(
+                    "wrong number of args for {}: want 2 got {}",
+                    stringify!($name),
+                    args.len()
+                ));
             }
             let x = &args[0];
             let $arg1 = x.downcast_ref::<Value>()
@@ -137,8 +214,8 @@ macro_rules! gn {
 
 ///	Returns the boolean OR of its arguments by returning the
 ///	first non-empty argument or the last argument, that is,
-///	"or x y" behaves as "if x then x else y". All the
-///	arguments are evaluated.
+///	"or x y" behaves as "if x then x else y". Within a template
+///	evaluation stops at the first non-empty argument.
 ///
 /// # Example
 /// ```
@@ -160,8 +237,8 @@ pub fn or(args: &[Arc<Any>]) -> Result<Arc<Any>, String> {
 
 /// Returns the boolean AND of its arguments by returning the
 ///	first empty argument or the last argument, that is,
-///	"and x y" behaves as "if x then y else x". All the
-///	arguments are evaluated.
+///	"and x y" behaves as "if x then y else x". Within a template
+///	evaluation stops at the first empty argument.
 ///
 /// # Example
 /// ```
@@ -197,6 +274,79 @@ pub fn not(args: &[Arc<Any>]) -> Result<Arc<Any>, String> {
     }
 }
 
+/// Returns the second argument unless it is empty, in which case the first argument
+/// is returned. Like Sprig's `default`, empty values are `false`, `0`, `""`, empty
+/// arrays and maps, `nil` and missing values. Without a second argument the default
+/// is returned.
+///
+/// # Example
+/// ```
+/// use gtmpl::template;
+/// let name = template(r#"{{ . | default "anon" }}"#, "");
+/// assert_eq!(&name.unwrap(), "anon");
+/// let name = template(r#"{{ . | default "anon" }}"#, "gtmpl");
+/// assert_eq!(&name.unwrap(), "gtmpl");
+/// ```
+pub fn default(args: &[Arc<Any>]) -> Result<Arc<Any>, String> {
+    match args.len() {
+        1 => Ok(Arc::clone(&args[0])),
+        2 if is_true(&args[1]) => Ok(Arc::clone(&args[1])),
+        2 => Ok(Arc::clone(&args[0])),
+        _ => Err(String::from("default requires 1 or 2 arguments")),
+    }
+}
+
+/// Returns true if its single argument is empty. Like with `default`, empty values
+/// are `false`, `0`, `""`, empty arrays and maps, `nil` and missing values.
+///
+/// # Example
+/// ```
+/// use gtmpl::template;
+/// let empty = template(r#"{{ empty . }} {{ empty "foo" }}"#, "");
+/// assert_eq!(&empty.unwrap(), "true false");
+/// ```
+pub fn empty(args: &[Arc<Any>]) -> Result<Arc<Any>, String> {
+    if args.len() != 1 {
+        Err(String::from("empty requires a single argument"))
+    } else {
+        Ok(varc!(!is_true(&args[0])))
+    }
+}
+
+/// Returns the first non-empty argument, or `nil` if all arguments are empty.
+///
+/// # Example
+/// ```
+/// use gtmpl::template;
+/// let name = template(r#"{{ coalesce . "" "anon" }}"#, 0);
+/// assert_eq!(&name.unwrap(), "anon");
+/// ```
+pub fn coalesce(args: &[Arc<Any>]) -> Result<Arc<Any>, String> {
+    Ok(args.iter()
+        .find(|arg| is_true(arg))
+        .map(Arc::clone)
+        .unwrap_or_else(|| Arc::new(Value::Nil)))
+}
+
+/// Returns the first argument if the third is truthy, and the second otherwise.
+///
+/// # Example
+/// ```
+/// use gtmpl::template;
+/// let ternary = template(r#"{{ ternary "yes" "no" . }} {{ . | ternary 1 2 }}"#, 0);
+/// assert_eq!(&ternary.unwrap(), "no 2");
+/// ```
+pub fn ternary(args: &[Arc<Any>]) -> Result<Arc<Any>, String> {
+    if args.len() != 3 {
+        return Err(String::from("ternary requires 3 arguments"));
+    }
+    if is_true(&args[2]) {
+        Ok(Arc::clone(&args[0]))
+    } else {
+        Ok(Arc::clone(&args[1]))
+    }
+}
+
 /// Returns the integer length of its argument.
 ///
 /// # Example
@@ -214,9 +364,9 @@ pub fn len(args: &[Arc<Any>]) -> Result<Arc<Any>, String> {
         match *x {
             Value::String(ref s) => s.len(),
             Value::Array(ref a) => a.len(),
-            Value::Object(ref o) => o.len(),
+            Value::Object(ref o) | Value::Map(ref o) => o.len(),
             _ => {
-                return Err(format!("unable to call len on {}", x));
+                return Err(format!("unable to call len on {}", kind_name(x)));
             }
         }
     } else {
@@ -250,17 +400,49 @@ pub fn call(args: &[Arc<Any>]) -> Result<Arc<Any>, String> {
     let vals: Vec<&Value> = args.iter()
         .map(|arg| {
             arg.downcast_ref::<Value>()
-                .ok_or_else(|| String::from("print requires arguemnts of type Value"))
+                .ok_or_else(|| String::from("call requires arguments of type Value"))
         })
         .collect::<Result<Vec<_>, String>>()?;
     if vals.is_empty() {
-        Err(String::from("call requires at least on argument"))
+        Err(String::from("call requires at least one argument"))
     } else if let Value::Function(ref f) = *vals[0] {
         (f.f)(&args[1..])
     } else {
-        Err(String::from(
-            "call requires the first argument to be a function",
-        ))
+        Err(format!("non-function {} of type Value", vals[0]))
+    }
+}
+
+/// Renders the first argument, which must be a string, as a template with the second
+/// argument as data and returns the output. Within a template the rendered text can
+/// use the functions and the associated templates of the executing template. When
+/// called directly only the builtin functions are available.
+///
+/// # Example
+/// ```
+/// use gtmpl::template;
+/// use std::collections::HashMap;
+/// let mut map = HashMap::new();
+/// map.insert("greeting".to_owned(), "Hello {{ .name | upper }}!");
+/// map.insert("name".to_owned(), "world");
+/// let rendered = template("{{ tpl .greeting . }}", map);
+/// assert_eq!(&rendered.unwrap(), "Hello WORLD!");
+/// ```
+pub fn tpl(args: &[Arc<Any>]) -> Result<Arc<Any>, String> {
+    let (text, data) = tpl_args(args)?;
+    let mut tmpl = Template::with_name(TPL_NAME);
+    tmpl.parse(text)?;
+    let s = tmpl.render(&data)?;
+    Ok(varc!(s))
+}
+
+pub(crate) fn tpl_args(args: &[Arc<Any>]) -> Result<(&str, Context), String> {
+    if args.len() != 2 {
+        return Err(String::from("tpl requires 2 arguments"));
+    }
+    match args[0].downcast_ref::<Value>() {
+        Some(&Value::String(ref s)) => Ok((s, Context::from_any(Arc::clone(&args[1])))),
+        Some(val) => Err(format!("tpl requires a template string, got {}", kind_name(val))),
+        None => Err(String::from("unable to downcast")),
     }
 }
 
@@ -290,9 +472,9 @@ pub fn print(args: &[Arc<Any>]) -> Result<Arc<Any>, String> {
             s.push_str(v);
         } else {
             if no_space {
-                s += &val.to_string();
+                s += &to_go_string(val);
             } else {
-                s += &format!(" {}", val.to_string())
+                s += &format!(" {}", to_go_string(val))
             }
             no_space = false;
         }
@@ -324,18 +506,10 @@ pub fn println(args: &[Arc<Any>]) -> Result<Arc<Any>, String> {
         Some(first_elt) => {
             let (lower, _) = iter.size_hint();
             let mut result = String::with_capacity(lower + 1);
-            if let Value::String(ref v) = *(*first_elt) {
-                result.push_str(v);
-            } else {
-                write!(&mut result, "{}", first_elt).unwrap();
-            }
+            result.push_str(&to_go_string(first_elt));
             for elt in iter {
                 result.push_str(" ");
-                if let Value::String(ref v) = *(*elt) {
-                    result.push_str(v);
-                } else {
-                    write!(&mut result, "{}", elt).unwrap();
-                }
+                result.push_str(&to_go_string(elt));
             }
             result.push_str("\n");
             result
@@ -345,6 +519,7 @@ pub fn println(args: &[Arc<Any>]) -> Result<Arc<Any>, String> {
 }
 
 /// An implementation of golang's fmt.Sprintf
+/// Explicit argument indexes like `%[1]s` are supported.
 /// Limitations:
 /// - float:
 ///   * `g`, `G`, and `b` are weired and not implement yet
@@ -355,6 +530,9 @@ pub fn println(args: &[Arc<Any>]) -> Result<Arc<Any>, String> {
 /// use gtmpl::template;
 /// let equal = template(r#"{{ printf "%v %s %v" "Hello" . "!" }}"#, "world");
 /// assert_eq!(&equal.unwrap(), "Hello world !");
+///
+/// let indexed = template(r#"{{ printf "%[1]s, %[1]s!" . }}"#, "hey");
+/// assert_eq!(&indexed.unwrap(), "hey, hey!");
 /// ```
 pub fn printf(args: &[Arc<Any>]) -> Result<Arc<Any>, String> {
     let vals: Vec<&Value> = args.iter()
@@ -403,14 +581,277 @@ pub fn index(args: &[Arc<Any>]) -> Result<Arc<Any>, String> {
     Ok(Arc::new(col.clone()))
 }
 
+/// Returns the result of slicing its first argument by the remaining
+/// arguments. Thus "slice x 1 2" is, in Go syntax, x[1:2], while "slice x"
+/// is x[:] and "slice x 1" is x[1:]. The first argument must be an array or
+/// a string. Strings are sliced by characters.
+///
+/// # Example
+/// ```
+/// use gtmpl::template;
+/// let ctx = vec![23, 42, 7];
+/// let slice = template("{{ index (slice . 1) 0 }} {{ slice \"foobar\" 1 3 }}", ctx);
+/// assert_eq!(&slice.unwrap(), "42 oo");
+/// ```
+pub fn slice(args: &[Arc<Any>]) -> Result<Arc<Any>, String> {
+    if args.is_empty() {
+        return Err(String::from("slice requires at least 1 argument"));
+    }
+    if args.len() > 3 {
+        return Err(String::from("too many slice indexes"));
+    }
+    let vals: Vec<&Value> = args.iter()
+        .map(|arg| {
+            arg.downcast_ref::<Value>()
+                .ok_or_else(|| String::from("slice arguments must be of type Value"))
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+    let len = match *vals[0] {
+        Value::Array(ref a) => a.len(),
+        Value::String(ref s) => s.chars().count(),
+        _ => return Err(format!("can't slice item of type {}", kind_name(vals[0]))),
+    };
+    let mut idx = [0, len];
+    for (i, val) in vals[1..].iter().enumerate() {
+        idx[i] = match **val {
+            Value::Number(ref n) => n.as_u64()
+                .map(|n| n as usize)
+                .ok_or_else(|| format!("invalid slice index {}", n))?,
+            _ => return Err(format!("cannot slice with index of type {}", kind_name(val))),
+        };
+    }
+    if idx[0] > idx[1] {
+        return Err(format!("invalid slice index: {} > {}", idx[0], idx[1]));
+    }
+    if idx[1] > len {
+        return Err(format!("slice index out of range: {}", idx[1]));
+    }
+    match *vals[0] {
+        Value::Array(ref a) => Ok(varc!(a[idx[0]..idx[1]].to_vec())),
+        Value::String(ref s) => Ok(varc!(
+            s.chars()
+                .skip(idx[0])
+                .take(idx[1] - idx[0])
+                .collect::<String>()
+        )),
+        _ => unreachable!(),
+    }
+}
+
+/// Returns an array of its arguments, e.g. to range over a fixed set of values.
+///
+/// # Example
+/// ```
+/// use gtmpl::template;
+/// let list = template("{{ range list 1 . 3 }}{{ . }}{{ end }}", 2);
+/// assert_eq!(&list.unwrap(), "123");
+/// ```
+pub fn list(args: &[Arc<Any>]) -> Result<Arc<Any>, String> {
+    let vals = args.iter()
+        .map(|arg| {
+            arg.downcast_ref::<Value>()
+                .cloned()
+                .ok_or_else(|| String::from("list requires arguments of type Value"))
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+    Ok(Arc::new(Value::Array(vals)))
+}
+
+/// Returns an array of the integers from 0 up to, but not including, its argument.
+/// A negative argument counts down instead. Arrays larger than 64 MiB are an error.
+///
+/// # Example
+/// ```
+/// use gtmpl::template;
+/// let until = template("{{ range until . }}{{ . }}{{ end }}", 5);
+/// assert_eq!(&until.unwrap(), "01234");
+/// ```
+pub fn until(args: &[Arc<Any>]) -> Result<Arc<Any>, String> {
+    if args.len() != 1 {
+        return Err(String::from("until requires 1 argument"));
+    }
+    let stop = int_arg("until", &args[0])?;
+    let step = if stop < 0 { -1 } else { 1 };
+    Ok(varc!(step_range("until", 0, stop, step)?))
+}
+
+/// Returns an array of the integers from the first argument up to, but not
+/// including, the second, counting in steps of the third argument. The array is
+/// empty if the step doesn't lead from start to stop. Like with `until`, arrays
+/// larger than 64 MiB are an error.
+///
+/// # Example
+/// ```
+/// use gtmpl::template;
+/// let until = template("{{ untilStep 0 10 3 }} {{ untilStep 3 0 -1 }}", 0);
+/// assert_eq!(&until.unwrap(), "[0 3 6 9] [3 2 1]");
+/// ```
+pub fn until_step(args: &[Arc<Any>]) -> Result<Arc<Any>, String> {
+    if args.len() != 3 {
+        return Err(String::from("untilStep requires 3 arguments"));
+    }
+    let start = int_arg("untilStep", &args[0])?;
+    let stop = int_arg("untilStep", &args[1])?;
+    let step = int_arg("untilStep", &args[2])?;
+    Ok(varc!(step_range("untilStep", start, stop, step)?))
+}
+
+fn step_range(name: &str, start: i64, stop: i64, step: i64) -> Result<Vec<Value>, String> {
+    let (start, stop, step) = (i128::from(start), i128::from(stop), i128::from(step));
+    let len = if (step > 0 && start < stop) || (step < 0 && start > stop) {
+        (stop - start + step - step.signum()) / step
+    } else {
+        0
+    };
+    let size = len * mem::size_of::<Value>() as i128;
+    check_size(name, usize::try_from(size).ok())?;
+    Ok((0..len).map(|k| Value::from((start + k * step) as i64)).collect())
+}
+
+fn int_arg(name: &str, arg: &Arc<Any>) -> Result<i64, String> {
+    match arg.downcast_ref::<Value>() {
+        Some(&Value::Number(ref n)) if !is_float(n) => n.as_i64()
+            .ok_or_else(|| format!("{} argument out of range: {}", name, n)),
+        Some(&Value::Number(ref n)) => {
+            Err(format!("{} requires integer arguments, got {}", name, n))
+        }
+        Some(val) => Err(format!("{} requires integer arguments, got {}", name, kind_name(val))),
+        None => Err(String::from("unable to downcast")),
+    }
+}
+
+/// Returns a copy of its argument in reverse order. The argument must be an
+/// array or a string. Strings are reversed by characters.
+///
+/// # Example
+/// ```
+/// use gtmpl::template;
+/// let ctx = vec![1, 2, 3];
+/// let reversed = template("{{ range reverse . }}{{ . }}{{ end }} {{ reverse \"foo\" }}", ctx);
+/// assert_eq!(&reversed.unwrap(), "321 oof");
+/// ```
+pub fn reverse(args: &[Arc<Any>]) -> Result<Arc<Any>, String> {
+    let val = single_value("reverse", args)?;
+    match *val {
+        Value::Array(ref a) => Ok(varc!(a.iter().rev().cloned().collect::<Vec<_>>())),
+        Value::String(ref s) => Ok(varc!(s.chars().rev().collect::<String>())),
+        _ => Err(format!("can't reverse item of type {}", kind_name(val))),
+    }
+}
+
+/// Returns the first element of its argument, which must be a non-empty array.
+///
+/// # Example
+/// ```
+/// use gtmpl::template;
+/// let first = template("{{ first . }}", vec![23, 42, 7]);
+/// assert_eq!(&first.unwrap(), "23");
+/// ```
+pub fn first(args: &[Arc<Any>]) -> Result<Arc<Any>, String> {
+    let a = single_array("first", args)?;
+    a.first()
+        .map(|v| Arc::new(v.clone()) as Arc<Any>)
+        .ok_or_else(|| String::from("first of empty array"))
+}
+
+/// Returns the last element of its argument, which must be a non-empty array.
+///
+/// # Example
+/// ```
+/// use gtmpl::template;
+/// let last = template("{{ last . }}", vec![23, 42, 7]);
+/// assert_eq!(&last.unwrap(), "7");
+/// ```
+pub fn last(args: &[Arc<Any>]) -> Result<Arc<Any>, String> {
+    let a = single_array("last", args)?;
+    a.last()
+        .map(|v| Arc::new(v.clone()) as Arc<Any>)
+        .ok_or_else(|| String::from("last of empty array"))
+}
+
+/// Returns all but the first element of its argument, which must be an array.
+/// The rest of an empty array is an empty array.
+///
+/// # Example
+/// ```
+/// use gtmpl::template;
+/// let rest = template("{{ range rest . }}{{ . }} {{ end }}", vec![23, 42, 7]);
+/// assert_eq!(&rest.unwrap(), "42 7 ");
+/// ```
+pub fn rest(args: &[Arc<Any>]) -> Result<Arc<Any>, String> {
+    let a = single_array("rest", args)?;
+    Ok(varc!(a.iter().skip(1).cloned().collect::<Vec<_>>()))
+}
+
+/// Returns true if the second argument, which must be an array, contains the first
+/// argument. Elements are compared like `eq` does.
+///
+/// # Example
+/// ```
+/// use gtmpl::template;
+/// let has = template("{{ has 42 . }} {{ has 3 . }}", vec![23, 42, 7]);
+/// assert_eq!(&has.unwrap(), "true false");
+/// ```
+pub fn has(args: &[Arc<Any>]) -> Result<Arc<Any>, String> {
+    if args.len() != 2 {
+        return Err(String::from("has requires 2 arguments"));
+    }
+    let unpack = || String::from("has requires arguments of type Value");
+    let item = args[0].downcast_ref::<Value>().ok_or_else(unpack)?;
+    match *args[1].downcast_ref::<Value>().ok_or_else(unpack)? {
+        Value::Array(ref a) => Ok(varc!(a.iter().any(|v| equal(item, v)))),
+        ref val => Err(format!("has requires an array, got {}", kind_name(val))),
+    }
+}
+
+/// Returns true if the first argument, which must be a map or an object, has the
+/// second argument as key. Unlike accessing the field, this tells a key holding a
+/// zero value apart from an absent key.
+///
+/// # Example
+/// ```
+/// use gtmpl::template;
+/// use std::collections::HashMap;
+/// let mut map = HashMap::new();
+/// map.insert("foo".to_owned(), 0);
+/// let has_key = template(r#"{{ hasKey . "foo" }} {{ hasKey . "bar" }}"#, map);
+/// assert_eq!(&has_key.unwrap(), "true false");
+/// ```
+pub fn has_key(args: &[Arc<Any>]) -> Result<Arc<Any>, String> {
+    if args.len() != 2 {
+        return Err(String::from("hasKey requires 2 arguments"));
+    }
+    let unpack = || String::from("hasKey requires arguments of type Value");
+    let col = args[0].downcast_ref::<Value>().ok_or_else(unpack)?;
+    let key = match *args[1].downcast_ref::<Value>().ok_or_else(unpack)? {
+        Value::String(ref s) => s.clone(),
+        Value::Number(ref n) => n.to_string(),
+        ref key => return Err(format!("invalid key for hasKey of type {}", kind_name(key))),
+    };
+    match *col {
+        Value::Object(ref o) | Value::Map(ref o) => Ok(varc!(o.contains_key(&key))),
+        _ => Err(format!("hasKey requires a map, got {}", kind_name(col))),
+    }
+}
+
+fn single_array<'a>(name: &str, args: &'a [Arc<Any>]) -> Result<&'a [Value], String> {
+    match *single_value(name, args)? {
+        Value::Array(ref a) => Ok(a),
+        ref val => Err(format!("{} requires an array, got {}", name, kind_name(val))),
+    }
+}
+
 fn get_item<'a>(col: &'a Value, key: &Value) -> Result<&'a Value, String> {
     let ret = match (col, key) {
         (&Value::Array(ref a), &Value::Number(ref n)) => {
             if let Some(i) = n.as_u64() {
-                a.get(i as usize)
-            } else {
-                None
+                return a.get(i as usize)
+                    .ok_or_else(|| format!("index out of range: {}", i));
             }
+            if let Some(i) = n.as_i64() {
+                return Err(format!("index out of range: {}", i));
+            }
+            None
         }
         (&Value::Object(ref o), &Value::Number(ref n))
         | (&Value::Map(ref o), &Value::Number(ref n)) => o.get(&n.to_string()),
@@ -436,362 +877,2398 @@ assert_eq!(&url.unwrap(), \"foo%20bar%3F\");
 ```
 "]
 pub fn urlquery(args: &[Arc<Any>]) -> Result<Arc<Any>, String> {
-    if args.len() != 1 {
-        return Err(String::from("urlquery requires one argument"));
-    }
-    let val = args[0]
-        .downcast_ref::<Value>()
-        .ok_or_else(|| String::from("unable to downcast"))?;
+    let s = single_string("urlquery", args)?;
+    Ok(varc!(utf8_percent_encode(&s, QUERY_ENCODE_SET).to_string()))
+}
 
-    match *val {
-        Value::String(ref s) => Ok(varc!(
-            utf8_percent_encode(s, DEFAULT_ENCODE_SET).to_string()
-        )),
-        _ => Err(String::from("Arguments need to be of type String")),
+/// Everything but the unreserved characters of RFC 3986.
+#[derive(Clone)]
+#[allow(non_camel_case_types)]
+struct QUERY_ENCODE_SET;
+
+impl EncodeSet for QUERY_ENCODE_SET {
+    fn contains(&self, byte: u8) -> bool {
+        !(byte.is_ascii_alphanumeric() || b"-_.~".contains(&byte))
     }
 }
 
 #[doc = "
-Returns the boolean truth of arg1 == arg2 [== arg3 ...]
+Returns the escaped HTML equivalent of the textual representation of its argument.
 
 # Example
 ```
 use gtmpl::template;
-let equal = template(\"{{ eq 1 1 . }}\", 1);
-assert_eq!(&equal.unwrap(), \"true\");
+let html = template(r#\"{{ html \"<a href='foo'>\" }}\"#, 0);
+assert_eq!(&html.unwrap(), \"&lt;a href=&#39;foo&#39;&gt;\");
 ```
 "]
-pub fn eq(args: &[Arc<Any>]) -> Result<Arc<Any>, String> {
-    if args.len() < 2 {
-        return Err(String::from("eq requires at least 2 arguments"));
+pub fn html(args: &[Arc<Any>]) -> Result<Arc<Any>, String> {
+    let s = single_string("html", args)?;
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '&' => escaped.push_str("&amp;"),
+            '\'' => escaped.push_str("&#39;"),
+            '"' => escaped.push_str("&#34;"),
+            '\0' => escaped.push('\u{FFFD}'),
+            _ => escaped.push(c),
+        }
     }
-    let unpack = || String::from("Arguments need to be of type Value.");
-    let first = args[0].downcast_ref::<Value>().ok_or_else(unpack)?;
-    Ok(Arc::new(Value::from(
-        args.iter()
-            .skip(1)
-            .map(|x| x.downcast_ref::<Value>())
-            .all(|x| x.map(|x| x == first).unwrap_or(false)),
-    )))
+    Ok(varc!(escaped))
 }
 
-gn!(
-#[doc="
-Returns the boolean truth of arg1 != arg2
+#[doc = "
+Returns the escaped JavaScript equivalent of the textual representation of its argument.
+Like in Go, quotes and backslashes are escaped with a backslash, while `<`, `>`, `&`,
+`=`, control characters, line and paragraph separators and other non-printable
+characters become `\\uXXXX` escapes.
 
 # Example
 ```
 use gtmpl::template;
-let not_equal = template(\"{{ ne 2 . }}\", 1);
-assert_eq!(&not_equal.unwrap(), \"true\");
+let js = template(r#\"{{ js \"'foo' <bar>\" }}\"#, 0);
+assert_eq!(&js.unwrap(), r#\"\\'foo\\' \\u003Cbar\\u003E\"#);
 ```
 "]
-ne(a: ref Value, b: ref Value) -> Result<Value, String> {
-    Ok(Value::from(a != b))
-});
+pub fn js(args: &[Arc<Any>]) -> Result<Arc<Any>, String> {
+    let s = single_string("js", args)?;
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '\'' => escaped.push_str("\\'"),
+            '"' => escaped.push_str("\\\""),
+            '<' | '>' | '&' | '=' => write!(&mut escaped, "\\u{:04X}", c as u32).unwrap(),
+            _ if c < ' ' || (!c.is_ascii() && !is_print(c)) => {
+                write!(&mut escaped, "\\u{:04X}", c as u32).unwrap()
+            }
+            _ => escaped.push(c),
+        }
+    }
+    Ok(varc!(escaped))
+}
 
-gn!(
-#[doc="
-Returns the boolean truth of arg1 < arg2
+// Approximates Go's `unicode.IsPrint` for non-ASCII characters: controls, separators,
+// format and private use characters aren't printable. Unassigned code points can't be
+// told apart without the Unicode tables and count as printable.
+fn is_print(c: char) -> bool {
+    if c.is_control() || c.is_whitespace() {
+        return false;
+    }
+    match c as u32 {
+        0xAD
+        | 0x600..=0x605
+        | 0x61C
+        | 0x6DD
+        | 0x70F
+        | 0x890..=0x891
+        | 0x8E2
+        | 0x180E
+        | 0x200B..=0x200F
+        | 0x202A..=0x202E
+        | 0x2060..=0x2064
+        | 0x2066..=0x206F
+        | 0xFEFF
+        | 0xFFF9..=0xFFFB
+        | 0x110BD
+        | 0x110CD
+        | 0x13430..=0x1343F
+        | 0x1BCA0..=0x1BCA3
+        | 0x1D173..=0x1D17A
+        | 0xE0001
+        | 0xE0020..=0xE007F => false,
+        0xE000..=0xF8FF | 0xF0000..=0x10FFFF => false,
+        _ => true,
+    }
+}
 
-# Example
-```
-use gtmpl::template;
-let less_than = template(\"{{ lt 0 . }}\", 1);
-assert_eq!(&less_than.unwrap(), \"true\");
-```
-"]
-lt(a: ref Value, b: ref Value) -> Result<Value, String> {
-    let ret = match cmp(a, b) {
-        None => return Err(format!("unable to compare {} and {}", a, b)),
-        Some(Ordering::Less) => true,
-        _ => false,
-    };
-    Ok(Value::from(ret))
-});
+/// Returns the standard base64 encoding of the textual representation of its
+/// argument.
+///
+/// # Example
+/// ```
+/// use gtmpl::template;
+/// let encoded = template("{{ b64enc . }}", "gtmpl");
+/// assert_eq!(&encoded.unwrap(), "Z3RtcGw=");
+/// ```
+pub fn b64enc(args: &[Arc<Any>]) -> Result<Arc<Any>, String> {
+    let s = single_string("b64enc", args)?;
+    Ok(varc!(base64::encode(&s)))
+}
 
-gn!(
-#[doc="
-Returns the boolean truth of arg1 <= arg2
+/// Returns the string decoded from the standard base64 encoding in its argument.
+/// The decoded bytes must be valid UTF-8.
+///
+/// # Example
+/// ```
+/// use gtmpl::template;
+/// let decoded = template("{{ b64dec . }}", "Z3RtcGw=");
+/// assert_eq!(&decoded.unwrap(), "gtmpl");
+/// ```
+pub fn b64dec(args: &[Arc<Any>]) -> Result<Arc<Any>, String> {
+    let s = single_string("b64dec", args)?;
+    let bytes = base64::decode(&s).map_err(|e| format!("invalid base64: {}", e))?;
+    let decoded =
+        String::from_utf8(bytes).map_err(|e| format!("invalid UTF-8 in base64: {}", e))?;
+    Ok(varc!(decoded))
+}
 
-# Example
-```
-use gtmpl::template;
-let less_or_equal = template(\"{{ le 1.4 . }}\", 1.4);
-assert_eq!(less_or_equal.unwrap(), \"true\");
+/// Returns the textual representation of its argument as a double-quoted string.
+/// Quotes, backslashes and control characters are escaped like Go's `%q`.
+///
+/// # Example
+/// ```
+/// use gtmpl::template;
+/// let quoted = template("{{ quote . }}", "say \"hi\"\n");
+/// assert_eq!(&quoted.unwrap(), r#""say \"hi\"\n""#);
+/// ```
+pub fn quote(args: &[Arc<Any>]) -> Result<Arc<Any>, String> {
+    let s = single_string("quote", args)?;
+    Ok(varc!(quote_str(&s)))
+}
 
-let less_or_equal = template(\"{{ le 0.2 . }}\", 1.4);
-assert_eq!(&less_or_equal.unwrap(), \"true\");
-```
-"]
-le(a: ref Value, b: ref Value) -> Result<Value, String> {
-    let ret = match cmp(a, b) {
-        None => return Err(format!("unable to compare {} and {}", a, b)),
-        Some(Ordering::Less) | Some(Ordering::Equal) => true,
-        _ => false,
-    };
-    Ok(Value::from(ret))
-});
+/// Returns the textual representation of its argument, as it would be printed.
+///
+/// # Example
+/// ```
+/// use gtmpl::template;
+/// let s = template(r#"{{ toString . | printf "%q" }}"#, vec![1, 2]);
+/// assert_eq!(&s.unwrap(), r#""[1 2]""#);
+/// ```
+pub fn to_string(args: &[Arc<Any>]) -> Result<Arc<Any>, String> {
+    let s = single_string("toString", args)?;
+    Ok(varc!(s))
+}
+
+/// Returns the integer parsed from its argument, which must be a string holding a
+/// decimal integer or an integral number. Also available as `atoi`.
+///
+/// # Example
+/// ```
+/// use gtmpl::template;
+/// let int = template("{{ add (int .) 1 }}", "41");
+/// assert_eq!(&int.unwrap(), "42");
+/// ```
+pub fn int(args: &[Arc<Any>]) -> Result<Arc<Any>, String> {
+    let val = single_value("int", args)?;
+    let i = match *val {
+        Value::String(ref s) => s.parse::<i64>()
+            .map(Value::from)
+            .or_else(|_| s.parse::<u64>().map(Value::from))
+            .map_err(|_| format!("unable to parse {:?} as integer", s))?,
+        Value::Number(ref n) if !is_float(n) => val.clone(),
+        _ => return Err(format!("unable to convert {} to integer", to_go_string(val))),
+    };
+    Ok(Arc::new(i))
+}
+
+/// Returns the float parsed from its argument, which must be a string holding a
+/// decimal number or a number.
+///
+/// # Example
+/// ```
+/// use gtmpl::template;
+/// let float = template("{{ mul (float .) 2 }}", "1.25");
+/// assert_eq!(&float.unwrap(), "2.5");
+/// ```
+pub fn float(args: &[Arc<Any>]) -> Result<Arc<Any>, String> {
+    let val = single_value("float", args)?;
+    let f = match *val {
+        Value::String(ref s) => s.parse::<f64>()
+            .ok()
+            .filter(|f| f.is_finite())
+            .ok_or_else(|| format!("unable to parse {:?} as float", s))?,
+        Value::Number(ref n) => n.as_f64()
+            .ok_or_else(|| format!("unable to convert {} to float", n))?,
+        _ => return Err(format!("unable to convert {} to float", to_go_string(val))),
+    };
+    Ok(varc!(f))
+}
+
+/// Returns the textual representation of its argument in upper case.
+///
+/// # Example
+/// ```
+/// use gtmpl::template;
+/// let upper = template("{{ upper . }}", "gtmpl");
+/// assert_eq!(&upper.unwrap(), "GTMPL");
+/// ```
+pub fn upper(args: &[Arc<Any>]) -> Result<Arc<Any>, String> {
+    let s = single_string("upper", args)?;
+    Ok(varc!(s.to_uppercase()))
+}
+
+/// Returns the textual representation of its argument in lower case.
+///
+/// # Example
+/// ```
+/// use gtmpl::template;
+/// let lower = template("{{ lower . }}", "GTMPL");
+/// assert_eq!(&lower.unwrap(), "gtmpl");
+/// ```
+pub fn lower(args: &[Arc<Any>]) -> Result<Arc<Any>, String> {
+    let s = single_string("lower", args)?;
+    Ok(varc!(s.to_lowercase()))
+}
+
+/// Returns the textual representation of its argument with the first letter of each
+/// word in upper case and the rest of the word in lower case. A word is a run of
+/// Unicode letters, digits and `_`, so `"wide-world"` becomes `"Wide-World"`. Unlike
+/// Go's `strings.Title`, already capitalized words are lowercased as well.
+///
+/// # Example
+/// ```
+/// use gtmpl::template;
+/// let title = template("{{ title . }}", "go templates for RUST");
+/// assert_eq!(&title.unwrap(), "Go Templates For Rust");
+/// ```
+pub fn title(args: &[Arc<Any>]) -> Result<Arc<Any>, String> {
+    fn is_word_char(c: char) -> bool {
+        c.is_alphanumeric() || c == '_'
+    }
+    let s = single_string("title", args)?;
+    let mut titled = String::with_capacity(s.len());
+    let mut rest = s.as_str();
+    while let Some(start) = rest.find(is_word_char) {
+        titled.push_str(&rest[..start]);
+        let word = &rest[start..];
+        let end = word.find(|c| !is_word_char(c)).unwrap_or(word.len());
+        let mut chars = word[..end].chars();
+        if let Some(first) = chars.next() {
+            titled.extend(first.to_uppercase());
+            // Lowercasing the whole word keeps context dependent mappings like
+            // the Greek final sigma intact.
+            titled.push_str(&chars.as_str().to_lowercase());
+        }
+        rest = &word[end..];
+    }
+    titled.push_str(rest);
+    Ok(varc!(titled))
+}
+
+/// Returns the textual representation of its argument without leading and trailing
+/// whitespace.
+///
+/// # Example
+/// ```
+/// use gtmpl::template;
+/// let trimmed = template("{{ trim . }}", "  gtmpl\n");
+/// assert_eq!(&trimmed.unwrap(), "gtmpl");
+/// ```
+pub fn trim(args: &[Arc<Any>]) -> Result<Arc<Any>, String> {
+    let s = single_string("trim", args)?;
+    Ok(varc!(s.trim()))
+}
+
+/// Returns the second argument without the first as leading prefix. The second
+/// argument is returned unchanged if it doesn't start with the prefix.
+///
+/// # Example
+/// ```
+/// use gtmpl::template;
+/// let trimmed = template(r#"{{ . | trimPrefix "go-" }}"#, "go-go-gtmpl");
+/// assert_eq!(&trimmed.unwrap(), "go-gtmpl");
+/// ```
+pub fn trim_prefix(args: &[Arc<Any>]) -> Result<Arc<Any>, String> {
+    let s = string_args("trimPrefix", args, 2)?;
+    if s[1].starts_with(s[0].as_str()) {
+        Ok(varc!(&s[1][s[0].len()..]))
+    } else {
+        Ok(varc!(s[1].as_str()))
+    }
+}
+
+/// Returns the second argument without the first as trailing suffix. The second
+/// argument is returned unchanged if it doesn't end with the suffix.
+///
+/// # Example
+/// ```
+/// use gtmpl::template;
+/// let trimmed = template(r#"{{ . | trimSuffix ".tmpl" }}"#, "index.html.tmpl");
+/// assert_eq!(&trimmed.unwrap(), "index.html");
+/// ```
+pub fn trim_suffix(args: &[Arc<Any>]) -> Result<Arc<Any>, String> {
+    let s = string_args("trimSuffix", args, 2)?;
+    if s[1].ends_with(s[0].as_str()) {
+        Ok(varc!(&s[1][..s[1].len() - s[0].len()]))
+    } else {
+        Ok(varc!(s[1].as_str()))
+    }
+}
+
+/// Returns the second argument with all leading and trailing characters contained
+/// in the first removed.
+///
+/// # Example
+/// ```
+/// use gtmpl::template;
+/// let trimmed = template(r#"{{ . | trimAll "$-" }}"#, "-$42.00$-");
+/// assert_eq!(&trimmed.unwrap(), "42.00");
+/// ```
+pub fn trim_all(args: &[Arc<Any>]) -> Result<Arc<Any>, String> {
+    let s = string_args("trimAll", args, 2)?;
+    Ok(varc!(s[1].trim_matches(|c| s[0].contains(c))))
+}
+
+/// Returns the last argument with all occurrences of the first replaced by the second.
+///
+/// # Example
+/// ```
+/// use gtmpl::template;
+/// let replaced = template(r#"{{ . | replace "-" " " }}"#, "go-templates-for-rust");
+/// assert_eq!(&replaced.unwrap(), "go templates for rust");
+/// ```
+pub fn replace(args: &[Arc<Any>]) -> Result<Arc<Any>, String> {
+    let s = string_args("replace", args, 3)?;
+    Ok(varc!(s[2].replace(s[0].as_str(), &s[1])))
+}
+
+/// Returns the second argument repeated as many times as the first argument says.
+/// Results larger than 64 MiB are an error.
+///
+/// # Example
+/// ```
+/// use gtmpl::template;
+/// let repeated = template(r#"{{ . | repeat 3 }}"#, "ab");
+/// assert_eq!(&repeated.unwrap(), "ababab");
+/// ```
+pub fn repeat(args: &[Arc<Any>]) -> Result<Arc<Any>, String> {
+    let (n, s) = count_and_string("repeat", args)?;
+    check_size("repeat", s.len().checked_mul(n))?;
+    Ok(varc!(s.repeat(n)))
+}
+
+/// Returns the second argument with every line indented by as many spaces as the
+/// first argument says. Results larger than 64 MiB are an error.
+///
+/// # Example
+/// ```
+/// use gtmpl::template;
+/// let indented = template(r#"{{ . | indent 2 }}"#, "a:\n  b: c");
+/// assert_eq!(&indented.unwrap(), "  a:\n    b: c");
+/// ```
+pub fn indent(args: &[Arc<Any>]) -> Result<Arc<Any>, String> {
+    let (n, s) = count_and_string("indent", args)?;
+    Ok(varc!(indent_lines("indent", n, &s)?))
+}
+
+/// Like `indent`, but prefixes the result with a newline.
+///
+/// # Example
+/// ```
+/// use gtmpl::template;
+/// let indented = template(r#"spec:{{ . | nindent 2 }}"#, "a: b\nc: d");
+/// assert_eq!(&indented.unwrap(), "spec:\n  a: b\n  c: d");
+/// ```
+pub fn nindent(args: &[Arc<Any>]) -> Result<Arc<Any>, String> {
+    let (n, s) = count_and_string("nindent", args)?;
+    Ok(varc!(format!("\n{}", indent_lines("nindent", n, &s)?)))
+}
+
+// Indents each line, empty ones included, like Sprig does.
+fn indent_lines(name: &str, n: usize, s: &str) -> Result<String, String> {
+    let lines = s.matches('\n').count() + 1;
+    check_size(name, n.checked_mul(lines).and_then(|p| p.checked_add(s.len())))?;
+    let pad = " ".repeat(n);
+    Ok(format!("{}{}", pad, s.replace('\n', &format!("\n{}", pad))))
+}
+
+// Functions generating strings or arrays from a count refuse to allocate more than
+// this many bytes, so a huge count is an error instead of an abort.
+const MAX_GENERATED_SIZE: usize = 64 << 20;
+
+fn check_size(name: &str, size: Option<usize>) -> Result<(), String> {
+    match size {
+        Some(size) if size <= MAX_GENERATED_SIZE => Ok(()),
+        _ => Err(format!("{} result too large", name)),
+    }
+}
+
+fn count_and_string(name: &str, args: &[Arc<Any>]) -> Result<(usize, String), String> {
+    if args.len() != 2 {
+        return Err(format!("{} requires 2 arguments", name));
+    }
+    let unpack = || String::from("unable to downcast");
+    let n = match *args[0].downcast_ref::<Value>().ok_or_else(unpack)? {
+        Value::Number(ref n) => n.as_u64()
+            .filter(|_| !is_float(n))
+            .map(|n| n as usize)
+            .ok_or_else(|| format!("{} requires a non-negative count, got {}", name, n))?,
+        ref val => {
+            return Err(format!("{} requires an integer count, got {}", name, kind_name(val)))
+        }
+    };
+    let s = args[1].downcast_ref::<Value>().ok_or_else(unpack)?;
+    Ok((n, to_go_string(s)))
+}
+
+/// Returns the boolean truth of whether the second argument contains the first.
+///
+/// # Example
+/// ```
+/// use gtmpl::template;
+/// let contains = template(r#"{{ contains "tmpl" . }}"#, "gtmpl");
+/// assert_eq!(&contains.unwrap(), "true");
+/// ```
+pub fn contains(args: &[Arc<Any>]) -> Result<Arc<Any>, String> {
+    let s = string_args("contains", args, 2)?;
+    Ok(varc!(s[1].contains(s[0].as_str())))
+}
+
+/// Returns the boolean truth of whether the second argument starts with the first.
+///
+/// # Example
+/// ```
+/// use gtmpl::template;
+/// let prefixed = template(r#"{{ hasPrefix "gt" . }}"#, "gtmpl");
+/// assert_eq!(&prefixed.unwrap(), "true");
+/// ```
+pub fn has_prefix(args: &[Arc<Any>]) -> Result<Arc<Any>, String> {
+    let s = string_args("hasPrefix", args, 2)?;
+    Ok(varc!(s[1].starts_with(s[0].as_str())))
+}
+
+/// Returns the boolean truth of whether the second argument ends with the first.
+///
+/// # Example
+/// ```
+/// use gtmpl::template;
+/// let suffixed = template(r#"{{ hasSuffix "gt" . }}"#, "gtmpl");
+/// assert_eq!(&suffixed.unwrap(), "false");
+/// ```
+pub fn has_suffix(args: &[Arc<Any>]) -> Result<Arc<Any>, String> {
+    let s = string_args("hasSuffix", args, 2)?;
+    Ok(varc!(s[1].ends_with(s[0].as_str())))
+}
+
+/// Returns the textual representation of the elements of the second argument, which
+/// must be an array, joined by the first argument.
+///
+/// # Example
+/// ```
+/// use gtmpl::template;
+/// let joined = template(r#"{{ . | join ", " }}"#, vec![1, 2, 3]);
+/// assert_eq!(&joined.unwrap(), "1, 2, 3");
+/// ```
+pub fn join(args: &[Arc<Any>]) -> Result<Arc<Any>, String> {
+    if args.len() != 2 {
+        return Err(String::from("join requires 2 arguments"));
+    }
+    let sep = single_string("join", &args[..1])?;
+    let list = args[1]
+        .downcast_ref::<Value>()
+        .ok_or_else(|| String::from("unable to downcast"))?;
+    match *list {
+        Value::Array(ref a) => Ok(varc!(a.iter().map(to_go_string).join(&sep))),
+        ref v => Err(format!("join requires an array, got {}", kind_name(v))),
+    }
+}
+
+/// Returns an array of the substrings of the second argument separated by the first.
+/// An empty separator splits after each character.
+///
+/// # Example
+/// ```
+/// use gtmpl::template;
+/// let split = template(r#"{{ range split "," . }}({{ . }}){{ end }}"#, "a,b,c");
+/// assert_eq!(&split.unwrap(), "(a)(b)(c)");
+/// ```
+pub fn split(args: &[Arc<Any>]) -> Result<Arc<Any>, String> {
+    let s = string_args("split", args, 2)?;
+    let parts: Vec<Value> = if s[0].is_empty() {
+        s[1].chars().map(|c| Value::from(c.to_string())).collect()
+    } else {
+        s[1].split(s[0].as_str()).map(Value::from).collect()
+    };
+    Ok(Arc::new(Value::Array(parts)))
+}
+
+/// Returns the JSON encoding of its argument. Keys of maps and objects are sorted.
+///
+/// # Example
+/// ```
+/// use gtmpl::template;
+/// let json = template("{{ toJson . }}", vec!["a", "b"]);
+/// assert_eq!(&json.unwrap(), r#"["a","b"]"#);
+/// ```
+pub fn to_json(args: &[Arc<Any>]) -> Result<Arc<Any>, String> {
+    let val = single_value("toJson", args)?;
+    Ok(varc!(json::to_json(val, None)?))
+}
+
+/// Returns the JSON encoding of its argument, indented by two spaces. Keys of maps
+/// and objects are sorted.
+///
+/// # Example
+/// ```
+/// use gtmpl::template;
+/// let json = template("{{ toPrettyJson . }}", vec!["a", "b"]);
+/// assert_eq!(&json.unwrap(), "[\n  \"a\",\n  \"b\"\n]");
+/// ```
+pub fn to_pretty_json(args: &[Arc<Any>]) -> Result<Arc<Any>, String> {
+    let val = single_value("toPrettyJson", args)?;
+    Ok(varc!(json::to_json(val, Some("  "))?))
+}
+
+/// Parses its string argument as JSON. Objects are returned as maps.
+///
+/// # Example
+/// ```
+/// use gtmpl::template;
+/// let name = template(r#"{{ with fromJson . }}{{ .name }}{{ end }}"#, r#"{"name": "foo"}"#);
+/// assert_eq!(&name.unwrap(), "foo");
+/// ```
+pub fn from_json(args: &[Arc<Any>]) -> Result<Arc<Any>, String> {
+    let val = single_value("fromJson", args)?;
+    match *val {
+        Value::String(ref s) => Ok(Arc::new(json::from_json(s)?)),
+        _ => Err(String::from("fromJson requires a string argument")),
+    }
+}
+
+fn single_value<'a>(name: &str, args: &'a [Arc<Any>]) -> Result<&'a Value, String> {
+    if args.len() != 1 {
+        return Err(format!("{} requires one argument", name));
+    }
+    args[0]
+        .downcast_ref::<Value>()
+        .ok_or_else(|| String::from("unable to downcast"))
+}
+
+fn string_args(name: &str, args: &[Arc<Any>], n: usize) -> Result<Vec<String>, String> {
+    if args.len() != n {
+        return Err(format!("{} requires {} arguments", name, n));
+    }
+    args.iter()
+        .map(|arg| {
+            arg.downcast_ref::<Value>()
+                .map(to_go_string)
+                .ok_or_else(|| String::from("unable to downcast"))
+        })
+        .collect()
+}
+
+fn single_string(name: &str, args: &[Arc<Any>]) -> Result<String, String> {
+    if args.len() != 1 {
+        return Err(format!("{} requires one argument", name));
+    }
+    let val = args[0]
+        .downcast_ref::<Value>()
+        .ok_or_else(|| String::from("unable to downcast"))?;
+    Ok(to_go_string(val))
+}
+
+/// Returns the sum of its arguments. The result is a float if any argument is a
+/// float, otherwise an integer.
+///
+/// # Example
+/// ```
+/// use gtmpl::template;
+/// let sum = template("{{ add 1 2 . }}", 3);
+/// assert_eq!(&sum.unwrap(), "6");
+/// ```
+pub fn add(args: &[Arc<Any>]) -> Result<Arc<Any>, String> {
+    arith("add", args, i64::checked_add, |a, b| a + b)
+}
+
+/// Returns the difference of its two arguments.
+///
+/// # Example
+/// ```
+/// use gtmpl::template;
+/// let diff = template("{{ sub 10 . }}", 3);
+/// assert_eq!(&diff.unwrap(), "7");
+/// ```
+pub fn sub(args: &[Arc<Any>]) -> Result<Arc<Any>, String> {
+    if args.len() != 2 {
+        return Err(String::from("sub requires 2 arguments"));
+    }
+    arith("sub", args, i64::checked_sub, |a, b| a - b)
+}
+
+/// Returns the product of its arguments. The result is a float if any argument is
+/// a float, otherwise an integer.
+///
+/// # Example
+/// ```
+/// use gtmpl::template;
+/// let product = template("{{ mul 2 2.5 }}", 0);
+/// assert_eq!(&product.unwrap(), "5");
+/// ```
+pub fn mul(args: &[Arc<Any>]) -> Result<Arc<Any>, String> {
+    arith("mul", args, i64::checked_mul, |a, b| a * b)
+}
+
+/// Returns the quotient of its two arguments. Integers use integer division.
+/// Dividing by zero is an error.
+///
+/// # Example
+/// ```
+/// use gtmpl::template;
+/// let quotient = template("{{ div 7 2 }} {{ div 7.5 2 }}", 0);
+/// assert_eq!(&quotient.unwrap(), "3 3.75");
+/// ```
+pub fn div(args: &[Arc<Any>]) -> Result<Arc<Any>, String> {
+    if args.len() != 2 {
+        return Err(String::from("div requires 2 arguments"));
+    }
+    if is_zero(&args[1]) {
+        return Err(String::from("div by zero"));
+    }
+    arith("div", args, i64::checked_div, |a, b| a / b)
+}
+
+/// Returns the remainder of dividing its first argument by its second. Dividing by
+/// zero is an error.
+///
+/// # Example
+/// ```
+/// use gtmpl::template;
+/// let remainder = template("{{ mod 7 . }}", 3);
+/// assert_eq!(&remainder.unwrap(), "1");
+/// ```
+pub fn modulo(args: &[Arc<Any>]) -> Result<Arc<Any>, String> {
+    if args.len() != 2 {
+        return Err(String::from("mod requires 2 arguments"));
+    }
+    if is_zero(&args[1]) {
+        return Err(String::from("mod by zero"));
+    }
+    arith("mod", args, i64::checked_rem, |a, b| a % b)
+}
+
+fn is_zero(arg: &Arc<Any>) -> bool {
+    arg.downcast_ref::<Value>()
+        .map(|val| match *val {
+            Value::Number(ref n) => n.as_f64() == Some(0.0) || n.as_i64() == Some(0),
+            _ => false,
+        })
+        .unwrap_or(false)
+}
+
+fn is_float(n: &Number) -> bool {
+    n.as_i64().is_none() && n.as_u64().is_none()
+}
+
+// Widens an integer, whether stored as `i64` or `u64`, so both compare exactly.
+fn as_int(n: &Number) -> Option<i128> {
+    n.as_i64()
+        .map(i128::from)
+        .or_else(|| n.as_u64().map(i128::from))
+}
+
+// Compares numbers by their value. Integers are compared exactly, even beyond the
+// precision of an `f64`, and an integer is compared with a float without rounding
+// either of them. Only `NaN` is unordered.
+fn cmp_numbers(left: &Number, right: &Number) -> Option<Ordering> {
+    match (as_int(left), as_int(right)) {
+        (Some(l), Some(r)) => Some(l.cmp(&r)),
+        (Some(l), None) => right.as_f64().and_then(|r| cmp_int_float(l, r)),
+        (None, Some(r)) => left.as_f64()
+            .and_then(|l| cmp_int_float(r, l))
+            .map(Ordering::reverse),
+        (None, None) => left.as_f64()
+            .and_then(|l| right.as_f64().and_then(|r| l.partial_cmp(&r))),
+    }
+}
+
+fn cmp_int_float(i: i128, f: f64) -> Option<Ordering> {
+    // Integers are within ±2^64, where an `f64` has no fraction left.
+    const LIMIT: f64 = 18_446_744_073_709_551_616.0;
+    if f.is_nan() {
+        return None;
+    }
+    if f >= LIMIT {
+        return Some(Ordering::Less);
+    }
+    if f <= -LIMIT {
+        return Some(Ordering::Greater);
+    }
+    let t = f.trunc();
+    Some(i.cmp(&(t as i128)).then(if f > t {
+        Ordering::Less
+    } else if f < t {
+        Ordering::Greater
+    } else {
+        Ordering::Equal
+    }))
+}
+
+// Folds the arguments with the integer operation, or the float operation if any
+// argument is a float. Unlike comparisons, integers have to fit an `i64` here.
+fn arith(
+    name: &str,
+    args: &[Arc<Any>],
+    int_op: fn(i64, i64) -> Option<i64>,
+    float_op: fn(f64, f64) -> f64,
+) -> Result<Arc<Any>, String> {
+    if args.len() < 2 {
+        return Err(format!("{} requires at least 2 arguments", name));
+    }
+    let nums = args.iter()
+        .map(|arg| {
+            let val = arg.downcast_ref::<Value>()
+                .ok_or_else(|| String::from("unable to downcast"))?;
+            match *val {
+                Value::Number(ref n) => Ok(n),
+                _ => Err(format!("{} requires numbers", name)),
+            }
+        })
+        .collect::<Result<Vec<&Number>, String>>()?;
+    if nums.iter().any(|n| is_float(n)) {
+        let floats = nums.iter()
+            .map(|n| n.as_f64())
+            .collect::<Option<Vec<f64>>>()
+            .ok_or_else(|| format!("{} result out of range", name))?;
+        let res = floats[1..].iter().fold(floats[0], |acc, &f| float_op(acc, f));
+        return Ok(varc!(res));
+    }
+    let ints = nums.iter()
+        .map(|n| n.as_i64())
+        .collect::<Option<Vec<i64>>>()
+        .ok_or_else(|| format!("{} result out of range", name))?;
+    let mut res = ints[0];
+    for &i in &ints[1..] {
+        res = int_op(res, i).ok_or_else(|| format!("{} result out of range", name))?;
+    }
+    Ok(varc!(res))
+}
+
+#[doc = "
+Returns the boolean truth of arg1 == arg2 [== arg3 ...]
+
+Like in Go, arrays, maps, objects and functions can't be compared and result in an
+error, as do values of different kinds other than `nil`. Numbers are compared by
+value like in `lt` and friends, so `eq 1 1.0` is true no matter how either number
+is stored.
+
+# Example
+```
+use gtmpl::template;
+let equal = template(\"{{ eq 1 1 . }}\", 1);
+assert_eq!(&equal.unwrap(), \"true\");
+```
+"]
+pub fn eq(args: &[Arc<Any>]) -> Result<Arc<Any>, String> {
+    if args.len() < 2 {
+        return Err(String::from("eq requires at least 2 arguments"));
+    }
+    let unpack = || String::from("Arguments need to be of type Value.");
+    let first = args[0].downcast_ref::<Value>().ok_or_else(unpack)?;
+    comparable(first)?;
+    for arg in &args[1..] {
+        let x = arg.downcast_ref::<Value>().ok_or_else(unpack)?;
+        comparable(x)?;
+        if !same_kind(first, x) && !is_nil(first) && !is_nil(x) {
+            return Err(incompatible(first, x));
+        }
+        if !equal(first, x) {
+            return Ok(varc!(false));
+        }
+    }
+    Ok(varc!(true))
+}
+
+gn!(
+#[doc="
+Returns the boolean truth of arg1 != arg2
+
+# Example
+```
+use gtmpl::template;
+let not_equal = template(\"{{ ne 2 . }}\", 1);
+assert_eq!(&not_equal.unwrap(), \"true\");
+```
+"]
+ne(a: ref Value, b: ref Value) -> Result<Value, String> {
+    comparable(a)?;
+    comparable(b)?;
+    let ret = match (a, b) {
+        (&Value::Number(_), &Value::Number(_)) => cmp(a, b) != Some(Ordering::Equal),
+        _ if is_nil(a) || is_nil(b) => !(is_nil(a) && is_nil(b)),
+        _ if same_kind(a, b) => a != b,
+        _ => return Err(incompatible(a, b)),
+    };
+    Ok(Value::from(ret))
+});
+
+gn!(
+#[doc="
+Returns the boolean truth of arg1 < arg2
+
+Numbers are compared by value, booleans with `false` before `true` and strings
+lexicographically by their bytes, like in Go. For UTF-8 this is the order of the
+Unicode code points, so e.g. upper case ASCII letters come before lower case ones.
+Arrays, maps, objects and functions are uncomparable. The same holds for `le`, `gt`
+and `ge`.
+
+# Example
+```
+use gtmpl::template;
+let less_than = template(\"{{ lt 0 . }}\", 1);
+assert_eq!(&less_than.unwrap(), \"true\");
+
+let less_than = template(r#\"{{ lt \"apple\" . }}\"#, \"banana\");
+assert_eq!(&less_than.unwrap(), \"true\");
+```
+"]
+lt(a: ref Value, b: ref Value) -> Result<Value, String> {
+    let ret = match cmp(a, b) {
+        None => return Err(incompatible(a, b)),
+        Some(Ordering::Less) => true,
+        _ => false,
+    };
+    Ok(Value::from(ret))
+});
+
+gn!(
+#[doc="
+Returns the boolean truth of arg1 <= arg2
+
+# Example
+```
+use gtmpl::template;
+let less_or_equal = template(\"{{ le 1.4 . }}\", 1.4);
+assert_eq!(less_or_equal.unwrap(), \"true\");
+
+let less_or_equal = template(\"{{ le 0.2 . }}\", 1.4);
+assert_eq!(&less_or_equal.unwrap(), \"true\");
+```
+"]
+le(a: ref Value, b: ref Value) -> Result<Value, String> {
+    let ret = match cmp(a, b) {
+        None => return Err(incompatible(a, b)),
+        Some(Ordering::Less) | Some(Ordering::Equal) => true,
+        _ => false,
+    };
+    Ok(Value::from(ret))
+});
+
+gn!(
+#[doc="
+Returns the boolean truth of arg1 > arg2
+
+# Example
+```
+use gtmpl::template;
+let greater_than = template(\"{{ gt 1.4 . }}\", 1.2);
+assert_eq!(&greater_than.unwrap(), \"true\");
+```
+"]
+gt(a: ref Value, b: ref Value) -> Result<Value, String> {
+    let ret = match cmp(a, b) {
+        None => return Err(incompatible(a, b)),
+        Some(Ordering::Greater) => true,
+        _ => false,
+    };
+    Ok(Value::from(ret))
+});
+
+gn!(
+#[doc="
+Returns the boolean truth of arg1 >= arg2
+
+# Example
+```
+use gtmpl::template;
+let greater_or_equal = template(\"{{ ge 1.4 1.3 }}\", 1.2);
+assert_eq!(greater_or_equal.unwrap(), \"true\");
+
+let greater_or_equal = template(\"{{ ge 1.4 . }}\", 0.2);
+assert_eq!(&greater_or_equal.unwrap(), \"true\");
+```
+"]
+ge(a: ref Value, b: ref Value) -> Result<Value, String> {
+    let ret = match cmp(a, b) {
+        None => return Err(incompatible(a, b)),
+        Some(Ordering::Greater) | Some(Ordering::Equal) => true,
+        _ => false,
+    };
+    Ok(Value::from(ret))
+});
+
+fn equal(left: &Value, right: &Value) -> bool {
+    match (left, right) {
+        (&Value::Number(ref l), &Value::Number(ref r)) => {
+            cmp_numbers(l, r) == Some(Ordering::Equal)
+        }
+        _ => left == right || (is_nil(left) && is_nil(right)),
+    }
+}
+
+fn is_nil(val: &Value) -> bool {
+    *val == Value::Nil || *val == Value::NoValue
+}
+
+// Arrays, maps, objects and functions can't be checked for equality, as in Go.
+fn comparable(val: &Value) -> Result<(), String> {
+    match *val {
+        Value::Array(_) | Value::Map(_) | Value::Object(_) | Value::Function(_) => {
+            Err(format!("uncomparable type: {}", kind_name(val)))
+        }
+        _ => Ok(()),
+    }
+}
+
+fn same_kind(left: &Value, right: &Value) -> bool {
+    ::std::mem::discriminant(left) == ::std::mem::discriminant(right)
+}
+
+fn incompatible(left: &Value, right: &Value) -> String {
+    if let Err(e) = comparable(left).and_then(|_| comparable(right)) {
+        e
+    } else if same_kind(left, right) {
+        format!("invalid type for comparison: {}", kind_name(left))
+    } else {
+        format!(
+            "incompatible types for comparison: {} and {}",
+            kind_name(left),
+            kind_name(right)
+        )
+    }
+}
+
+fn cmp(left: &Value, right: &Value) -> Option<Ordering> {
+    match (left, right) {
+        (&Value::Number(ref l), &Value::Number(ref r)) => cmp_numbers(l, r),
+        (&Value::Bool(ref l), &Value::Bool(ref r)) => l.partial_cmp(r),
+        (&Value::String(ref l), &Value::String(ref r)) => l.partial_cmp(r),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests_mocked {
+    use super::*;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_eq() {
+        let vals: Vec<Arc<Any>> = vec![varc!("foo".to_owned()), varc!("foo".to_owned())];
+        let ret = eq(&vals).unwrap();
+        let ret_ = ret.downcast_ref::<Value>();
+        assert_eq!(ret_, Some(&Value::Bool(true)));
+        let vals: Vec<Arc<Any>> = vec![varc!(1u32), varc!(1u32), varc!(1i8)];
+        let ret = eq(&vals).unwrap();
+        let ret_ = ret.downcast_ref::<Value>();
+        assert_eq!(ret_, Some(&Value::Bool(true)));
+        let vals: Vec<Arc<Any>> = vec![varc!(false), varc!(false), varc!(false)];
+        let ret = eq(&vals).unwrap();
+        let ret_ = ret.downcast_ref::<Value>();
+        assert_eq!(ret_, Some(&Value::Bool(true)));
+
+        // Like `ne`, values of different kinds are an error rather than unequal.
+        let vals: Vec<Arc<Any>> = vec![varc!("a"), varc!(1)];
+        let err = String::from("incompatible types for comparison: string and number");
+        assert_eq!(eq(&vals).err(), Some(err.clone()));
+        assert_eq!(ne(&vals).err(), Some(err));
+        let vals: Vec<Arc<Any>> = vec![varc!(1), varc!(1), varc!(true)];
+        assert!(eq(&vals).is_err());
+
+        let vals: Vec<Arc<Any>> = vec![varc!("a"), Arc::new(Value::Nil)];
+        let ret = eq(&vals).unwrap();
+        let ret_ = ret.downcast_ref::<Value>();
+        assert_eq!(ret_, Some(&Value::Bool(false)));
+    }
+
+    #[test]
+    fn test_eq_uncomparable() {
+        let vals: Vec<Arc<Any>> = vec![varc!(vec![1, 2]), varc!(vec![1, 2])];
+        assert_eq!(
+            eq(&vals).err(),
+            Some(String::from("uncomparable type: array"))
+        );
+        let vals: Vec<Arc<Any>> = vec![varc!(1), varc!(vec![1])];
+        assert!(eq(&vals).is_err());
+        let vals: Vec<Arc<Any>> = vec![varc!(vec![1]), varc!(1)];
+        assert!(eq(&vals).is_err());
+
+        let mut m = HashMap::new();
+        m.insert("a".to_owned(), 1);
+        let vals: Vec<Arc<Any>> = vec![varc!(m.clone()), varc!(m.clone())];
+        assert_eq!(eq(&vals).err(), Some(String::from("uncomparable type: map")));
+        let vals: Vec<Arc<Any>> = vec![varc!(m.clone()), varc!(m)];
+        assert!(ne(&vals).is_err());
+
+        let vals: Vec<Arc<Any>> = vec![varc!(vec![1, 2]), varc!(vec![1, 3])];
+        assert!(ne(&vals).is_err());
+    }
+
+    #[test]
+    fn test_and() {
+        let vals: Vec<Arc<Any>> = vec![varc!(0i32), varc!(1u8)];
+        let ret = and(&vals).unwrap();
+        let ret_ = ret.downcast_ref::<Value>();
+        assert_eq!(ret_, Some(&Value::from(0i32)));
+
+        let vals: Vec<Arc<Any>> = vec![varc!(1i32), varc!(2u8)];
+        let ret = and(&vals).unwrap();
+        let ret_ = ret.downcast_ref::<Value>();
+        assert_eq!(ret_, Some(&Value::from(2u8)));
+    }
+
+    #[test]
+    fn test_or() {
+        let vals: Vec<Arc<Any>> = vec![varc!(0i32), varc!(1u8)];
+        let ret = or(&vals).unwrap();
+        let ret_ = ret.downcast_ref::<Value>();
+        assert_eq!(ret_, Some(&Value::from(1u8)));
+
+        let vals: Vec<Arc<Any>> = vec![varc!(0i32), varc!(0u8)];
+        let ret = or(&vals).unwrap();
+        let ret_ = ret.downcast_ref::<Value>();
+        assert_eq!(ret_, Some(&Value::from(0u8)));
+    }
+
+    #[test]
+    fn test_default() {
+        let present: Vec<Arc<Any>> = vec![varc!("anon"), varc!("foo")];
+        let empty: Vec<Arc<Any>> = vec![
+            Arc::new(Value::Nil),
+            Arc::new(Value::NoValue),
+            varc!(""),
+            varc!(0u8),
+            varc!(false),
+        ];
+        let ret = default(&present).unwrap();
+        let ret_ = ret.downcast_ref::<Value>();
+        assert_eq!(ret_, Some(&Value::from("foo")));
+        for e in empty {
+            let vals: Vec<Arc<Any>> = vec![varc!("anon"), e];
+            let ret = default(&vals).unwrap();
+            let ret_ = ret.downcast_ref::<Value>();
+            assert_eq!(ret_, Some(&Value::from("anon")));
+        }
+
+        let vals: Vec<Arc<Any>> = vec![varc!("anon")];
+        let ret = default(&vals).unwrap();
+        let ret_ = ret.downcast_ref::<Value>();
+        assert_eq!(ret_, Some(&Value::from("anon")));
+
+        let vals: Vec<Arc<Any>> = vec![];
+        assert!(default(&vals).is_err());
+    }
+
+    #[test]
+    fn test_empty() {
+        let empty_vals: Vec<Arc<Any>> = vec![
+            Arc::new(Value::Nil),
+            Arc::new(Value::NoValue),
+            varc!(""),
+            varc!(0u8),
+            varc!(0i64),
+            varc!(0.0),
+            varc!(false),
+            varc!(Vec::<u8>::new()),
+            varc!(HashMap::<String, u8>::new()),
+        ];
+        for e in empty_vals {
+            let ret = empty(&[e]).unwrap();
+            let ret_ = ret.downcast_ref::<Value>();
+            assert_eq!(ret_, Some(&Value::from(true)));
+        }
+
+        let present: Vec<Arc<Any>> = vec![varc!("foo"), varc!(1u8), varc!(-1), varc!(vec![0])];
+        for p in present {
+            let ret = empty(&[p]).unwrap();
+            let ret_ = ret.downcast_ref::<Value>();
+            assert_eq!(ret_, Some(&Value::from(false)));
+        }
+
+        let vals: Vec<Arc<Any>> = vec![];
+        assert!(empty(&vals).is_err());
+    }
+
+    #[test]
+    fn test_coalesce() {
+        let vals: Vec<Arc<Any>> = vec![
+            Arc::new(Value::NoValue),
+            varc!(""),
+            varc!(Vec::<u8>::new()),
+            varc!("foo"),
+            varc!("bar"),
+        ];
+        let ret = coalesce(&vals).unwrap();
+        let ret_ = ret.downcast_ref::<Value>();
+        assert_eq!(ret_, Some(&Value::from("foo")));
+
+        let vals: Vec<Arc<Any>> = vec![varc!(0u8), varc!(false)];
+        let ret = coalesce(&vals).unwrap();
+        let ret_ = ret.downcast_ref::<Value>();
+        assert_eq!(ret_, Some(&Value::Nil));
+
+        let vals: Vec<Arc<Any>> = vec![];
+        let ret = coalesce(&vals).unwrap();
+        let ret_ = ret.downcast_ref::<Value>();
+        assert_eq!(ret_, Some(&Value::Nil));
+    }
+
+    #[test]
+    fn test_ternary() {
+        let vals: Vec<Arc<Any>> = vec![varc!("yes"), varc!("no"), varc!(true)];
+        let ret = ternary(&vals).unwrap();
+        let ret_ = ret.downcast_ref::<Value>();
+        assert_eq!(ret_, Some(&Value::from("yes")));
+
+        let vals: Vec<Arc<Any>> = vec![varc!("yes"), varc!("no"), varc!(false)];
+        let ret = ternary(&vals).unwrap();
+        let ret_ = ret.downcast_ref::<Value>();
+        assert_eq!(ret_, Some(&Value::from("no")));
+
+        let vals: Vec<Arc<Any>> = vec![varc!(1), varc!(2), varc!("")];
+        let ret = ternary(&vals).unwrap();
+        let ret_ = ret.downcast_ref::<Value>();
+        assert_eq!(ret_, Some(&Value::from(2)));
+
+        let vals: Vec<Arc<Any>> = vec![varc!(1), varc!(2), Arc::new(Value::NoValue)];
+        let ret = ternary(&vals).unwrap();
+        let ret_ = ret.downcast_ref::<Value>();
+        assert_eq!(ret_, Some(&Value::from(2)));
+
+        let vals: Vec<Arc<Any>> = vec![varc!(1), varc!(2), varc!(vec![0])];
+        let ret = ternary(&vals).unwrap();
+        let ret_ = ret.downcast_ref::<Value>();
+        assert_eq!(ret_, Some(&Value::from(1)));
+
+        let vals: Vec<Arc<Any>> = vec![varc!(1), varc!(true)];
+        assert!(ternary(&vals).is_err());
+    }
+
+    #[test]
+    fn test_int() {
+        let vals: Vec<Arc<Any>> = vec![varc!("42")];
+        let ret = int(&vals).unwrap();
+        let ret_ = ret.downcast_ref::<Value>();
+        assert_eq!(ret_, Some(&Value::from(42)));
+
+        let vals: Vec<Arc<Any>> = vec![varc!("-7")];
+        let ret = int(&vals).unwrap();
+        let ret_ = ret.downcast_ref::<Value>();
+        assert_eq!(ret_, Some(&Value::from(-7)));
+
+        let vals: Vec<Arc<Any>> = vec![varc!("18446744073709551615")];
+        let ret = int(&vals).unwrap();
+        let ret_ = ret.downcast_ref::<Value>();
+        assert_eq!(ret_, Some(&Value::from(u64::max_value())));
+
+        let vals: Vec<Arc<Any>> = vec![varc!(23)];
+        let ret = int(&vals).unwrap();
+        let ret_ = ret.downcast_ref::<Value>();
+        assert_eq!(ret_, Some(&Value::from(23)));
+
+        let vals: Vec<Arc<Any>> = vec![varc!("abc")];
+        assert_eq!(
+            int(&vals).unwrap_err(),
+            r#"unable to parse "abc" as integer"#
+        );
+
+        let vals: Vec<Arc<Any>> = vec![
+            varc!("3.14"),
+            varc!(" 1"),
+            varc!(""),
+            varc!(1.5),
+            varc!(true),
+        ];
+        for v in vals {
+            assert!(int(&[v]).is_err());
+        }
+    }
+
+    #[test]
+    fn test_float() {
+        let vals: Vec<Arc<Any>> = vec![varc!("2.75")];
+        let ret = float(&vals).unwrap();
+        let ret_ = ret.downcast_ref::<Value>();
+        assert_eq!(ret_, Some(&Value::from(2.75)));
+
+        let vals: Vec<Arc<Any>> = vec![varc!("42")];
+        let ret = float(&vals).unwrap();
+        let ret_ = ret.downcast_ref::<Value>();
+        assert_eq!(ret_, Some(&Value::from(42.0)));
+
+        let vals: Vec<Arc<Any>> = vec![varc!("-3.14")];
+        let ret = float(&vals).unwrap();
+        let ret_ = ret.downcast_ref::<Value>();
+        assert_eq!(ret_.map(to_go_string), Some(String::from("-3.14")));
+
+        let vals: Vec<Arc<Any>> = vec![varc!(2)];
+        let ret = float(&vals).unwrap();
+        let ret_ = ret.downcast_ref::<Value>();
+        assert_eq!(ret_, Some(&Value::from(2.0)));
+
+        let vals: Vec<Arc<Any>> = vec![varc!("abc")];
+        assert_eq!(float(&vals).unwrap_err(), r#"unable to parse "abc" as float"#);
+
+        let vals: Vec<Arc<Any>> = vec![varc!("NaN"), varc!("inf"), varc!(""), varc!(vec![1])];
+        for v in vals {
+            assert!(float(&[v]).is_err());
+        }
+    }
+
+    #[test]
+    fn test_ne() {
+        let vals: Vec<Arc<Any>> = vec![varc!(0i32), varc!(1u8)];
+        let ret = ne(&vals).unwrap();
+        let ret_ = ret.downcast_ref::<Value>();
+        assert_eq!(ret_, Some(&Value::from(true)));
+
+        let vals: Vec<Arc<Any>> = vec![varc!(0i32), varc!(0u8)];
+        let ret = ne(&vals).unwrap();
+        let ret_ = ret.downcast_ref::<Value>();
+        assert_eq!(ret_, Some(&Value::from(false)));
+
+        let vals: Vec<Arc<Any>> = vec![varc!("foo"), varc!("bar")];
+        let ret = ne(&vals).unwrap();
+        let ret_ = ret.downcast_ref::<Value>();
+        assert_eq!(ret_, Some(&Value::from(true)));
+
+        let vals: Vec<Arc<Any>> = vec![varc!("foo"), varc!("foo")];
+        let ret = ne(&vals).unwrap();
+        let ret_ = ret.downcast_ref::<Value>();
+        assert_eq!(ret_, Some(&Value::from(false)));
+
+        let vals: Vec<Arc<Any>> = vec![varc!(1i32), varc!(1.5f64)];
+        let ret = ne(&vals).unwrap();
+        let ret_ = ret.downcast_ref::<Value>();
+        assert_eq!(ret_, Some(&Value::from(true)));
+
+        let vals: Vec<Arc<Any>> = vec![varc!("foo"), varc!(1u8)];
+        assert!(ne(&vals).is_err());
+    }
+
+    #[test]
+    fn test_lt() {
+        let vals: Vec<Arc<Any>> = vec![varc!(-1i32), varc!(1u8)];
+        let ret = lt(&vals).unwrap();
+        let ret_ = ret.downcast_ref::<Value>();
+        assert_eq!(ret_, Some(&Value::from(true)));
+
+        let vals: Vec<Arc<Any>> = vec![varc!(0i32), varc!(0u8)];
+        let ret = lt(&vals).unwrap();
+        let ret_ = ret.downcast_ref::<Value>();
+        assert_eq!(ret_, Some(&Value::from(false)));
+
+        let vals: Vec<Arc<Any>> = vec![varc!(1i32), varc!(0u8)];
+        let ret = lt(&vals).unwrap();
+        let ret_ = ret.downcast_ref::<Value>();
+        assert_eq!(ret_, Some(&Value::from(false)));
+    }
+
+    #[test]
+    fn test_cmp_mixed_numbers() {
+        let vals: Vec<Arc<Any>> = vec![varc!(1u8), varc!(1.0f64)];
+        let ret = eq(&vals).unwrap();
+        let ret_ = ret.downcast_ref::<Value>();
+        assert_eq!(ret_, Some(&Value::from(true)));
+
+        let vals: Vec<Arc<Any>> = vec![varc!(1u8), varc!(1.5f64)];
+        let ret = lt(&vals).unwrap();
+        let ret_ = ret.downcast_ref::<Value>();
+        assert_eq!(ret_, Some(&Value::from(true)));
+        let ret = eq(&vals).unwrap();
+        let ret_ = ret.downcast_ref::<Value>();
+        assert_eq!(ret_, Some(&Value::from(false)));
+
+        // Beyond 2^53 integers can't be converted to floats exactly.
+        let big = (1u64 << 53) + 1;
+        let vals: Vec<Arc<Any>> = vec![varc!(big), varc!(1.5f64)];
+        let ret = gt(&vals).unwrap();
+        let ret_ = ret.downcast_ref::<Value>();
+        assert_eq!(ret_, Some(&Value::from(true)));
+
+        let vals: Vec<Arc<Any>> = vec![varc!(big), varc!(big - 1)];
+        let ret = gt(&vals).unwrap();
+        let ret_ = ret.downcast_ref::<Value>();
+        assert_eq!(ret_, Some(&Value::from(true)));
+        let ret = ne(&vals).unwrap();
+        let ret_ = ret.downcast_ref::<Value>();
+        assert_eq!(ret_, Some(&Value::from(true)));
+
+        let vals: Vec<Arc<Any>> = vec![varc!(::std::i64::MIN), varc!(::std::u64::MAX)];
+        let ret = lt(&vals).unwrap();
+        let ret_ = ret.downcast_ref::<Value>();
+        assert_eq!(ret_, Some(&Value::from(true)));
+        let ret = eq(&vals).unwrap();
+        let ret_ = ret.downcast_ref::<Value>();
+        assert_eq!(ret_, Some(&Value::from(false)));
+
+        let vals: Vec<Arc<Any>> = vec![varc!(::std::u64::MAX), varc!(::std::f64::INFINITY)];
+        let ret = lt(&vals).unwrap();
+        let ret_ = ret.downcast_ref::<Value>();
+        assert_eq!(ret_, Some(&Value::from(true)));
+
+        let vals: Vec<Arc<Any>> = vec![varc!(1u8), varc!(::std::f64::NAN)];
+        assert!(lt(&vals).is_err());
+        let ret = eq(&vals).unwrap();
+        let ret_ = ret.downcast_ref::<Value>();
+        assert_eq!(ret_, Some(&Value::from(false)));
+    }
+
+    #[test]
+    fn test_cmp_negative_floats() {
+        let cases = vec![
+            (Value::from(-1.5f64), Value::from(-1i64), Ordering::Less),
+            (Value::from(-2.5f64), Value::from(-2i64), Ordering::Less),
+            (Value::from(-2i64), Value::from(-2.5f64), Ordering::Greater),
+            (Value::from(-0.5f64), Value::from(-1i64), Ordering::Greater),
+            (Value::from(-0.5f64), Value::from(0u8), Ordering::Less),
+            (Value::from(-0.5f64), Value::from(0.5f64), Ordering::Less),
+            (Value::from(-1.5f64), Value::from(-1.25f64), Ordering::Less),
+            (Value::from(-1.5f64), Value::from(-1.5f64), Ordering::Equal),
+            (Value::from(-3.0f64), Value::from(-3i64), Ordering::Equal),
+        ];
+        for (a, b, ord) in cases {
+            let vals: Vec<Arc<Any>> = vec![Arc::new(a), Arc::new(b)];
+            for &(f, want) in &[
+                (eq as Func, ord == Ordering::Equal),
+                (ne as Func, ord != Ordering::Equal),
+                (lt as Func, ord == Ordering::Less),
+                (le as Func, ord != Ordering::Greater),
+                (gt as Func, ord == Ordering::Greater),
+                (ge as Func, ord != Ordering::Less),
+            ] {
+                let ret = f(&vals).unwrap();
+                let ret_ = ret.downcast_ref::<Value>();
+                assert_eq!(ret_, Some(&Value::from(want)), "{:?}", ord);
+            }
+        }
+    }
+
+    #[test]
+    fn test_cmp_strings() {
+        let cases = vec![
+            ("apple", "banana", Ordering::Less),
+            ("apple", "apple", Ordering::Equal),
+            ("apple", "app", Ordering::Greater),
+            ("", "a", Ordering::Less),
+            ("Zebra", "apple", Ordering::Less),
+            ("apple", "Apple", Ordering::Greater),
+            ("é", "z", Ordering::Greater),
+            ("€", "😀", Ordering::Less),
+        ];
+        for (a, b, ord) in cases {
+            let vals: Vec<Arc<Any>> = vec![varc!(a), varc!(b)];
+            for &(f, want) in &[
+                (lt as Func, ord == Ordering::Less),
+                (le as Func, ord != Ordering::Greater),
+                (gt as Func, ord == Ordering::Greater),
+                (ge as Func, ord != Ordering::Less),
+            ] {
+                let ret = f(&vals).unwrap();
+                let ret_ = ret.downcast_ref::<Value>();
+                assert_eq!(ret_, Some(&Value::from(want)), "{} {}", a, b);
+            }
+        }
+
+        let vals: Vec<Arc<Any>> = vec![varc!("1"), varc!(1)];
+        assert_eq!(
+            lt(&vals).err(),
+            Some(String::from("incompatible types for comparison: string and number"))
+        );
+        let vals: Vec<Arc<Any>> = vec![Arc::new(Value::Nil), Arc::new(Value::Nil)];
+        assert_eq!(
+            lt(&vals).err(),
+            Some(String::from("invalid type for comparison: nil"))
+        );
+        let vals: Vec<Arc<Any>> = vec![varc!(vec![1]), varc!(vec![1, 2])];
+        for f in &[lt as Func, le, gt, ge] {
+            assert_eq!(
+                f(&vals).err(),
+                Some(String::from("uncomparable type: array"))
+            );
+        }
+    }
+
+    #[test]
+    fn test_le() {
+        let vals: Vec<Arc<Any>> = vec![varc!(-1i32), varc!(1u8)];
+        let ret = le(&vals).unwrap();
+        let ret_ = ret.downcast_ref::<Value>();
+        assert_eq!(ret_, Some(&Value::from(true)));
+
+        let vals: Vec<Arc<Any>> = vec![varc!(0i32), varc!(0u8)];
+        let ret = le(&vals).unwrap();
+        let ret_ = ret.downcast_ref::<Value>();
+        assert_eq!(ret_, Some(&Value::from(true)));
+
+        let vals: Vec<Arc<Any>> = vec![varc!(1i32), varc!(0u8)];
+        let ret = le(&vals).unwrap();
+        let ret_ = ret.downcast_ref::<Value>();
+        assert_eq!(ret_, Some(&Value::from(false)));
+    }
+
+    #[test]
+    fn test_gt() {
+        let vals: Vec<Arc<Any>> = vec![varc!(-1i32), varc!(1u8)];
+        let ret = gt(&vals).unwrap();
+        let ret_ = ret.downcast_ref::<Value>();
+        assert_eq!(ret_, Some(&Value::from(false)));
+
+        let vals: Vec<Arc<Any>> = vec![varc!(0i32), varc!(0u8)];
+        let ret = gt(&vals).unwrap();
+        let ret_ = ret.downcast_ref::<Value>();
+        assert_eq!(ret_, Some(&Value::from(false)));
+
+        let vals: Vec<Arc<Any>> = vec![varc!(1i32), varc!(0u8)];
+        let ret = gt(&vals).unwrap();
+        let ret_ = ret.downcast_ref::<Value>();
+        assert_eq!(ret_, Some(&Value::from(true)));
+    }
+
+    #[test]
+    fn test_ge() {
+        let vals: Vec<Arc<Any>> = vec![varc!(-1i32), varc!(1u8)];
+        let ret = ge(&vals).unwrap();
+        let ret_ = ret.downcast_ref::<Value>();
+        assert_eq!(ret_, Some(&Value::from(false)));
+
+        let vals: Vec<Arc<Any>> = vec![varc!(0i32), varc!(0u8)];
+        let ret = ge(&vals).unwrap();
+        let ret_ = ret.downcast_ref::<Value>();
+        assert_eq!(ret_, Some(&Value::from(true)));
+
+        let vals: Vec<Arc<Any>> = vec![varc!(1i32), varc!(0u8)];
+        let ret = ge(&vals).unwrap();
+        let ret_ = ret.downcast_ref::<Value>();
+        assert_eq!(ret_, Some(&Value::from(true)));
+    }
+
+    #[test]
+    fn test_print() {
+        let vals: Vec<Arc<Any>> = vec![varc!("foo"), varc!(1u8)];
+        let ret = print(&vals).unwrap();
+        let ret_ = ret.downcast_ref::<Value>();
+        assert_eq!(ret_, Some(&Value::from("foo1")));
+
+        let vals: Vec<Arc<Any>> = vec![varc!("foo"), varc!(1u8), varc!(2)];
+        let ret = print(&vals).unwrap();
+        let ret_ = ret.downcast_ref::<Value>();
+        assert_eq!(ret_, Some(&Value::from("foo1 2")));
+
+        let vals: Vec<Arc<Any>> = vec![varc!(true), varc!(1), varc!("foo"), varc!(2)];
+        let ret = print(&vals).unwrap();
+        let ret_ = ret.downcast_ref::<Value>();
+        assert_eq!(ret_, Some(&Value::from("true 1foo2")));
+
+        let vals: Vec<Arc<Any>> = vec![varc!(1), varc!(2), varc!(3)];
+        let ret = print(&vals).unwrap();
+        let ret_ = ret.downcast_ref::<Value>();
+        assert_eq!(ret_, Some(&Value::from("1 2 3")));
+    }
+
+    #[test]
+    fn test_println() {
+        let vals: Vec<Arc<Any>> = vec![varc!("foo"), varc!(1u8)];
+        let ret = println(&vals).unwrap();
+        let ret_ = ret.downcast_ref::<Value>();
+        assert_eq!(ret_, Some(&Value::from("foo 1\n")));
+
+        let vals: Vec<Arc<Any>> = vec![];
+        let ret = println(&vals).unwrap();
+        let ret_ = ret.downcast_ref::<Value>();
+        assert_eq!(ret_, Some(&Value::from("\n")));
+
+        let vals: Vec<Arc<Any>> = vec![varc!(1), varc!(2), varc!("foo")];
+        let ret = println(&vals).unwrap();
+        let ret_ = ret.downcast_ref::<Value>();
+        assert_eq!(ret_, Some(&Value::from("1 2 foo\n")));
+    }
+
+    #[test]
+    fn test_printf() {
+        let vals: Vec<Arc<Any>> = vec![varc!("%d items at %s"), varc!(23), varc!("foo")];
+        let ret = printf(&vals).unwrap();
+        let ret_ = ret.downcast_ref::<Value>();
+        assert_eq!(ret_, Some(&Value::from("23 items at foo")));
+
+        let vals: Vec<Arc<Any>> = vec![varc!("%d"), varc!("foo")];
+        let ret = printf(&vals);
+        assert!(ret.is_err());
+
+        let vals: Vec<Arc<Any>> = vec![varc!(1)];
+        let ret = printf(&vals);
+        assert!(ret.is_err());
+
+        let mut o = HashMap::new();
+        o.insert("b".to_owned(), vec![1, 2]);
+        o.insert("a".to_owned(), vec![]);
+        let vals: Vec<Arc<Any>> = vec![varc!("%v %v"), varc!(o), varc!(vec![vec![1], vec![2]])];
+        let ret = printf(&vals).unwrap();
+        let ret_ = ret.downcast_ref::<Value>();
+        assert_eq!(ret_, Some(&Value::from("map[a:[] b:[1 2]] [[1] [2]]")));
+    }
+
+    #[test]
+    fn test_index() {
+        let vals: Vec<Arc<Any>> = vec![varc!(vec![vec![1, 2], vec![3, 4]]), varc!(1), varc!(0)];
+        let ret = index(&vals).unwrap();
+        let ret_ = ret.downcast_ref::<Value>();
+        assert_eq!(ret_, Some(&Value::from(3)));
+
+        let mut o = HashMap::new();
+        o.insert(String::from("foo"), vec![String::from("bar")]);
+        let col = Arc::new(Value::from(o));
+        let vals: Vec<Arc<Any>> = vec![col, varc!("foo"), varc!(0)];
+        let ret = index(&vals).unwrap();
+        let ret_ = ret.downcast_ref::<Value>();
+        assert_eq!(ret_, Some(&Value::from("bar")));
+
+        let mut o = HashMap::new();
+        o.insert(String::from("foo"), String::from("bar"));
+        let col = Arc::new(Value::from(o));
+        let vals: Vec<Arc<Any>> = vec![col, varc!("foo2")];
+        let ret = index(&vals).unwrap();
+        let ret_ = ret.downcast_ref::<Value>();
+        assert_eq!(ret_, Some(&Value::NoValue));
+
+        let vals: Vec<Arc<Any>> = vec![varc!(vec![1, 2]), varc!(-1)];
+        let ret = index(&vals);
+        assert_eq!(ret.err(), Some(String::from("index out of range: -1")));
+
+        let vals: Vec<Arc<Any>> = vec![varc!(vec![1, 2]), varc!(2)];
+        let ret = index(&vals);
+        assert_eq!(ret.err(), Some(String::from("index out of range: 2")));
+    }
+
+    #[test]
+    fn test_list() {
+        let vals: Vec<Arc<Any>> = vec![varc!(1), varc!("foo"), varc!(vec![2])];
+        let ret = list(&vals).unwrap();
+        let ret_ = ret.downcast_ref::<Value>();
+        assert_eq!(
+            ret_,
+            Some(&Value::Array(vec![
+                Value::from(1),
+                Value::from("foo"),
+                Value::from(vec![2]),
+            ]))
+        );
+
+        let vals: Vec<Arc<Any>> = vec![];
+        let ret = list(&vals).unwrap();
+        let ret_ = ret.downcast_ref::<Value>();
+        assert_eq!(ret_, Some(&Value::Array(vec![])));
+    }
+
+    #[test]
+    fn test_until() {
+        let vals: Vec<Arc<Any>> = vec![varc!(3)];
+        let ret = until(&vals).unwrap();
+        let ret_ = ret.downcast_ref::<Value>();
+        assert_eq!(ret_, Some(&Value::from(vec![0, 1, 2])));
+
+        let vals: Vec<Arc<Any>> = vec![varc!(-2)];
+        let ret = until(&vals).unwrap();
+        let ret_ = ret.downcast_ref::<Value>();
+        assert_eq!(ret_, Some(&Value::from(vec![0, -1])));
+
+        let vals: Vec<Arc<Any>> = vec![varc!(0)];
+        let ret = until(&vals).unwrap();
+        let ret_ = ret.downcast_ref::<Value>();
+        assert_eq!(ret_, Some(&Value::Array(vec![])));
+
+        let vals: Vec<Arc<Any>> = vec![varc!(1.5)];
+        assert_eq!(
+            until(&vals).unwrap_err(),
+            "until requires integer arguments, got 1.5"
+        );
+
+        let vals: Vec<Arc<Any>> = vec![varc!("3")];
+        assert_eq!(
+            until(&vals).unwrap_err(),
+            "until requires integer arguments, got string"
+        );
+
+        let vals: Vec<Arc<Any>> = vec![varc!(i64::max_value())];
+        assert_eq!(until(&vals).unwrap_err(), "until result too large");
+
+        let vals: Vec<Arc<Any>> = vec![varc!(i64::min_value())];
+        assert_eq!(until(&vals).unwrap_err(), "until result too large");
+    }
+
+    #[test]
+    fn test_until_step() {
+        let vals: Vec<Arc<Any>> = vec![varc!(0), varc!(10), varc!(2)];
+        let ret = until_step(&vals).unwrap();
+        let ret_ = ret.downcast_ref::<Value>();
+        assert_eq!(ret_, Some(&Value::from(vec![0, 2, 4, 6, 8])));
+
+        let vals: Vec<Arc<Any>> = vec![varc!(5), varc!(-1), varc!(-3)];
+        let ret = until_step(&vals).unwrap();
+        let ret_ = ret.downcast_ref::<Value>();
+        assert_eq!(ret_, Some(&Value::from(vec![5, 2])));
+
+        let vals: Vec<Arc<Any>> = vec![varc!(0), varc!(10), varc!(-1)];
+        let ret = until_step(&vals).unwrap();
+        let ret_ = ret.downcast_ref::<Value>();
+        assert_eq!(ret_, Some(&Value::Array(vec![])));
+
+        let vals: Vec<Arc<Any>> = vec![varc!(0), varc!(10), varc!(0)];
+        let ret = until_step(&vals).unwrap();
+        let ret_ = ret.downcast_ref::<Value>();
+        assert_eq!(ret_, Some(&Value::Array(vec![])));
+
+        let max = i64::max_value();
+        let vals: Vec<Arc<Any>> = vec![varc!(max - 1), varc!(max), varc!(max)];
+        let ret = until_step(&vals).unwrap();
+        let ret_ = ret.downcast_ref::<Value>();
+        assert_eq!(ret_, Some(&Value::from(vec![max - 1])));
+
+        let min = i64::min_value();
+        let vals: Vec<Arc<Any>> = vec![varc!(min), varc!(max), varc!(max)];
+        let ret = until_step(&vals).unwrap();
+        let ret_ = ret.downcast_ref::<Value>();
+        assert_eq!(ret_, Some(&Value::from(vec![min, -1, max - 1])));
+
+        let vals: Vec<Arc<Any>> = vec![varc!(min), varc!(max), varc!(1)];
+        assert_eq!(until_step(&vals).unwrap_err(), "untilStep result too large");
+
+        let vals: Vec<Arc<Any>> = vec![varc!(0), varc!(10)];
+        assert!(until_step(&vals).is_err());
+    }
+
+    #[test]
+    fn test_tpl() {
+        let vals: Vec<Arc<Any>> = vec![varc!("{{ . | upper }} {{ len . }}"), varc!("foo")];
+        let ret = tpl(&vals).unwrap();
+        let ret_ = ret.downcast_ref::<Value>();
+        assert_eq!(ret_, Some(&Value::from("FOO 3")));
+
+        let vals: Vec<Arc<Any>> = vec![varc!("{{ .foo "), varc!(1)];
+        assert!(tpl(&vals).is_err());
+
+        let vals: Vec<Arc<Any>> = vec![varc!("{{ . }}")];
+        assert!(tpl(&vals).is_err());
+    }
+
+    #[test]
+    fn test_reverse() {
+        let vals: Vec<Arc<Any>> = vec![varc!(vec![1, 2, 3])];
+        let ret = reverse(&vals).unwrap();
+        let ret_ = ret.downcast_ref::<Value>();
+        assert_eq!(ret_, Some(&Value::from(vec![3, 2, 1])));
+        let orig = vals[0].downcast_ref::<Value>();
+        assert_eq!(orig, Some(&Value::from(vec![1, 2, 3])));
+
+        let vals: Vec<Arc<Any>> = vec![varc!("äöü€")];
+        let ret = reverse(&vals).unwrap();
+        let ret_ = ret.downcast_ref::<Value>();
+        assert_eq!(ret_, Some(&Value::from("€üöä")));
+
+        let vals: Vec<Arc<Any>> = vec![varc!(Vec::<u8>::new())];
+        let ret = reverse(&vals).unwrap();
+        let ret_ = ret.downcast_ref::<Value>();
+        assert_eq!(ret_, Some(&Value::Array(vec![])));
+
+        let vals: Vec<Arc<Any>> = vec![varc!(1)];
+        assert_eq!(
+            reverse(&vals).err(),
+            Some(String::from("can't reverse item of type number"))
+        );
+    }
+
+    #[test]
+    fn test_first_last_rest() {
+        let vals: Vec<Arc<Any>> = vec![varc!(vec![1, 2, 3])];
+        let ret = first(&vals).unwrap();
+        let ret_ = ret.downcast_ref::<Value>();
+        assert_eq!(ret_, Some(&Value::from(1)));
+
+        let ret = last(&vals).unwrap();
+        let ret_ = ret.downcast_ref::<Value>();
+        assert_eq!(ret_, Some(&Value::from(3)));
+
+        let ret = rest(&vals).unwrap();
+        let ret_ = ret.downcast_ref::<Value>();
+        assert_eq!(ret_, Some(&Value::from(vec![2, 3])));
+
+        let vals: Vec<Arc<Any>> = vec![varc!(vec![1])];
+        let ret = rest(&vals).unwrap();
+        let ret_ = ret.downcast_ref::<Value>();
+        assert_eq!(ret_, Some(&Value::Array(vec![])));
+
+        let vals: Vec<Arc<Any>> = vec![varc!(Vec::<u8>::new())];
+        assert_eq!(first(&vals).unwrap_err(), "first of empty array");
+        assert_eq!(last(&vals).unwrap_err(), "last of empty array");
+        let ret = rest(&vals).unwrap();
+        let ret_ = ret.downcast_ref::<Value>();
+        assert_eq!(ret_, Some(&Value::Array(vec![])));
+
+        let vals: Vec<Arc<Any>> = vec![varc!("foo")];
+        assert_eq!(first(&vals).unwrap_err(), "first requires an array, got string");
+        assert!(last(&vals).is_err());
+        assert!(rest(&vals).is_err());
+    }
+
+    #[test]
+    fn test_has() {
+        let vals: Vec<Arc<Any>> = vec![varc!(2), varc!(vec![1, 2, 3])];
+        let ret = has(&vals).unwrap();
+        let ret_ = ret.downcast_ref::<Value>();
+        assert_eq!(ret_, Some(&Value::from(true)));
+
+        let vals: Vec<Arc<Any>> = vec![varc!(2.0), varc!(vec![1, 2, 3])];
+        let ret = has(&vals).unwrap();
+        let ret_ = ret.downcast_ref::<Value>();
+        assert_eq!(ret_, Some(&Value::from(true)));
+
+        let vals: Vec<Arc<Any>> = vec![varc!("2"), varc!(vec![1, 2, 3])];
+        let ret = has(&vals).unwrap();
+        let ret_ = ret.downcast_ref::<Value>();
+        assert_eq!(ret_, Some(&Value::from(false)));
+
+        let vals: Vec<Arc<Any>> = vec![varc!(1), varc!(Vec::<u8>::new())];
+        let ret = has(&vals).unwrap();
+        let ret_ = ret.downcast_ref::<Value>();
+        assert_eq!(ret_, Some(&Value::from(false)));
+
+        let vals: Vec<Arc<Any>> = vec![varc!("o"), varc!("foo")];
+        assert_eq!(has(&vals).unwrap_err(), "has requires an array, got string");
+    }
+
+    #[test]
+    fn test_has_key() {
+        let mut map = HashMap::new();
+        map.insert("zero".to_owned(), Value::from(0));
+        map.insert("empty".to_owned(), Value::from(""));
+        map.insert("1".to_owned(), Value::Nil);
+        let vals: Vec<Arc<Any>> = vec![varc!(map.clone()), varc!("zero")];
+        let ret = has_key(&vals).unwrap();
+        let ret_ = ret.downcast_ref::<Value>();
+        assert_eq!(ret_, Some(&Value::from(true)));
+
+        let vals: Vec<Arc<Any>> = vec![varc!(map.clone()), varc!("empty")];
+        let ret = has_key(&vals).unwrap();
+        let ret_ = ret.downcast_ref::<Value>();
+        assert_eq!(ret_, Some(&Value::from(true)));
+
+        let vals: Vec<Arc<Any>> = vec![varc!(map.clone()), varc!(1)];
+        let ret = has_key(&vals).unwrap();
+        let ret_ = ret.downcast_ref::<Value>();
+        assert_eq!(ret_, Some(&Value::from(true)));
+
+        let vals: Vec<Arc<Any>> = vec![varc!(map.clone()), varc!("absent")];
+        let ret = has_key(&vals).unwrap();
+        let ret_ = ret.downcast_ref::<Value>();
+        assert_eq!(ret_, Some(&Value::from(false)));
+
+        let vals: Vec<Arc<Any>> = vec![varc!(vec![1]), varc!(0)];
+        assert_eq!(has_key(&vals).unwrap_err(), "hasKey requires a map, got array");
+    }
+
+    #[test]
+    fn test_len() {
+        let vals: Vec<Arc<Any>> = vec![varc!(vec![1, 2])];
+        let ret = len(&vals).unwrap();
+        let ret_ = ret.downcast_ref::<Value>();
+        assert_eq!(ret_, Some(&Value::from(2)));
+
+        let vals: Vec<Arc<Any>> = vec![varc!(1)];
+        assert_eq!(len(&vals).unwrap_err(), "unable to call len on number");
+    }
+
+    #[test]
+    fn test_slice() {
+        let vals: Vec<Arc<Any>> = vec![varc!(vec![1, 2, 3]), varc!(1), varc!(2)];
+        let ret = slice(&vals).unwrap();
+        let ret_ = ret.downcast_ref::<Value>();
+        assert_eq!(ret_, Some(&Value::from(vec![2])));
+
+        let vals: Vec<Arc<Any>> = vec![varc!(vec![1, 2, 3]), varc!(1)];
+        let ret = slice(&vals).unwrap();
+        let ret_ = ret.downcast_ref::<Value>();
+        assert_eq!(ret_, Some(&Value::from(vec![2, 3])));
+
+        let vals: Vec<Arc<Any>> = vec![varc!(vec![1, 2, 3])];
+        let ret = slice(&vals).unwrap();
+        let ret_ = ret.downcast_ref::<Value>();
+        assert_eq!(ret_, Some(&Value::from(vec![1, 2, 3])));
+
+        let vals: Vec<Arc<Any>> = vec![varc!("äöü€"), varc!(1), varc!(3)];
+        let ret = slice(&vals).unwrap();
+        let ret_ = ret.downcast_ref::<Value>();
+        assert_eq!(ret_, Some(&Value::from("öü")));
+
+        let vals: Vec<Arc<Any>> = vec![varc!("äöü€"), varc!(4)];
+        let ret = slice(&vals).unwrap();
+        let ret_ = ret.downcast_ref::<Value>();
+        assert_eq!(ret_, Some(&Value::from("")));
+
+        let vals: Vec<Arc<Any>> = vec![varc!(vec![1, 2, 3]), varc!(2), varc!(1)];
+        assert!(slice(&vals).is_err());
+
+        let vals: Vec<Arc<Any>> = vec![varc!(vec![1, 2, 3]), varc!(1), varc!(4)];
+        assert!(slice(&vals).is_err());
+
+        let vals: Vec<Arc<Any>> = vec![varc!("foo"), varc!(-1)];
+        assert!(slice(&vals).is_err());
+
+        let vals: Vec<Arc<Any>> = vec![varc!(1), varc!(0)];
+        assert_eq!(
+            slice(&vals).err(),
+            Some(String::from("can't slice item of type number"))
+        );
+
+        let vals: Vec<Arc<Any>> = vec![varc!("foo"), varc!("1")];
+        assert_eq!(
+            slice(&vals).err(),
+            Some(String::from("cannot slice with index of type string"))
+        );
+    }
+
+    #[test]
+    fn test_urlquery() {
+        let vals: Vec<Arc<Any>> = vec![varc!("a b&c=d/e?f~g_h.i-j")];
+        let ret = urlquery(&vals).unwrap();
+        let ret_ = ret.downcast_ref::<Value>();
+        assert_eq!(ret_, Some(&Value::from("a%20b%26c%3Dd%2Fe%3Ff~g_h.i-j")));
+
+        let vals: Vec<Arc<Any>> = vec![varc!("ä"), varc!(1)];
+        assert!(urlquery(&vals).is_err());
+
+        let vals: Vec<Arc<Any>> = vec![varc!("ä")];
+        let ret = urlquery(&vals).unwrap();
+        let ret_ = ret.downcast_ref::<Value>();
+        assert_eq!(ret_, Some(&Value::from("%C3%A4")));
+
+        let vals: Vec<Arc<Any>> = vec![varc!(23)];
+        let ret = urlquery(&vals).unwrap();
+        let ret_ = ret.downcast_ref::<Value>();
+        assert_eq!(ret_, Some(&Value::from("23")));
+    }
+
+    #[test]
+    fn test_quote() {
+        let vals: Vec<Arc<Any>> = vec![varc!("say \"hi\"\n\t\\ é\u{1}")];
+        let ret = quote(&vals).unwrap();
+        let ret_ = ret.downcast_ref::<Value>();
+        assert_eq!(ret_, Some(&Value::from(r#""say \"hi\"\n\t\\ é\x01""#)));
+
+        let vals: Vec<Arc<Any>> = vec![varc!(42)];
+        let ret = quote(&vals).unwrap();
+        let ret_ = ret.downcast_ref::<Value>();
+        assert_eq!(ret_, Some(&Value::from(r#""42""#)));
+
+        let vals: Vec<Arc<Any>> = vec![];
+        assert!(quote(&vals).is_err());
+    }
+
+    #[test]
+    fn test_to_string() {
+        let vals: Vec<Arc<Any>> = vec![varc!(42)];
+        let ret = to_string(&vals).unwrap();
+        let ret_ = ret.downcast_ref::<Value>();
+        assert_eq!(ret_, Some(&Value::from("42")));
+
+        let vals: Vec<Arc<Any>> = vec![varc!(vec![1.5, 2.0])];
+        let ret = to_string(&vals).unwrap();
+        let ret_ = ret.downcast_ref::<Value>();
+        assert_eq!(ret_, Some(&Value::from("[1.5 2]")));
+
+        let vals: Vec<Arc<Any>> = vec![varc!("foo")];
+        let ret = to_string(&vals).unwrap();
+        let ret_ = ret.downcast_ref::<Value>();
+        assert_eq!(ret_, Some(&Value::from("foo")));
+    }
+
+    #[test]
+    fn test_upper_lower() {
+        let vals: Vec<Arc<Any>> = vec![varc!("Grüße, Σίσυφος!")];
+        let ret = upper(&vals).unwrap();
+        let ret_ = ret.downcast_ref::<Value>();
+        assert_eq!(ret_, Some(&Value::from("GRÜSSE, ΣΊΣΥΦΟΣ!")));
+
+        let ret = lower(&vals).unwrap();
+        let ret_ = ret.downcast_ref::<Value>();
+        assert_eq!(ret_, Some(&Value::from("grüße, σίσυφος!")));
+
+        let vals: Vec<Arc<Any>> = vec![varc!(true)];
+        let ret = upper(&vals).unwrap();
+        let ret_ = ret.downcast_ref::<Value>();
+        assert_eq!(ret_, Some(&Value::from("TRUE")));
+
+        let vals: Vec<Arc<Any>> = vec![varc!("a"), varc!("b")];
+        assert!(upper(&vals).is_err());
+        assert!(lower(&vals).is_err());
+    }
+
+    #[test]
+    fn test_title() {
+        let vals: Vec<Arc<Any>> = vec![varc!("hello wide-world, ünïcode_x 2nd")];
+        let ret = title(&vals).unwrap();
+        let ret_ = ret.downcast_ref::<Value>();
+        assert_eq!(ret_, Some(&Value::from("Hello Wide-World, Ünïcode_x 2nd")));
+
+        let vals: Vec<Arc<Any>> = vec![varc!("hello WORLD")];
+        let ret = title(&vals).unwrap();
+        let ret_ = ret.downcast_ref::<Value>();
+        assert_eq!(ret_, Some(&Value::from("Hello World")));
+
+        let vals: Vec<Arc<Any>> = vec![varc!("  ÉCOLE élève\tΣΊΣΥΦΟΣ straße ")];
+        let ret = title(&vals).unwrap();
+        let ret_ = ret.downcast_ref::<Value>();
+        assert_eq!(ret_, Some(&Value::from("  École Élève\tΣίσυφος Straße ")));
+
+        let vals: Vec<Arc<Any>> = vec![varc!("")];
+        let ret = title(&vals).unwrap();
+        let ret_ = ret.downcast_ref::<Value>();
+        assert_eq!(ret_, Some(&Value::from("")));
+    }
+
+    #[test]
+    fn test_trim() {
+        let vals: Vec<Arc<Any>> = vec![varc!(" \t foo bar\n ")];
+        let ret = trim(&vals).unwrap();
+        let ret_ = ret.downcast_ref::<Value>();
+        assert_eq!(ret_, Some(&Value::from("foo bar")));
+
+        let vals: Vec<Arc<Any>> = vec![varc!(42)];
+        let ret = trim(&vals).unwrap();
+        let ret_ = ret.downcast_ref::<Value>();
+        assert_eq!(ret_, Some(&Value::from("42")));
+    }
 
-gn!(
-#[doc="
-Returns the boolean truth of arg1 > arg2
+    #[test]
+    fn test_trim_prefix_suffix() {
+        let vals: Vec<Arc<Any>> = vec![varc!("ab"), varc!("ababc")];
+        let ret = trim_prefix(&vals).unwrap();
+        let ret_ = ret.downcast_ref::<Value>();
+        assert_eq!(ret_, Some(&Value::from("abc")));
 
-# Example
-```
-use gtmpl::template;
-let greater_than = template(\"{{ gt 1.4 . }}\", 1.2);
-assert_eq!(&greater_than.unwrap(), \"true\");
-```
-"]
-gt(a: ref Value, b: ref Value) -> Result<Value, String> {
-    let ret = match cmp(a, b) {
-        None => return Err(format!("unable to compare {} and {}", a, b)),
-        Some(Ordering::Greater) => true,
-        _ => false,
-    };
-    Ok(Value::from(ret))
-});
+        let vals: Vec<Arc<Any>> = vec![varc!("bc"), varc!("ababc")];
+        let ret = trim_prefix(&vals).unwrap();
+        let ret_ = ret.downcast_ref::<Value>();
+        assert_eq!(ret_, Some(&Value::from("ababc")));
 
-gn!(
-#[doc="
-Returns the boolean truth of arg1 >= arg2
+        let vals: Vec<Arc<Any>> = vec![varc!("bc"), varc!("abcbc")];
+        let ret = trim_suffix(&vals).unwrap();
+        let ret_ = ret.downcast_ref::<Value>();
+        assert_eq!(ret_, Some(&Value::from("abc")));
 
-# Example
-```
-use gtmpl::template;
-let greater_or_equal = template(\"{{ ge 1.4 1.3 }}\", 1.2);
-assert_eq!(greater_or_equal.unwrap(), \"true\");
+        let vals: Vec<Arc<Any>> = vec![varc!("ab"), varc!("abcbc")];
+        let ret = trim_suffix(&vals).unwrap();
+        let ret_ = ret.downcast_ref::<Value>();
+        assert_eq!(ret_, Some(&Value::from("abcbc")));
 
-let greater_or_equal = template(\"{{ ge 1.4 . }}\", 0.2);
-assert_eq!(&greater_or_equal.unwrap(), \"true\");
-```
-"]
-ge(a: ref Value, b: ref Value) -> Result<Value, String> {
-    let ret = match cmp(a, b) {
-        None => return Err(format!("unable to compare {} and {}", a, b)),
-        Some(Ordering::Greater) | Some(Ordering::Equal) => true,
-        _ => false,
-    };
-    Ok(Value::from(ret))
-});
+        let vals: Vec<Arc<Any>> = vec![varc!(""), varc!("äöü")];
+        let ret = trim_suffix(&vals).unwrap();
+        let ret_ = ret.downcast_ref::<Value>();
+        assert_eq!(ret_, Some(&Value::from("äöü")));
 
-fn cmp(left: &Value, right: &Value) -> Option<Ordering> {
-    match (left, right) {
-        (&Value::Number(ref l), &Value::Number(ref r)) => {
-            if let (Some(lf), Some(rf)) = (l.as_f64(), r.as_f64()) {
-                return lf.partial_cmp(&rf);
-            }
-            if let (Some(li), Some(ri)) = (l.as_i64(), r.as_i64()) {
-                return li.partial_cmp(&ri);
-            }
-            if let (Some(lu), Some(ru)) = (l.as_u64(), r.as_u64()) {
-                return lu.partial_cmp(&ru);
-            }
-            None
-        }
-        (&Value::Bool(ref l), &Value::Bool(ref r)) => l.partial_cmp(r),
-        (&Value::String(ref l), &Value::String(ref r)) => l.partial_cmp(r),
-        (&Value::Array(ref l), &Value::Array(ref r)) => l.len().partial_cmp(&r.len()),
-        _ => None,
+        let vals: Vec<Arc<Any>> = vec![varc!("ü")];
+        assert!(trim_prefix(&vals).is_err());
     }
-}
-
-#[cfg(test)]
-mod tests_mocked {
-    use super::*;
-    use std::collections::HashMap;
 
     #[test]
-    fn test_eq() {
-        let vals: Vec<Arc<Any>> = vec![varc!("foo".to_owned()), varc!("foo".to_owned())];
-        let ret = eq(&vals).unwrap();
+    fn test_trim_all() {
+        let vals: Vec<Arc<Any>> = vec![varc!("ä-"), varc!("-ä-foo-bar-ää")];
+        let ret = trim_all(&vals).unwrap();
         let ret_ = ret.downcast_ref::<Value>();
-        assert_eq!(ret_, Some(&Value::Bool(true)));
-        let vals: Vec<Arc<Any>> = vec![varc!(1u32), varc!(1u32), varc!(1i8)];
-        let ret = eq(&vals).unwrap();
+        assert_eq!(ret_, Some(&Value::from("foo-bar")));
+
+        let vals: Vec<Arc<Any>> = vec![varc!("xyz"), varc!("foo")];
+        let ret = trim_all(&vals).unwrap();
         let ret_ = ret.downcast_ref::<Value>();
-        assert_eq!(ret_, Some(&Value::Bool(true)));
-        let vals: Vec<Arc<Any>> = vec![varc!(false), varc!(false), varc!(false)];
-        let ret = eq(&vals).unwrap();
+        assert_eq!(ret_, Some(&Value::from("foo")));
+
+        let vals: Vec<Arc<Any>> = vec![varc!(""), varc!(" foo ")];
+        let ret = trim_all(&vals).unwrap();
         let ret_ = ret.downcast_ref::<Value>();
-        assert_eq!(ret_, Some(&Value::Bool(true)));
+        assert_eq!(ret_, Some(&Value::from(" foo ")));
+
+        let vals: Vec<Arc<Any>> = vec![varc!("0"), varc!(1000)];
+        let ret = trim_all(&vals).unwrap();
+        let ret_ = ret.downcast_ref::<Value>();
+        assert_eq!(ret_, Some(&Value::from("1")));
     }
 
     #[test]
-    fn test_and() {
-        let vals: Vec<Arc<Any>> = vec![varc!(0i32), varc!(1u8)];
-        let ret = and(&vals).unwrap();
+    fn test_replace() {
+        let vals: Vec<Arc<Any>> = vec![varc!("o"), varc!("0"), varc!("foo boo")];
+        let ret = replace(&vals).unwrap();
         let ret_ = ret.downcast_ref::<Value>();
-        assert_eq!(ret_, Some(&Value::from(0i32)));
+        assert_eq!(ret_, Some(&Value::from("f00 b00")));
 
-        let vals: Vec<Arc<Any>> = vec![varc!(1i32), varc!(2u8)];
-        let ret = and(&vals).unwrap();
+        let vals: Vec<Arc<Any>> = vec![varc!(""), varc!("-"), varc!("ab")];
+        let ret = replace(&vals).unwrap();
         let ret_ = ret.downcast_ref::<Value>();
-        assert_eq!(ret_, Some(&Value::from(2u8)));
+        assert_eq!(ret_, Some(&Value::from("-a-b-")));
+
+        let vals: Vec<Arc<Any>> = vec![varc!("o"), varc!("")];
+        assert!(replace(&vals).is_err());
     }
 
     #[test]
-    fn test_or() {
-        let vals: Vec<Arc<Any>> = vec![varc!(0i32), varc!(1u8)];
-        let ret = or(&vals).unwrap();
+    fn test_repeat() {
+        let vals: Vec<Arc<Any>> = vec![varc!(2), varc!("äb")];
+        let ret = repeat(&vals).unwrap();
         let ret_ = ret.downcast_ref::<Value>();
-        assert_eq!(ret_, Some(&Value::from(1u8)));
+        assert_eq!(ret_, Some(&Value::from("äbäb")));
 
-        let vals: Vec<Arc<Any>> = vec![varc!(0i32), varc!(0u8)];
-        let ret = or(&vals).unwrap();
+        let vals: Vec<Arc<Any>> = vec![varc!(0), varc!("ab")];
+        let ret = repeat(&vals).unwrap();
         let ret_ = ret.downcast_ref::<Value>();
-        assert_eq!(ret_, Some(&Value::from(0u8)));
+        assert_eq!(ret_, Some(&Value::from("")));
+
+        let vals: Vec<Arc<Any>> = vec![varc!(-1), varc!("ab")];
+        assert!(repeat(&vals).is_err());
+
+        let vals: Vec<Arc<Any>> = vec![varc!(1.5), varc!("ab")];
+        assert!(repeat(&vals).is_err());
+
+        let vals: Vec<Arc<Any>> = vec![varc!("2"), varc!("ab")];
+        assert_eq!(
+            repeat(&vals).unwrap_err(),
+            "repeat requires an integer count, got string"
+        );
+
+        let vals: Vec<Arc<Any>> = vec![varc!(u64::max_value()), varc!("ab")];
+        assert_eq!(repeat(&vals).unwrap_err(), "repeat result too large");
+
+        let vals: Vec<Arc<Any>> = vec![varc!(40_000_000), varc!("ab")];
+        assert_eq!(repeat(&vals).unwrap_err(), "repeat result too large");
+
+        let vals: Vec<Arc<Any>> = vec![varc!(u64::max_value()), varc!("")];
+        let ret = repeat(&vals).unwrap();
+        let ret_ = ret.downcast_ref::<Value>();
+        assert_eq!(ret_, Some(&Value::from("")));
     }
 
     #[test]
-    fn test_ne() {
-        let vals: Vec<Arc<Any>> = vec![varc!(0i32), varc!(1u8)];
-        let ret = ne(&vals).unwrap();
+    fn test_indent() {
+        let vals: Vec<Arc<Any>> = vec![varc!(2), varc!("a:\n  b: c\n\nd: e")];
+        let ret = indent(&vals).unwrap();
         let ret_ = ret.downcast_ref::<Value>();
-        assert_eq!(ret_, Some(&Value::from(true)));
+        assert_eq!(ret_, Some(&Value::from("  a:\n    b: c\n  \n  d: e")));
 
-        let vals: Vec<Arc<Any>> = vec![varc!(0i32), varc!(0u8)];
-        let ret = ne(&vals).unwrap();
+        let ret = nindent(&vals).unwrap();
         let ret_ = ret.downcast_ref::<Value>();
-        assert_eq!(ret_, Some(&Value::from(false)));
+        assert_eq!(ret_, Some(&Value::from("\n  a:\n    b: c\n  \n  d: e")));
 
-        let vals: Vec<Arc<Any>> = vec![varc!("foo"), varc!("bar")];
-        let ret = ne(&vals).unwrap();
+        let vals: Vec<Arc<Any>> = vec![varc!(0), varc!("a\nb")];
+        let ret = indent(&vals).unwrap();
         let ret_ = ret.downcast_ref::<Value>();
-        assert_eq!(ret_, Some(&Value::from(true)));
+        assert_eq!(ret_, Some(&Value::from("a\nb")));
 
-        let vals: Vec<Arc<Any>> = vec![varc!("foo"), varc!("foo")];
-        let ret = ne(&vals).unwrap();
+        let vals: Vec<Arc<Any>> = vec![varc!(2), varc!("")];
+        let ret = indent(&vals).unwrap();
         let ret_ = ret.downcast_ref::<Value>();
-        assert_eq!(ret_, Some(&Value::from(false)));
+        assert_eq!(ret_, Some(&Value::from("  ")));
+
+        let vals: Vec<Arc<Any>> = vec![varc!(2)];
+        assert!(nindent(&vals).is_err());
+
+        let vals: Vec<Arc<Any>> = vec![varc!(u64::max_value()), varc!("a\nb")];
+        assert_eq!(indent(&vals).unwrap_err(), "indent result too large");
+        assert_eq!(nindent(&vals).unwrap_err(), "nindent result too large");
+
+        let vals: Vec<Arc<Any>> = vec![varc!(40_000_000), varc!("a\nb")];
+        assert_eq!(indent(&vals).unwrap_err(), "indent result too large");
     }
 
     #[test]
-    fn test_lt() {
-        let vals: Vec<Arc<Any>> = vec![varc!(-1i32), varc!(1u8)];
-        let ret = lt(&vals).unwrap();
+    fn test_contains() {
+        let vals: Vec<Arc<Any>> = vec![varc!("oo"), varc!("foo")];
+        let ret = contains(&vals).unwrap();
         let ret_ = ret.downcast_ref::<Value>();
         assert_eq!(ret_, Some(&Value::from(true)));
 
-        let vals: Vec<Arc<Any>> = vec![varc!(0i32), varc!(0u8)];
-        let ret = lt(&vals).unwrap();
+        let vals: Vec<Arc<Any>> = vec![varc!("bar"), varc!("foo")];
+        let ret = contains(&vals).unwrap();
         let ret_ = ret.downcast_ref::<Value>();
         assert_eq!(ret_, Some(&Value::from(false)));
 
-        let vals: Vec<Arc<Any>> = vec![varc!(1i32), varc!(0u8)];
-        let ret = lt(&vals).unwrap();
+        let vals: Vec<Arc<Any>> = vec![varc!(""), varc!("")];
+        let ret = contains(&vals).unwrap();
         let ret_ = ret.downcast_ref::<Value>();
-        assert_eq!(ret_, Some(&Value::from(false)));
+        assert_eq!(ret_, Some(&Value::from(true)));
     }
 
     #[test]
-    fn test_le() {
-        let vals: Vec<Arc<Any>> = vec![varc!(-1i32), varc!(1u8)];
-        let ret = le(&vals).unwrap();
+    fn test_has_prefix_suffix() {
+        let vals: Vec<Arc<Any>> = vec![varc!("fo"), varc!("foo")];
+        let ret = has_prefix(&vals).unwrap();
         let ret_ = ret.downcast_ref::<Value>();
         assert_eq!(ret_, Some(&Value::from(true)));
+        let ret = has_suffix(&vals).unwrap();
+        let ret_ = ret.downcast_ref::<Value>();
+        assert_eq!(ret_, Some(&Value::from(false)));
 
-        let vals: Vec<Arc<Any>> = vec![varc!(0i32), varc!(0u8)];
-        let ret = le(&vals).unwrap();
+        let vals: Vec<Arc<Any>> = vec![varc!(""), varc!("foo")];
+        let ret = has_prefix(&vals).unwrap();
+        let ret_ = ret.downcast_ref::<Value>();
+        assert_eq!(ret_, Some(&Value::from(true)));
+        let ret = has_suffix(&vals).unwrap();
         let ret_ = ret.downcast_ref::<Value>();
         assert_eq!(ret_, Some(&Value::from(true)));
 
-        let vals: Vec<Arc<Any>> = vec![varc!(1i32), varc!(0u8)];
-        let ret = le(&vals).unwrap();
+        let vals: Vec<Arc<Any>> = vec![varc!("foo")];
+        assert!(has_prefix(&vals).is_err());
+        assert!(has_suffix(&vals).is_err());
+    }
+
+    #[test]
+    fn test_join() {
+        let vals: Vec<Arc<Any>> = vec![varc!(", "), varc!(vec!["a", "b", "c"])];
+        let ret = join(&vals).unwrap();
         let ret_ = ret.downcast_ref::<Value>();
-        assert_eq!(ret_, Some(&Value::from(false)));
+        assert_eq!(ret_, Some(&Value::from("a, b, c")));
+
+        let vals: Vec<Arc<Any>> = vec![varc!("-"), varc!(vec![1, 2])];
+        let ret = join(&vals).unwrap();
+        let ret_ = ret.downcast_ref::<Value>();
+        assert_eq!(ret_, Some(&Value::from("1-2")));
+
+        let vals: Vec<Arc<Any>> = vec![varc!(","), Arc::new(Value::Array(vec![]))];
+        let ret = join(&vals).unwrap();
+        let ret_ = ret.downcast_ref::<Value>();
+        assert_eq!(ret_, Some(&Value::from("")));
+
+        let vals: Vec<Arc<Any>> = vec![varc!(","), varc!("a,b")];
+        assert_eq!(
+            join(&vals).unwrap_err(),
+            "join requires an array, got string"
+        );
     }
 
     #[test]
-    fn test_gt() {
-        let vals: Vec<Arc<Any>> = vec![varc!(-1i32), varc!(1u8)];
-        let ret = gt(&vals).unwrap();
+    fn test_split() {
+        let vals: Vec<Arc<Any>> = vec![varc!(","), varc!("a,b,,c")];
+        let ret = split(&vals).unwrap();
         let ret_ = ret.downcast_ref::<Value>();
-        assert_eq!(ret_, Some(&Value::from(false)));
+        assert_eq!(ret_, Some(&Value::from(vec!["a", "b", "", "c"])));
 
-        let vals: Vec<Arc<Any>> = vec![varc!(0i32), varc!(0u8)];
-        let ret = gt(&vals).unwrap();
+        let vals: Vec<Arc<Any>> = vec![varc!(","), varc!("")];
+        let ret = split(&vals).unwrap();
         let ret_ = ret.downcast_ref::<Value>();
-        assert_eq!(ret_, Some(&Value::from(false)));
+        assert_eq!(ret_, Some(&Value::from(vec![""])));
 
-        let vals: Vec<Arc<Any>> = vec![varc!(1i32), varc!(0u8)];
-        let ret = gt(&vals).unwrap();
+        let vals: Vec<Arc<Any>> = vec![varc!(""), varc!("añb")];
+        let ret = split(&vals).unwrap();
         let ret_ = ret.downcast_ref::<Value>();
-        assert_eq!(ret_, Some(&Value::from(true)));
+        assert_eq!(ret_, Some(&Value::from(vec!["a", "ñ", "b"])));
     }
 
     #[test]
-    fn test_ge() {
-        let vals: Vec<Arc<Any>> = vec![varc!(-1i32), varc!(1u8)];
-        let ret = ge(&vals).unwrap();
+    fn test_add() {
+        let vals: Vec<Arc<Any>> = vec![varc!(1u8), varc!(-3i32), varc!(5u64)];
+        let ret = add(&vals).unwrap();
         let ret_ = ret.downcast_ref::<Value>();
-        assert_eq!(ret_, Some(&Value::from(false)));
+        assert_eq!(ret_, Some(&Value::from(3i64)));
 
-        let vals: Vec<Arc<Any>> = vec![varc!(0i32), varc!(0u8)];
-        let ret = ge(&vals).unwrap();
+        let vals: Vec<Arc<Any>> = vec![varc!(1u8), varc!(0.5f64)];
+        let ret = add(&vals).unwrap();
         let ret_ = ret.downcast_ref::<Value>();
-        assert_eq!(ret_, Some(&Value::from(true)));
+        assert_eq!(ret_, Some(&Value::from(1.5f64)));
 
-        let vals: Vec<Arc<Any>> = vec![varc!(1i32), varc!(0u8)];
-        let ret = ge(&vals).unwrap();
+        let vals: Vec<Arc<Any>> = vec![varc!(1u8), varc!("foo")];
+        assert!(add(&vals).is_err());
+
+        let vals: Vec<Arc<Any>> = vec![varc!(1i64 << 62), varc!(1i64 << 62)];
+        assert!(add(&vals).is_err());
+    }
+
+    #[test]
+    fn test_sub() {
+        let vals: Vec<Arc<Any>> = vec![varc!(3u8), varc!(5u8)];
+        let ret = sub(&vals).unwrap();
         let ret_ = ret.downcast_ref::<Value>();
-        assert_eq!(ret_, Some(&Value::from(true)));
+        assert_eq!(ret_, Some(&Value::from(-2i64)));
+
+        let vals: Vec<Arc<Any>> = vec![varc!(3.5f64), varc!(1u8)];
+        let ret = sub(&vals).unwrap();
+        let ret_ = ret.downcast_ref::<Value>();
+        assert_eq!(ret_, Some(&Value::from(2.5f64)));
+
+        let vals: Vec<Arc<Any>> = vec![varc!(1u8), varc!(1.5f64)];
+        let ret = sub(&vals).unwrap();
+        let ret_ = ret.downcast_ref::<Value>();
+        assert_eq!(ret_.map(to_go_string), Some(String::from("-0.5")));
+
+        let vals: Vec<Arc<Any>> = vec![varc!(-1i8), varc!(0.25f64)];
+        let ret = sub(&vals).unwrap();
+        let ret_ = ret.downcast_ref::<Value>();
+        assert_eq!(ret_.map(to_go_string), Some(String::from("-1.25")));
+
+        let vals: Vec<Arc<Any>> = vec![varc!(3u8), varc!(5u8), varc!(1u8)];
+        assert!(sub(&vals).is_err());
     }
 
     #[test]
-    fn test_print() {
-        let vals: Vec<Arc<Any>> = vec![varc!("foo"), varc!(1u8)];
-        let ret = print(&vals).unwrap();
+    fn test_mul() {
+        let vals: Vec<Arc<Any>> = vec![varc!(2u8), varc!(3u8), varc!(4u8)];
+        let ret = mul(&vals).unwrap();
         let ret_ = ret.downcast_ref::<Value>();
-        assert_eq!(ret_, Some(&Value::from("foo1")));
+        assert_eq!(ret_, Some(&Value::from(24i64)));
 
-        let vals: Vec<Arc<Any>> = vec![varc!("foo"), varc!(1u8), varc!(2)];
-        let ret = print(&vals).unwrap();
+        let vals: Vec<Arc<Any>> = vec![varc!(3u8), varc!(0.5f64)];
+        let ret = mul(&vals).unwrap();
         let ret_ = ret.downcast_ref::<Value>();
-        assert_eq!(ret_, Some(&Value::from("foo1 2")));
+        assert_eq!(ret_, Some(&Value::from(1.5f64)));
 
-        let vals: Vec<Arc<Any>> = vec![varc!(true), varc!(1), varc!("foo"), varc!(2)];
-        let ret = print(&vals).unwrap();
+        let vals: Vec<Arc<Any>> = vec![varc!(-3i8), varc!(0.5f64)];
+        let ret = mul(&vals).unwrap();
         let ret_ = ret.downcast_ref::<Value>();
-        assert_eq!(ret_, Some(&Value::from("true 1foo2")));
+        assert_eq!(ret_.map(to_go_string), Some(String::from("-1.5")));
     }
 
     #[test]
-    fn test_println() {
-        let vals: Vec<Arc<Any>> = vec![varc!("foo"), varc!(1u8)];
-        let ret = println(&vals).unwrap();
+    fn test_div_mod() {
+        let vals: Vec<Arc<Any>> = vec![varc!(7u8), varc!(2u8)];
+        let ret = div(&vals).unwrap();
         let ret_ = ret.downcast_ref::<Value>();
-        assert_eq!(ret_, Some(&Value::from("foo 1\n")));
+        assert_eq!(ret_, Some(&Value::from(3i64)));
+        let ret = modulo(&vals).unwrap();
+        let ret_ = ret.downcast_ref::<Value>();
+        assert_eq!(ret_, Some(&Value::from(1i64)));
+
+        let vals: Vec<Arc<Any>> = vec![varc!(7u8), varc!(2.5f64)];
+        let ret = div(&vals).unwrap();
+        let ret_ = ret.downcast_ref::<Value>();
+        assert_eq!(ret_, Some(&Value::from(2.8f64)));
+        let ret = modulo(&vals).unwrap();
+        let ret_ = ret.downcast_ref::<Value>();
+        assert_eq!(ret_, Some(&Value::from(2u8)));
+
+        let vals: Vec<Arc<Any>> = vec![varc!(-7.5f64), varc!(2u8)];
+        let ret = div(&vals).unwrap();
+        let ret_ = ret.downcast_ref::<Value>();
+        assert_eq!(ret_.map(to_go_string), Some(String::from("-3.75")));
+        let ret = modulo(&vals).unwrap();
+        let ret_ = ret.downcast_ref::<Value>();
+        assert_eq!(ret_.map(to_go_string), Some(String::from("-1.5")));
+
+        // A negative fraction stays a float for the next operation.
+        let vals: Vec<Arc<Any>> = vec![varc!(-0.5f64), varc!(2u8)];
+        let quarter = div(&vals).unwrap();
+        let ret = add(&[quarter, varc!(1u8)]).unwrap();
+        let ret_ = ret.downcast_ref::<Value>();
+        assert_eq!(ret_.map(to_go_string), Some(String::from("0.75")));
+
+        let vals: Vec<Arc<Any>> = vec![varc!(7u8), varc!(0u8)];
+        assert_eq!(div(&vals).err(), Some(String::from("div by zero")));
+        assert_eq!(modulo(&vals).err(), Some(String::from("mod by zero")));
+        let vals: Vec<Arc<Any>> = vec![varc!(7.5f64), varc!(0.0f64)];
+        assert!(div(&vals).is_err());
+        assert!(modulo(&vals).is_err());
+    }
+
+    #[test]
+    fn test_to_json() {
+        let mut m = HashMap::new();
+        m.insert("b".to_owned(), Value::from(vec![1, 2]));
+        m.insert("a".to_owned(), Value::from("x"));
+        let vals: Vec<Arc<Any>> = vec![Arc::new(Value::Map(m))];
+        let ret = to_json(&vals).unwrap();
+        let ret_ = ret.downcast_ref::<Value>();
+        assert_eq!(ret_, Some(&Value::from(r#"{"a":"x","b":[1,2]}"#)));
+
+        let ret = to_pretty_json(&vals).unwrap();
+        let ret_ = ret.downcast_ref::<Value>();
+        assert_eq!(
+            ret_,
+            Some(&Value::from("{\n  \"a\": \"x\",\n  \"b\": [\n    1,\n    2\n  ]\n}"))
+        );
 
         let vals: Vec<Arc<Any>> = vec![];
-        let ret = println(&vals).unwrap();
+        assert!(to_json(&vals).is_err());
+    }
+
+    #[test]
+    fn test_from_json() {
+        let vals: Vec<Arc<Any>> = vec![varc!(r#"{"a": [1, "x"]}"#)];
+        let ret = from_json(&vals).unwrap();
         let ret_ = ret.downcast_ref::<Value>();
-        assert_eq!(ret_, Some(&Value::from("\n")));
+        let mut m = HashMap::new();
+        m.insert(
+            "a".to_owned(),
+            Value::Array(vec![Value::from(1u64), Value::from("x")]),
+        );
+        assert_eq!(ret_, Some(&Value::Map(m)));
+
+        let vals: Vec<Arc<Any>> = vec![varc!("-1.5")];
+        let ret = from_json(&vals).unwrap();
+        let ret_ = ret.downcast_ref::<Value>();
+        assert_eq!(ret_.map(to_go_string), Some(String::from("-1.5")));
+
+        let vals: Vec<Arc<Any>> = vec![varc!(r#"{"a": "#)];
+        assert!(from_json(&vals).is_err());
+        let vals: Vec<Arc<Any>> = vec![varc!(1)];
+        assert!(from_json(&vals).is_err());
     }
 
     #[test]
-    fn test_index() {
-        let vals: Vec<Arc<Any>> = vec![varc!(vec![vec![1, 2], vec![3, 4]]), varc!(1), varc!(0)];
-        let ret = index(&vals).unwrap();
+    fn test_html() {
+        let vals: Vec<Arc<Any>> = vec![varc!(r#"<a href="foo">'bar' & baz</a>"#)];
+        let ret = html(&vals).unwrap();
         let ret_ = ret.downcast_ref::<Value>();
-        assert_eq!(ret_, Some(&Value::from(3)));
+        assert_eq!(
+            ret_,
+            Some(&Value::from(
+                "&lt;a href=&#34;foo&#34;&gt;&#39;bar&#39; &amp; baz&lt;/a&gt;"
+            ))
+        );
 
-        let mut o = HashMap::new();
-        o.insert(String::from("foo"), vec![String::from("bar")]);
-        let col = Arc::new(Value::from(o));
-        let vals: Vec<Arc<Any>> = vec![col, varc!("foo"), varc!(0)];
-        let ret = index(&vals).unwrap();
+        let vals: Vec<Arc<Any>> = vec![varc!(23)];
+        let ret = html(&vals).unwrap();
         let ret_ = ret.downcast_ref::<Value>();
-        assert_eq!(ret_, Some(&Value::from("bar")));
+        assert_eq!(ret_, Some(&Value::from("23")));
+    }
 
-        let mut o = HashMap::new();
-        o.insert(String::from("foo"), String::from("bar"));
-        let col = Arc::new(Value::from(o));
-        let vals: Vec<Arc<Any>> = vec![col, varc!("foo2")];
-        let ret = index(&vals).unwrap();
+    #[test]
+    fn test_b64() {
+        let vals: Vec<Arc<Any>> = vec![varc!("äöü gtmpl\n")];
+        let ret = b64enc(&vals).unwrap();
         let ret_ = ret.downcast_ref::<Value>();
-        assert_eq!(ret_, Some(&Value::NoValue));
+        assert_eq!(ret_, Some(&Value::from("w6TDtsO8IGd0bXBsCg==")));
+
+        let vals: Vec<Arc<Any>> = vec![Arc::clone(&ret)];
+        let ret = b64dec(&vals).unwrap();
+        let ret_ = ret.downcast_ref::<Value>();
+        assert_eq!(ret_, Some(&Value::from("äöü gtmpl\n")));
+
+        let vals: Vec<Arc<Any>> = vec![varc!("")];
+        let ret = b64dec(&b64enc(&vals).map(|r| vec![r]).unwrap()).unwrap();
+        let ret_ = ret.downcast_ref::<Value>();
+        assert_eq!(ret_, Some(&Value::from("")));
+
+        let vals: Vec<Arc<Any>> = vec![varc!(42)];
+        let ret = b64enc(&vals).unwrap();
+        let ret_ = ret.downcast_ref::<Value>();
+        assert_eq!(ret_, Some(&Value::from("NDI=")));
+
+        let vals: Vec<Arc<Any>> = vec![varc!("not base64!")];
+        assert!(b64dec(&vals).unwrap_err().starts_with("invalid base64"));
+
+        let vals: Vec<Arc<Any>> = vec![varc!("/w==")];
+        assert!(b64dec(&vals).unwrap_err().starts_with("invalid UTF-8"));
+    }
+
+    #[test]
+    fn test_js() {
+        let vals: Vec<Arc<Any>> = vec![varc!("'a' \"b\" \\ <c>&d=e\nä")];
+        let ret = js(&vals).unwrap();
+        let ret_ = ret.downcast_ref::<Value>();
+        assert_eq!(
+            ret_,
+            Some(&Value::from(
+                r#"\'a\' \"b\" \\ \u003Cc\u003E\u0026d\u003De\u000Aä"#
+            ))
+        );
+
+        let vals: Vec<Arc<Any>> = vec![varc!(
+            "a\u{2028}b\u{2029}c\u{85}\u{A0}\u{200B}\u{E000}\u{7F}€ 😀"
+        )];
+        let ret = js(&vals).unwrap();
+        let ret_ = ret.downcast_ref::<Value>();
+        assert_eq!(
+            ret_,
+            Some(&Value::from(
+                "a\\u2028b\\u2029c\\u0085\\u00A0\\u200B\\uE000\u{7F}€ 😀"
+            ))
+        );
     }
 
     #[test]
@@ -828,5 +3305,16 @@ mod tests_mocked {
         let ret = has_prefix(&vals).unwrap();
         let ret_ = ret.downcast_ref::<Value>();
         assert_eq!(ret_, Some(&Value::from(true)));
+
+        let vals: Vec<Arc<Any>> = vec![varc!("foobar")];
+        assert_eq!(
+            has_prefix(&vals).err(),
+            Some(String::from("wrong number of args for has_prefix: want 2 got 1"))
+        );
+        let vals: Vec<Arc<Any>> = vec![varc!(1u32), varc!(2u32), varc!(3u32)];
+        assert_eq!(
+            add(&vals).err(),
+            Some(String::from("wrong number of args for add: want 2 got 3"))
+        );
     }
 }
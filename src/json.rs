@@ -0,0 +1,430 @@
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::iter::Peekable;
+use std::str::Chars;
+
+use gtmpl_value::Value;
+
+/// Serializes a value to JSON. Map and object keys are written in sorted order. With an
+/// `indent` each element is written on its own line, indented by `indent` per level.
+pub fn to_json(val: &Value, indent: Option<&str>) -> Result<String, String> {
+    let mut out = String::new();
+    write_value(&mut out, val, indent, 0)?;
+    Ok(out)
+}
+
+fn write_value(
+    out: &mut String,
+    val: &Value,
+    indent: Option<&str>,
+    depth: usize,
+) -> Result<(), String> {
+    match *val {
+        Value::Nil | Value::NoValue => out.push_str("null"),
+        Value::Bool(b) => out.push_str(if b { "true" } else { "false" }),
+        Value::Number(ref n) => {
+            if n.as_f64().map(|f| !f.is_finite()).unwrap_or(false) {
+                return Err(format!("unable to represent {} as json", n));
+            }
+            write!(out, "{}", n).map_err(|e| format!("{}", e))?;
+        }
+        Value::String(ref s) => write_string(out, s),
+        Value::Array(ref a) => {
+            if a.is_empty() {
+                out.push_str("[]");
+                return Ok(());
+            }
+            out.push('[');
+            for (i, v) in a.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                write_newline(out, indent, depth + 1);
+                write_value(out, v, indent, depth + 1)?;
+            }
+            write_newline(out, indent, depth);
+            out.push(']');
+        }
+        Value::Object(ref o) | Value::Map(ref o) => {
+            if o.is_empty() {
+                out.push_str("{}");
+                return Ok(());
+            }
+            let mut keys: Vec<&String> = o.keys().collect();
+            keys.sort();
+            out.push('{');
+            for (i, k) in keys.into_iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                write_newline(out, indent, depth + 1);
+                write_string(out, k);
+                out.push(':');
+                if indent.is_some() {
+                    out.push(' ');
+                }
+                write_value(out, &o[k], indent, depth + 1)?;
+            }
+            write_newline(out, indent, depth);
+            out.push('}');
+        }
+        Value::Function(_) => return Err(String::from("unable to represent a function as json")),
+    }
+    Ok(())
+}
+
+fn write_newline(out: &mut String, indent: Option<&str>, depth: usize) {
+    if let Some(indent) = indent {
+        out.push('\n');
+        for _ in 0..depth {
+            out.push_str(indent);
+        }
+    }
+}
+
+fn write_string(out: &mut String, s: &str) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+/// Converts a `serde_json::Value`. Numbers stay integers if they fit a `u64` or `i64`
+/// and objects become `Value::Map`s, like with `from_json`.
+#[cfg(feature = "serde_json")]
+pub fn from_serde_json(val: ::serde_json::Value) -> Value {
+    use serde_json::Value as Json;
+    match val {
+        Json::Null => Value::Nil,
+        Json::Bool(b) => Value::from(b),
+        Json::Number(n) => {
+            if let Some(u) = n.as_u64() {
+                Value::from(u)
+            } else if let Some(i) = n.as_i64() {
+                Value::from(i)
+            } else {
+                n.as_f64().map(Value::from).unwrap_or(Value::Nil)
+            }
+        }
+        Json::String(s) => Value::from(s),
+        Json::Array(a) => Value::Array(a.into_iter().map(from_serde_json).collect()),
+        Json::Object(o) => Value::Map(
+            o.into_iter()
+                .map(|(k, v)| (k, from_serde_json(v)))
+                .collect(),
+        ),
+    }
+}
+
+/// Parses JSON text into a value. Objects become `Value::Map`s.
+pub fn from_json(s: &str) -> Result<Value, String> {
+    let mut p = JsonParser {
+        chars: s.chars().peekable(),
+    };
+    let val = p.value()?;
+    p.skip_whitespace();
+    match p.chars.next() {
+        None => Ok(val),
+        Some(c) => Err(format!("invalid character {:?} after top-level value", c)),
+    }
+}
+
+struct JsonParser<'a> {
+    chars: Peekable<Chars<'a>>,
+}
+
+impl<'a> JsonParser<'a> {
+    fn skip_whitespace(&mut self) {
+        while let Some(&c) = self.chars.peek() {
+            if c != ' ' && c != '\t' && c != '\n' && c != '\r' {
+                break;
+            }
+            self.chars.next();
+        }
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), String> {
+        match self.chars.next() {
+            Some(c) if c == expected => Ok(()),
+            Some(c) => Err(format!("invalid character {:?}, expected {:?}", c, expected)),
+            None => Err(String::from("unexpected end of json input")),
+        }
+    }
+
+    fn literal(&mut self, lit: &str, val: Value) -> Result<Value, String> {
+        for c in lit.chars() {
+            self.expect(c)?;
+        }
+        Ok(val)
+    }
+
+    fn value(&mut self) -> Result<Value, String> {
+        self.skip_whitespace();
+        match self.chars.peek().cloned() {
+            Some('{') => self.object(),
+            Some('[') => self.array(),
+            Some('"') => self.string().map(Value::from),
+            Some('t') => self.literal("true", Value::from(true)),
+            Some('f') => self.literal("false", Value::from(false)),
+            Some('n') => self.literal("null", Value::Nil),
+            Some(c) if c == '-' || c.is_ascii_digit() => self.number(),
+            Some(c) => Err(format!("invalid character {:?} looking for beginning of value", c)),
+            None => Err(String::from("unexpected end of json input")),
+        }
+    }
+
+    fn object(&mut self) -> Result<Value, String> {
+        self.expect('{')?;
+        let mut map = HashMap::new();
+        self.skip_whitespace();
+        if self.chars.peek() == Some(&'}') {
+            self.chars.next();
+            return Ok(Value::Map(map));
+        }
+        loop {
+            self.skip_whitespace();
+            let key = self.string()?;
+            self.skip_whitespace();
+            self.expect(':')?;
+            let val = self.value()?;
+            map.insert(key, val);
+            self.skip_whitespace();
+            match self.chars.next() {
+                Some(',') => continue,
+                Some('}') => return Ok(Value::Map(map)),
+                Some(c) => return Err(format!("invalid character {:?} in object", c)),
+                None => return Err(String::from("unexpected end of json input")),
+            }
+        }
+    }
+
+    fn array(&mut self) -> Result<Value, String> {
+        self.expect('[')?;
+        let mut vec = vec![];
+        self.skip_whitespace();
+        if self.chars.peek() == Some(&']') {
+            self.chars.next();
+            return Ok(Value::Array(vec));
+        }
+        loop {
+            vec.push(self.value()?);
+            self.skip_whitespace();
+            match self.chars.next() {
+                Some(',') => continue,
+                Some(']') => return Ok(Value::Array(vec)),
+                Some(c) => return Err(format!("invalid character {:?} in array", c)),
+                None => return Err(String::from("unexpected end of json input")),
+            }
+        }
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        self.expect('"')?;
+        let mut s = String::new();
+        loop {
+            match self.chars.next() {
+                Some('"') => return Ok(s),
+                Some('\\') => {
+                    let c = match self.chars.next() {
+                        Some('"') => '"',
+                        Some('\\') => '\\',
+                        Some('/') => '/',
+                        Some('b') => '\u{8}',
+                        Some('f') => '\u{c}',
+                        Some('n') => '\n',
+                        Some('r') => '\r',
+                        Some('t') => '\t',
+                        Some('u') => self.unicode_escape()?,
+                        Some(c) => return Err(format!("invalid escape {:?} in string", c)),
+                        None => return Err(String::from("unexpected end of json input")),
+                    };
+                    s.push(c);
+                }
+                Some(c) if (c as u32) < 0x20 => {
+                    return Err(format!("invalid character {:?} in string", c))
+                }
+                Some(c) => s.push(c),
+                None => return Err(String::from("unexpected end of json input")),
+            }
+        }
+    }
+
+    fn hex4(&mut self) -> Result<u32, String> {
+        let mut n = 0;
+        for _ in 0..4 {
+            let d = self.chars
+                .next()
+                .and_then(|c| c.to_digit(16))
+                .ok_or_else(|| String::from("invalid unicode escape in string"))?;
+            n = n * 16 + d;
+        }
+        Ok(n)
+    }
+
+    fn unicode_escape(&mut self) -> Result<char, String> {
+        let mut n = self.hex4()?;
+        // Characters outside the BMP are written as a surrogate pair.
+        if (0xD800..0xDC00).contains(&n) {
+            self.expect('\\')?;
+            self.expect('u')?;
+            let low = self.hex4()?;
+            if !(0xDC00..0xE000).contains(&low) {
+                return Err(String::from("invalid surrogate pair in string"));
+            }
+            n = 0x10000 + ((n - 0xD800) << 10) + (low - 0xDC00);
+        }
+        ::std::char::from_u32(n).ok_or_else(|| String::from("invalid unicode escape in string"))
+    }
+
+    fn number(&mut self) -> Result<Value, String> {
+        let mut text = String::new();
+        while let Some(&c) = self.chars.peek() {
+            if !(c.is_ascii_digit() || c == '-' || c == '+' || c == '.' || c == 'e' || c == 'E') {
+                break;
+            }
+            text.push(c);
+            self.chars.next();
+        }
+        if let Ok(u) = text.parse::<u64>() {
+            return Ok(Value::from(u));
+        }
+        if let Ok(i) = text.parse::<i64>() {
+            return Ok(Value::from(i));
+        }
+        text.parse::<f64>()
+            .map(Value::from)
+            .map_err(|_| format!("invalid number {}", text))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn nested() -> Value {
+        let mut inner = HashMap::new();
+        inner.insert("z".to_owned(), Value::from(vec![1, 2]));
+        inner.insert("a".to_owned(), Value::Nil);
+        let mut outer = HashMap::new();
+        outer.insert("name".to_owned(), Value::from("foo \"bar\"\n"));
+        outer.insert("inner".to_owned(), Value::Map(inner));
+        outer.insert("empty".to_owned(), Value::Array(vec![]));
+        outer.insert("ok".to_owned(), Value::from(true));
+        Value::Object(outer)
+    }
+
+    #[test]
+    fn test_to_json() {
+        let json = to_json(&nested(), None).unwrap();
+        assert_eq!(
+            json,
+            r#"{"empty":[],"inner":{"a":null,"z":[1,2]},"name":"foo \"bar\"\n","ok":true}"#
+        );
+        assert_eq!(to_json(&Value::from(1.5), None).unwrap(), "1.5");
+        assert_eq!(to_json(&Value::from("\u{1}"), None).unwrap(), r#""\u0001""#);
+    }
+
+    #[test]
+    fn test_to_json_pretty() {
+        let json = to_json(&nested(), Some("  ")).unwrap();
+        let expected = r#"{
+  "empty": [],
+  "inner": {
+    "a": null,
+    "z": [
+      1,
+      2
+    ]
+  },
+  "name": "foo \"bar\"\n",
+  "ok": true
+}"#;
+        assert_eq!(json, expected);
+    }
+
+    #[test]
+    fn test_to_json_deterministic() {
+        let first = to_json(&nested(), None).unwrap();
+        for _ in 0..10 {
+            assert_eq!(to_json(&nested(), None).unwrap(), first);
+        }
+    }
+
+    #[test]
+    fn test_from_json() {
+        let val = from_json(r#" {"name": "foo", "list": [1, -2, 1.5, true, null], "o": {}} "#);
+        let mut o = HashMap::new();
+        o.insert("name".to_owned(), Value::from("foo"));
+        o.insert(
+            "list".to_owned(),
+            Value::Array(vec![
+                Value::from(1u64),
+                Value::from(-2i64),
+                Value::from(1.5),
+                Value::from(true),
+                Value::Nil,
+            ]),
+        );
+        o.insert("o".to_owned(), Value::Map(HashMap::new()));
+        assert_eq!(val, Ok(Value::Map(o)));
+
+        let val = from_json(r#""a\"\n\u00e9\ud83d\ude00""#);
+        assert_eq!(val, Ok(Value::from("a\"\n\u{e9}\u{1f600}")));
+
+        let val = from_json("[-1.5, -0.25e1]").unwrap();
+        assert_eq!(to_json(&val, None), Ok(String::from("[-1.5,-2.5]")));
+    }
+
+    #[test]
+    fn test_from_json_err() {
+        assert!(from_json("").is_err());
+        assert!(from_json("{").is_err());
+        assert!(from_json(r#"{"a" 1}"#).is_err());
+        assert!(from_json("[1,]").is_err());
+        assert!(from_json("tru").is_err());
+        assert!(from_json("1 2").is_err());
+        assert!(from_json("\"\n\"").is_err());
+    }
+
+    #[cfg(feature = "serde_json")]
+    #[test]
+    fn test_from_serde_json() {
+        let json: ::serde_json::Value = ::serde_json::from_str(
+            r#"{"name": "foo", "list": [1, -2, 1.5, true, null], "o": {}}"#,
+        ).unwrap();
+        let mut o = HashMap::new();
+        o.insert("name".to_owned(), Value::from("foo"));
+        o.insert(
+            "list".to_owned(),
+            Value::Array(vec![
+                Value::from(1u64),
+                Value::from(-2i64),
+                Value::from(1.5),
+                Value::from(true),
+                Value::Nil,
+            ]),
+        );
+        o.insert("o".to_owned(), Value::Map(HashMap::new()));
+        assert_eq!(from_serde_json(json), Value::Map(o));
+    }
+
+    #[test]
+    fn test_json_round_trip() {
+        let json = to_json(&nested(), None).unwrap();
+        let val = from_json(&json).unwrap();
+        assert_eq!(to_json(&val, None).unwrap(), json);
+        let pretty = to_json(&nested(), Some("  ")).unwrap();
+        assert_eq!(from_json(&pretty), Ok(val));
+    }
+}
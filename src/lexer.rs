@@ -7,10 +7,11 @@ use std::sync::mpsc::{channel, Receiver, Sender};
 
 type Pos = usize;
 
-static LEFT_TRIM_MARKER: &str = "- ";
-static RIGHT_TRIM_MARKER: &str = " -";
-static LEFT_DELIM: &str = "{{";
-static RIGHT_DELIM: &str = "}}";
+// A trim marker is a '-' separated from the delimiter's content by a space.
+static TRIM_MARKER: char = '-';
+static TRIM_MARKER_LEN: usize = 2;
+pub(crate) static LEFT_DELIM: &str = "{{";
+pub(crate) static RIGHT_DELIM: &str = "}}";
 static LEFT_COMMENT: &str = "/*";
 static RIGHT_COMMENT: &str = "*/";
 
@@ -19,6 +20,8 @@ lazy_static! {
         let mut m = HashMap::new();
         m.insert(".", ItemType::ItemDot);
         m.insert("block", ItemType::ItemBlock);
+        m.insert("break", ItemType::ItemBreak);
+        m.insert("continue", ItemType::ItemContinue);
         m.insert("define", ItemType::ItemDefine);
         m.insert("end", ItemType::ItemEnd);
         m.insert("else", ItemType::ItemElse);
@@ -38,6 +41,7 @@ pub enum ItemType {
     ItemChar,         // printable ASCII character; grab bag for comma etc.
     ItemCharConstant, // character constant
     ItemComplex,      // complex constant (1+2i); imaginary is just a number
+    ItemAssign,       // equals ('=') introducing an assignment
     ItemColonEquals,  // colon-equals (':=') introducing a declaration
     ItemEOF,
     ItemField,      // alphanumeric identifier starting with '.'
@@ -56,6 +60,8 @@ pub enum ItemType {
     // Keywords, appear after all the rest.
     ItemKeyword,  // used only to delimit the keywords
     ItemBlock,    // block keyword
+    ItemBreak,    // break keyword
+    ItemContinue, // continue keyword
     ItemDot,      // the cursor, spelled '.'
     ItemDefine,   // define keyword
     ItemElse,     // else keyword
@@ -104,6 +110,8 @@ pub struct Lexer {
 
 struct LexerStateMachine {
     input: String,              // the string being scanned
+    left_delim: String,         // start of action
+    right_delim: String,        // end of action
     state: State,               // the next lexing function to enter
     pos: Pos,                   // current position in the input
     start: Pos,                 // start position of this item
@@ -155,10 +163,14 @@ impl Iterator for Lexer {
 }
 
 impl Lexer {
-    pub fn new(input: String) -> Lexer {
+    /// Creates a lexer using the given action delimiters. Empty delimiters fall
+    /// back to the default `{{` and `}}`.
+    pub fn new(input: String, left: &str, right: &str) -> Lexer {
         let (tx, rx) = channel();
         let mut l = LexerStateMachine {
             input: input,
+            left_delim: if left.is_empty() { LEFT_DELIM } else { left }.to_owned(),
+            right_delim: if right.is_empty() { RIGHT_DELIM } else { right }.to_owned(),
             state: State::LexText,
             pos: 0,
             start: 0,
@@ -231,7 +243,7 @@ impl LexerStateMachine {
     }
 
     fn backup(&mut self) {
-        self.pos -= 1;
+        self.pos -= self.width;
         if self.width == 1
             && self.input[self.pos..]
                 .chars()
@@ -293,12 +305,12 @@ impl LexerStateMachine {
 
     fn lex_text(&mut self) -> State {
         self.width = 0;
-        let x = self.input[self.pos..].find(&LEFT_DELIM);
+        let x = self.input[self.pos..].find(&self.left_delim);
         match x {
             Some(x) => {
                 self.pos += x;
-                let ld = self.pos + LEFT_DELIM.len();
-                let trim = if self.input[ld..].starts_with(LEFT_TRIM_MARKER) {
+                let ld = self.pos + self.left_delim.len();
+                let trim = if has_left_trim_marker(&self.input[ld..]) {
                     rtrim_len(&self.input[self.start..self.pos])
                 } else {
                     0
@@ -323,19 +335,21 @@ impl LexerStateMachine {
     }
 
     fn at_right_delim(&mut self) -> (bool, bool) {
-        if self.input[self.pos..].starts_with(&RIGHT_DELIM) {
+        if self.input[self.pos..].starts_with(&self.right_delim) {
             return (true, false);
         }
-        if self.input[self.pos..].starts_with(&format!("{}{}", RIGHT_TRIM_MARKER, RIGHT_DELIM)) {
+        if has_right_trim_marker(&self.input[self.pos..])
+            && self.input[self.pos + TRIM_MARKER_LEN..].starts_with(&self.right_delim)
+        {
             return (true, true);
         }
         (false, false)
     }
 
     fn lex_left_delim(&mut self) -> State {
-        self.pos += LEFT_DELIM.len();
-        let trim = self.input[self.pos..].starts_with(LEFT_TRIM_MARKER);
-        let after_marker = if trim { LEFT_TRIM_MARKER.len() } else { 0 };
+        self.pos += self.left_delim.len();
+        let trim = has_left_trim_marker(&self.input[self.pos..]);
+        let after_marker = if trim { TRIM_MARKER_LEN } else { 0 };
         if self.input[(self.pos + after_marker)..].starts_with(LEFT_COMMENT) {
             self.pos += after_marker;
             self.ignore();
@@ -366,10 +380,10 @@ impl LexerStateMachine {
         }
 
         if trim {
-            self.pos += RIGHT_TRIM_MARKER.len();
+            self.pos += TRIM_MARKER_LEN;
         }
 
-        self.pos += RIGHT_DELIM.len();
+        self.pos += self.right_delim.len();
 
         if trim {
             self.pos += ltrim_len(&self.input[self.pos..]);
@@ -380,12 +394,12 @@ impl LexerStateMachine {
     }
 
     fn lex_right_delim(&mut self) -> State {
-        let trim = self.input[self.pos..].starts_with(RIGHT_TRIM_MARKER);
+        let trim = has_right_trim_marker(&self.input[self.pos..]);
         if trim {
-            self.pos += RIGHT_TRIM_MARKER.len();
+            self.pos += TRIM_MARKER_LEN;
             self.ignore();
         }
-        self.pos += RIGHT_DELIM.len();
+        self.pos += self.right_delim.len();
         self.emit(ItemType::ItemRightDelim);
         if trim {
             self.pos += ltrim_len(&self.input[self.pos..]);
@@ -431,12 +445,17 @@ impl LexerStateMachine {
                         }
                         _ => self.errorf("expected :="),
                     },
+                    '=' => {
+                        self.emit(ItemType::ItemAssign);
+                        State::LexInsideAction
+                    }
                     '|' => {
                         self.emit(ItemType::ItemPipe);
                         State::LexInsideAction
                     }
                     '.' => match self.input[self.pos..].chars().next() {
-                        Some('0'...'9') => {
+                        // A numeric field directly following a field is an array index.
+                        Some('0'...'9') if !self.after_field() => {
                             self.backup();
                             State::LexNumber
                         }
@@ -463,10 +482,25 @@ impl LexerStateMachine {
     }
 
     fn lex_space(&mut self) -> State {
+        let mut spaces = 1;
         while self.peek()
             .and_then(|c| Some(c.is_whitespace()))
             .unwrap_or(false)
-        {}
+        {
+            self.next();
+            spaces += 1;
+        }
+        // The last space may belong to the trim marker of a closing delimiter.
+        let last = self.pos - 1;
+        if self.input.is_char_boundary(last)
+            && has_right_trim_marker(&self.input[last..])
+            && self.input[last + TRIM_MARKER_LEN..].starts_with(&self.right_delim)
+        {
+            self.pos = last;
+            if spaces == 1 {
+                return State::LexRightDelim;
+            }
+        }
         self.emit(ItemType::ItemSpace);
         State::LexInsideAction
     }
@@ -513,16 +547,24 @@ impl LexerStateMachine {
         State::LexInsideAction
     }
 
+    fn after_field(&self) -> bool {
+        self.input[..self.pos - 1]
+            .chars()
+            .next_back()
+            .map(|c| c.is_alphanumeric() || c == '_' || c == '$')
+            .unwrap_or(false)
+    }
+
     fn at_terminator(&mut self) -> bool {
         match self.peek() {
             Some(c) => {
                 match c {
                     '.' | ',' | '|' | ':' | ')' | '(' | ' ' | '\t' | '\r' | '\n' => true,
                     // this is what golang does to detect a delimiter
-                    _ => RIGHT_DELIM.starts_with(c),
+                    _ => self.right_delim.starts_with(c),
                 }
             }
-            None => false,
+            None => true,
         }
     }
 
@@ -621,16 +663,27 @@ impl LexerStateMachine {
     }
 }
 
+// Like Go, only ASCII spaces are trimmed.
+fn is_space(c: char) -> bool {
+    c == ' ' || c == '\t' || c == '\r' || c == '\n'
+}
+
+fn has_left_trim_marker(s: &str) -> bool {
+    let mut chars = s.chars();
+    chars.next() == Some(TRIM_MARKER) && chars.next().map(is_space).unwrap_or(false)
+}
+
+fn has_right_trim_marker(s: &str) -> bool {
+    let mut chars = s.chars();
+    chars.next().map(is_space).unwrap_or(false) && chars.next() == Some(TRIM_MARKER)
+}
+
 fn rtrim_len(s: &str) -> usize {
-    match s.rfind(|c: char| !c.is_whitespace()) {
-        Some(i) => s.len() - 1 - i,
-        None => s.len(),
-    }
+    s.len() - s.trim_end_matches(is_space).len()
 }
 
 fn ltrim_len(s: &str) -> usize {
-    let l = s.len();
-    s.find(|c: char| !c.is_whitespace()).unwrap_or(l)
+    s.len() - s.trim_start_matches(is_space).len()
 }
 
 #[cfg(test)]
@@ -640,7 +693,7 @@ mod tests {
     use super::*;
     #[test]
     fn lexer_run() {
-        let mut l = Lexer::new("abc".to_owned());
+        let mut l = Lexer::new("abc".to_owned(), "", "");
         let i1 = l.next().unwrap();
         assert_eq!(i1.typ, ItemType::ItemText);
         assert_eq!(&i1.val, "abc");
@@ -649,7 +702,7 @@ mod tests {
     #[test]
     fn lex_simple() {
         let s = r#"something {{ if eq "foo" "bar" }}"#;
-        let l = Lexer::new(s.to_owned());
+        let l = Lexer::new(s.to_owned(), "", "");
         let items = l.collect::<Vec<_>>();
         assert_eq!(items.len(), 13);
     }
@@ -657,7 +710,7 @@ mod tests {
     #[test]
     fn test_input() {
         let s = r#"something {{ .foo }}"#;
-        let l = Lexer::new(s.to_owned());
+        let l = Lexer::new(s.to_owned(), "", "");
         let items = l.collect::<Vec<_>>();
         let s_ = items.into_iter().map(|i| i.val).join("");
         assert_eq!(s_, s);
@@ -666,27 +719,155 @@ mod tests {
     #[test]
     fn test_underscore() {
         let s = r#"something {{ .foo_bar }}"#;
-        let l = Lexer::new(s.to_owned());
+        let l = Lexer::new(s.to_owned(), "", "");
         let items = l.collect::<Vec<_>>();
         let s_ = items.into_iter().map(|i| i.val).join("");
         assert_eq!(s_, s);
     }
 
+    #[test]
+    fn test_unicode_field() {
+        let s = r#"{{ .café.ünï_2 $größe .日本 }}"#;
+        let l = Lexer::new(s.to_owned(), "", "");
+        let items = l.filter(|i| i.typ != ItemType::ItemSpace).collect::<Vec<_>>();
+        let typs = items.iter().map(|i| i.typ.clone()).collect::<Vec<_>>();
+        let vals = items.iter().map(|i| i.val.as_str()).collect::<Vec<_>>();
+        assert_eq!(
+            &typs[1..5],
+            &[
+                ItemType::ItemField,
+                ItemType::ItemField,
+                ItemType::ItemVariable,
+                ItemType::ItemField,
+            ]
+        );
+        assert_eq!(&vals[1..5], &[".café", ".ünï_2", "$größe", ".日本"]);
+    }
+
+    #[test]
+    fn test_numeric_field() {
+        let s = r#"{{ .foo.0.bar 1 .5 }}"#;
+        let l = Lexer::new(s.to_owned(), "", "");
+        let items = l.map(|i| i.typ).collect::<Vec<_>>();
+        assert_eq!(
+            &items[2..9],
+            &[
+                ItemType::ItemField,
+                ItemType::ItemField,
+                ItemType::ItemField,
+                ItemType::ItemSpace,
+                ItemType::ItemNumber,
+                ItemType::ItemSpace,
+                ItemType::ItemNumber,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_assign() {
+        let s = r#"{{ $x := 1 }}{{ $x = 2 }}"#;
+        let l = Lexer::new(s.to_owned(), "", "");
+        let items = l.filter(|i| i.typ != ItemType::ItemSpace)
+            .map(|i| i.typ)
+            .collect::<Vec<_>>();
+        assert_eq!(items[2], ItemType::ItemColonEquals);
+        assert_eq!(items[7], ItemType::ItemAssign);
+    }
+
+    #[test]
+    fn test_negative_number() {
+        let s = r#"{{-3}}{{- -3 -}}{{ print 10 -3 -1.5 }}"#;
+        let l = Lexer::new(s.to_owned(), "", "");
+        let numbers = l.filter(|i| i.typ == ItemType::ItemNumber)
+            .map(|i| i.val)
+            .collect::<Vec<_>>();
+        assert_eq!(numbers, &["-3", "-3", "10", "-3", "-1.5"]);
+    }
+
+    #[test]
+    fn test_space() {
+        let s = "{{  . \t }}";
+        let l = Lexer::new(s.to_owned(), "", "");
+        let items = l.map(|i| (i.typ, i.val)).collect::<Vec<_>>();
+        assert_eq!(
+            items,
+            vec![
+                (ItemType::ItemLeftDelim, "{{".to_owned()),
+                (ItemType::ItemSpace, "  ".to_owned()),
+                (ItemType::ItemDot, ".".to_owned()),
+                (ItemType::ItemSpace, " \t ".to_owned()),
+                (ItemType::ItemRightDelim, "}}".to_owned()),
+                (ItemType::ItemEOF, "".to_owned()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_unclosed_action() {
+        for s in &["{{ . ", "{{ .", "{{ "] {
+            let l = Lexer::new((*s).to_owned(), "", "");
+            let last = l.last().unwrap();
+            assert_eq!(last.typ, ItemType::ItemError);
+            assert_eq!(last.val, "unclosed action");
+        }
+    }
+
     #[test]
     fn test_trim() {
         let s = r#"something {{- .foo -}} 2000"#;
-        let l = Lexer::new(s.to_owned());
+        let l = Lexer::new(s.to_owned(), "", "");
         let items = l.collect::<Vec<_>>();
         let s_ = items.into_iter().map(|i| i.val).join("");
         assert_eq!(s_, r#"something{{.foo}}2000"#);
     }
 
+    #[test]
+    fn test_trim_whitespace() {
+        let trimmed = |s: &str| {
+            Lexer::new(s.to_owned(), "", "")
+                .filter(|i| i.typ != ItemType::ItemSpace)
+                .map(|i| i.val)
+                .join("")
+        };
+        assert_eq!(trimmed("a \t\r\n\r\n\n {{- .x -}} \t\r\n\n\n b"), "a{{.x}}b");
+        assert_eq!(trimmed("a\n\n{{-\t.x\t-}}\n\nb"), "a{{.x}}b");
+        assert_eq!(trimmed("a\n\t{{- .x  \t -}}\n\tb"), "a{{.x}}b");
+        assert_eq!(trimmed("a\r\n\t{{- /* c */ -}}\r\n\tb"), "ab");
+        assert_eq!(trimmed("a \n\n{{- /* c */}}\n\n{{- .x}}"), "a{{.x}}");
+        // Only ASCII spaces are trimmed.
+        assert_eq!(trimmed("a\u{a0} {{- .x -}} \u{a0}b"), "a\u{a0}{{.x}}\u{a0}b");
+        // Without a space the minus is part of the action.
+        assert_eq!(trimmed("a {{-3}} b"), "a {{-3}} b");
+    }
+
     #[test]
     fn test_comment() {
         let s = r#"something {{- /* foo */ -}} 2000"#;
-        let l = Lexer::new(s.to_owned());
+        let l = Lexer::new(s.to_owned(), "", "");
         let items = l.collect::<Vec<_>>();
         let s_ = items.into_iter().map(|i| i.val).join("");
         assert_eq!(s_, r#"something2000"#);
     }
+
+    #[test]
+    fn test_comment_multiline() {
+        let s = "a {{/* foo\n }} {{ bar\n */}} b {{- /* \n */}} c {{/* */ -}}\n d";
+        let l = Lexer::new(s.to_owned(), "", "");
+        let items = l.collect::<Vec<_>>();
+        let s_ = items.into_iter().map(|i| i.val).join("");
+        assert_eq!(s_, "a  b c d");
+    }
+
+    #[test]
+    fn test_comment_err() {
+        let s = "a {{/* foo }}";
+        let l = Lexer::new(s.to_owned(), "", "");
+        let items = l.collect::<Vec<_>>();
+        assert_eq!(items.last().map(|i| &i.typ), Some(&ItemType::ItemError));
+
+        let s = "a {{/* foo */ . }}";
+        let l = Lexer::new(s.to_owned(), "", "");
+        let items = l.collect::<Vec<_>>();
+        assert_eq!(items.last().map(|i| &i.typ), Some(&ItemType::ItemError));
+    }
 }
@@ -7,6 +7,21 @@
 //! let output = gtmpl::template("Finally! Some {{ . }} for Rust", "gtmpl");
 //! assert_eq!(&output.unwrap(), "Finally! Some gtmpl for Rust");
 //! ```
+//!
+//! ## Truthiness
+//!
+//! Like in Go, `if`, `with`, `and`, `or` and `not` treat `false`, `0`, `""`, `nil`,
+//! missing values and empty arrays and maps as false and anything else as true. So
+//! `with` skips its body for a value of `0` too. To use a value regardless of its
+//! truth, bind it to a variable instead:
+//!
+//! ```rust
+//! let output = gtmpl::template(
+//!     "{{ with . }}{{ . }}{{ else }}none{{ end }} {{ $count := . }}{{ $count }}",
+//!     0,
+//! );
+//! assert_eq!(&output.unwrap(), "none 0");
+//! ```
 #[cfg_attr(feature = "cargo-clippy", allow(useless_attribute))]
 #[allow(unused_imports)]
 #[macro_use]
@@ -15,11 +30,14 @@ extern crate gtmpl_derive;
 #[allow(unused_imports)]
 #[macro_use]
 extern crate gtmpl_value;
+extern crate glob;
 extern crate itertools;
 #[macro_use]
 extern crate lazy_static;
+#[cfg(feature = "serde_json")]
+extern crate serde_json;
 mod lexer;
-mod node;
+pub mod node;
 mod parse;
 #[doc(inlne)]
 pub mod funcs;
@@ -28,10 +46,14 @@ mod exec;
 mod utils;
 mod print_verb;
 mod printf;
+mod json;
 
 #[doc(inline)]
 pub use template::Template;
 
+#[doc(inline)]
+pub use parse::Tree;
+
 #[doc(inline)]
 pub use exec::Context;
 
@@ -44,14 +66,41 @@ pub use gtmpl_value::from_value;
 pub use gtmpl_value::Value;
 
 /// Provides simple basic templating given just a template sting and context.
+/// Parse and execution errors are both returned as `Err`.
 ///
 /// ## Example
 /// ```rust
 /// let output = gtmpl::template("Finally! Some {{ . }} for Rust", "gtmpl");
 /// assert_eq!(&output.unwrap(), "Finally! Some gtmpl for Rust");
+///
+/// let output = gtmpl::template("Finally! Some {{ . for Rust", "gtmpl");
+/// assert!(output.is_err());
 /// ```
 pub fn template<T: Into<Value>>(template_str: &str, context: T) -> Result<String, String> {
     let mut tmpl = Template::default();
     tmpl.parse(template_str)?;
-    tmpl.render(&Context::from(context)?)
+    tmpl.render(&Context::from(context))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_template() {
+        let output = template("{{ . }}", 23);
+        assert_eq!(output, Ok(String::from("23")));
+
+        let output = template("no actions", Value::Nil);
+        assert_eq!(output, Ok(String::from("no actions")));
+    }
+
+    #[test]
+    fn test_template_err() {
+        let output = template("{{ if . }}", 23);
+        assert!(output.is_err());
+
+        let output = template("{{ .foo }}", 23);
+        assert!(output.is_err());
+    }
 }
@@ -1,3 +1,4 @@
+//! Nodes of a parsed template, to walk its structure e.g. for linting.
 use std::fmt::{Display, Error, Formatter};
 use std::sync::Arc;
 
@@ -81,6 +82,10 @@ nodes!(
     String,
     EndNode,
     End,
+    BreakNode,
+    Break,
+    ContinueNode,
+    Continue,
     ElseNode,
     Else,
     IfNode,
@@ -133,8 +138,10 @@ impl Nodes {
     pub fn is_empty_tree(&self) -> Result<bool, String> {
         match *self {
             Nodes::List(ref n) => n.is_empty_tree(),
-            Nodes::Text(ref n) => Ok(n.text.is_empty()),
+            Nodes::Text(ref n) => Ok(n.text.trim().is_empty()),
             Nodes::Action(_)
+            | Nodes::Break(_)
+            | Nodes::Continue(_)
             | Nodes::If(_)
             | Nodes::Range(_)
             | Nodes::Template(_)
@@ -206,6 +213,7 @@ impl Display for TextNode {
 
 node!(
     PipeNode {
+        is_assign: bool,
         decl: Vec<VariableNode>,
         cmds: Vec<CommandNode>
     }
@@ -217,6 +225,7 @@ impl PipeNode {
             typ: NodeType::Pipe,
             tr,
             pos,
+            is_assign: false,
             decl,
             cmds: vec![],
         }
@@ -229,7 +238,8 @@ impl PipeNode {
 
 impl Display for PipeNode {
     fn fmt(&self, f: &mut Formatter) -> Result<(), Error> {
-        write!(f, "{} := ", self.decl.iter().join(", "))
+        let op = if self.is_assign { "=" } else { ":=" };
+        write!(f, "{} {} ", self.decl.iter().join(", "), op)
             .and_then(|_| write!(f, "{}", self.cmds.iter().join(" | ")))
     }
 }
@@ -427,7 +437,11 @@ impl ChainNode {
         }
     }
 
-    pub fn add(&mut self, val: String) {
+    pub fn add(&mut self, mut val: String) {
+        // Fields are stored without their leading period.
+        if val.starts_with('.') {
+            val.remove(0);
+        }
         self.field.push(val);
     }
 }
@@ -441,8 +455,7 @@ impl Display for ChainNode {
             return Err(e);
         }
         for field in &self.field {
-            // fields are prefixed with .
-            if let Err(e) = write!(f, "{}", field) {
+            if let Err(e) = write!(f, ".{}", field) {
                 return Err(e);
             }
         }
@@ -640,6 +653,42 @@ impl Display for EndNode {
     }
 }
 
+node!(BreakNode {});
+
+impl BreakNode {
+    pub fn new(tr: TreeId, pos: Pos) -> BreakNode {
+        BreakNode {
+            typ: NodeType::Break,
+            tr,
+            pos,
+        }
+    }
+}
+
+impl Display for BreakNode {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), Error> {
+        write!(f, "{{{{break}}}}")
+    }
+}
+
+node!(ContinueNode {});
+
+impl ContinueNode {
+    pub fn new(tr: TreeId, pos: Pos) -> ContinueNode {
+        ContinueNode {
+            typ: NodeType::Continue,
+            tr,
+            pos,
+        }
+    }
+}
+
+impl Display for ContinueNode {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), Error> {
+        write!(f, "{{{{continue}}}}")
+    }
+}
+
 node!(ElseNode {});
 
 impl ElseNode {
@@ -798,4 +847,28 @@ mod tests {
         let t1 = EndNode::new(1, 0);
         assert_eq!(t1.to_string(), "{{end}}");
     }
+
+    #[test]
+    fn test_negative_number() {
+        let n = NumberNode::new(1, 0, "-3".to_owned(), &ItemType::ItemNumber).unwrap();
+        assert!(n.is_i64);
+        assert!(!n.is_u64);
+        assert_eq!(*n.value, Value::from(-3i64));
+
+        let n = NumberNode::new(1, 0, "-1.5".to_owned(), &ItemType::ItemNumber).unwrap();
+        assert!(n.is_f64);
+        assert!(!n.is_i64);
+        assert!(!n.is_u64);
+        assert_eq!(n.text, "-1.5");
+        let f = match *n.value {
+            Value::Number(ref f) => f.as_f64(),
+            _ => None,
+        };
+        assert_eq!(f, Some(-1.5));
+        assert_eq!(n.value.to_string(), "-1.5");
+
+        let n = NumberNode::new(1, 0, "-0".to_owned(), &ItemType::ItemNumber).unwrap();
+        assert!(n.is_u64);
+        assert_eq!(*n.value, Value::from(0u64));
+    }
 }
@@ -1,39 +1,58 @@
 use std::collections::{HashMap, VecDeque};
+use std::mem;
+use std::sync::Arc;
 
 use lexer::{Item, ItemType, Lexer};
 use node::*;
 use utils::*;
 use gtmpl_value::Func;
 
+// Maximum nesting of control structures, blocks and parenthesized pipelines. Parsing and
+// executing recurse per level, so deeper templates are rejected instead of overflowing a
+// 2MB thread stack.
+const MAX_PARSE_DEPTH: usize = 100;
+
+// Maximum number of `else if` and `else with` links on the way to any node. Chains are
+// parsed and executed in a loop, but cloning, printing and dropping the parsed tree
+// still recurse per link.
+const MAX_ELSE_CHAIN: usize = 250;
+
 pub struct Parser<'a> {
-    name: &'a str,
-    text: &'a str,
+    name: String,
+    text: Arc<str>,
     pub funcs: HashMap<&'a str, Func>,
     lex: Option<Lexer>,
     line: usize,
     token: VecDeque<Item>,
     peek_count: usize,
     pub tree_ids: HashMap<TreeId, String>,
-    pub tree_set: HashMap<String, Tree<'a>>,
+    pub tree_set: HashMap<String, Tree>,
     tree_id: TreeId,
-    tree: Option<Tree<'a>>,
-    tree_stack: VecDeque<Tree<'a>>,
+    tree: Option<Tree>,
+    tree_stack: VecDeque<Tree>,
     max_tree_id: TreeId,
+    range_depth: usize,
+    depth: usize,
+    chain_depth: usize,
 }
 
-pub struct Tree<'a> {
+/// A parsed template. Its `root` node can be walked to inspect the template without
+/// executing it.
+#[derive(Clone)]
+pub struct Tree {
     name: String,
     id: TreeId,
-    parse_name: &'a str,
+    parse_name: String,
+    text: Arc<str>,
     pub root: Option<Nodes>,
     vars: Vec<String>,
 }
 
 impl<'a> Parser<'a> {
-    pub fn new(name: &'a str) -> Parser<'a> {
+    pub fn new(name: String) -> Parser<'a> {
         Parser {
             name,
-            text: "",
+            text: Arc::from(""),
             funcs: HashMap::new(),
             lex: None,
             line: 0,
@@ -45,35 +64,63 @@ impl<'a> Parser<'a> {
             tree: None,
             tree_stack: VecDeque::new(),
             max_tree_id: 0,
+            range_depth: 0,
+            depth: 0,
+            chain_depth: 0,
         }
     }
 }
 
-impl<'a> Tree<'a> {
-    fn new(name: String, id: TreeId) -> Tree<'a> {
+impl Tree {
+    fn new(name: String, id: TreeId) -> Tree {
         Tree {
             name,
             id,
-            parse_name: "",
+            parse_name: String::new(),
+            text: Arc::from(""),
             root: None,
             vars: vec![],
         }
     }
 
-    pub fn pop_vars(&mut self, n: usize) {
+    /// Returns the name of the template.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn is_empty(&self) -> Result<bool, String> {
+        match self.root {
+            Some(ref r) => r.is_empty_tree(),
+            None => Ok(true),
+        }
+    }
+
+    /// Returns the location of a node as `parse_name:line`, used to give context to errors.
+    pub fn error_context(&self, node: &Nodes) -> String {
+        let line = 1 + self.text
+            .bytes()
+            .take(node.pos())
+            .filter(|&b| b == b'\n')
+            .count();
+        format!("{}:{}", self.parse_name, line)
+    }
+
+    pub(crate) fn pop_vars(&mut self, n: usize) {
         self.vars.truncate(n);
     }
 }
 
 pub fn parse<'a>(
-    name: &'a str,
-    text: &'a str,
+    name: &str,
+    text: &str,
+    left_delim: &str,
+    right_delim: &str,
     funcs: HashMap<&'a str, Func>,
 ) -> Result<Parser<'a>, String> {
-    let mut p = Parser::new(name);
-    p.text = text;
+    let mut p = Parser::new(name.to_owned());
+    p.text = Arc::from(text);
     p.funcs = funcs;
-    p.lex = Some(Lexer::new(text.to_owned()));
+    p.lex = Some(Lexer::new(text.to_owned(), left_delim, right_delim));
     p.parse_tree()?;
     Ok(p)
 }
@@ -142,13 +189,14 @@ impl<'a> Parser<'a> {
         self.error(&format!("unexpected end in {}", context))
     }
 
-    fn start_parse(&mut self, name: String, id: TreeId, parse_name: &'a str) {
+    fn start_parse(&mut self, name: String, id: TreeId, parse_name: String) {
         if let Some(t) = self.tree.take() {
             self.tree_stack.push_back(t);
         }
         self.tree_id = id;
         let mut t = Tree::new(name, id);
         t.parse_name = parse_name;
+        t.text = Arc::clone(&self.text);
         self.tree = Some(t);
     }
 
@@ -161,15 +209,16 @@ impl<'a> Parser<'a> {
 
     // top level parser
     fn parse_tree(&mut self) -> Result<(), String> {
-        let name = self.name.to_owned();
-        let parse_name = self.name;
+        let name = self.name.clone();
+        let parse_name = self.name.clone();
+        self.max_tree_id = 1;
         self.start_parse(name, 1, parse_name);
         self.parse()?;
         self.stop_parse()?;
         Ok(())
     }
 
-    fn add_tree(&mut self, name: String, t: Tree<'a>) {
+    fn add_tree(&mut self, name: String, t: Tree) {
         self.tree_ids.insert(t.id, name.clone());
         self.tree_set.insert(name, t);
     }
@@ -182,7 +231,7 @@ impl<'a> Parser<'a> {
         let name = if let Some(t) = self.tree.as_ref() {
             &t.parse_name
         } else {
-            self.name
+            &self.name
         };
         format!("template: {}:{}:{}", name, self.line, msg)
     }
@@ -210,14 +259,13 @@ impl<'a> Parser<'a> {
         let tree = self.tree.take().ok_or_else(|| self.error_msg("no tree"))?;
         if let Some(t) = self.tree_set.get(tree.name.as_str()) {
             if let Some(ref r) = t.root {
-                match r.is_empty_tree() {
-                    Err(e) => return Err(e),
-                    Ok(false) => {
-                        let err =
-                            format!("template multiple definitions of template {}", &tree.name);
-                        return self.error(&err);
+                if !r.is_empty_tree()? {
+                    // An empty redefinition keeps the existing template.
+                    if tree.is_empty()? {
+                        return Ok(());
                     }
-                    Ok(true) => {}
+                    let err = format!("template multiple definitions of template {}", &tree.name);
+                    return self.error(&err);
                 }
             }
         }
@@ -293,12 +341,13 @@ impl<'a> Parser<'a> {
 
     fn parse_definition(&mut self) -> Result<(), String> {
         let context = "define clause";
-        let id = self.tree_id;
-        let parse_name = self.name;
+        let parse_name = self.name.clone();
         let token = self.next_non_space_must(context)?;
         let name = self.parse_template_name(&token, context)?;
         self.expect(&ItemType::ItemRightDelim, "define end")?;
-        self.start_parse(name, id + 1, parse_name);
+        self.max_tree_id += 1;
+        let tree_id = self.max_tree_id;
+        self.start_parse(name, tree_id, parse_name);
         let (list, end) = self.item_list()?;
         if *end.typ() != NodeType::End {
             return Err(format!("unexpected {} in {}", end, context));
@@ -309,6 +358,19 @@ impl<'a> Parser<'a> {
         self.stop_parse()
     }
 
+    // Enters a nesting level, to be left with `leave` once it is parsed.
+    fn enter(&mut self) -> Result<(), String> {
+        if self.depth >= MAX_PARSE_DEPTH {
+            return self.error("exceeded maximum nesting depth");
+        }
+        self.depth += 1;
+        Ok(())
+    }
+
+    fn leave(&mut self) {
+        self.depth -= 1;
+    }
+
     fn item_list(&mut self) -> Result<(ListNode, Nodes), String> {
         let pos = self.peek_non_space_must("item list")?.pos;
         let mut list = ListNode::new(self.tree_id, pos);
@@ -339,6 +401,8 @@ impl<'a> Parser<'a> {
         let token = self.next_non_space_must("action")?;
         match token.typ {
             ItemType::ItemBlock => return self.block_control(),
+            ItemType::ItemBreak => return self.break_control(),
+            ItemType::ItemContinue => return self.continue_control(),
             ItemType::ItemElse => return self.else_control(),
             ItemType::ItemEnd => return self.end_control(),
             ItemType::ItemIf => return self.if_control(),
@@ -358,36 +422,75 @@ impl<'a> Parser<'a> {
 
     fn parse_control(
         &mut self,
-        allow_else_if: bool,
         context: &str,
     ) -> Result<(Pos, PipeNode, ListNode, Option<ListNode>), String> {
         let vars_len = self.tree.as_ref().map(|t| t.vars.len()).ok_or("no tree")?;
-        let pipe = self.pipeline(context)?;
-        let (list, next) = self.item_list()?;
-        let else_list = match *next.typ() {
-            NodeType::End => None,
-            NodeType::Else => {
-                if allow_else_if && self.peek_must("else if")?.typ == ItemType::ItemIf {
-                    self.next_must("else if")?;
-                    let mut else_list = ListNode::new(self.tree_id, next.pos());
-                    else_list.append(self.if_control()?);
-                    Some(else_list)
-                } else {
-                    let (else_list, next) = self.item_list()?;
-                    if *next.typ() != NodeType::End {
-                        return self.error(&format!("expected end; found {}", next));
+        // `else if` and `else with` nest the chained control inside the else list. The
+        // links of such a chain are on the same level though, so they are collected in a
+        // loop and count against `MAX_ELSE_CHAIN` instead of the nesting depth.
+        let mut links = vec![];
+        let mut else_list = loop {
+            self.enter()?;
+            let pipe = self.pipeline(context)?;
+            if context == "range" {
+                self.range_depth += 1;
+            }
+            let list = self.item_list();
+            if context == "range" {
+                self.range_depth -= 1;
+            }
+            let (list, next) = list?;
+            self.leave();
+            links.push((pipe, list, next.pos()));
+            match *next.typ() {
+                NodeType::End => break None,
+                NodeType::Else => {
+                    let chained = self.peek_must("else")?.typ.clone();
+                    if (context == "if" && chained == ItemType::ItemIf)
+                        || (context == "with" && chained == ItemType::ItemWith)
+                    {
+                        let chain = format!("else {}", context);
+                        if self.chain_depth >= MAX_ELSE_CHAIN {
+                            return self.error(&format!("exceeded maximum {} chain length", chain));
+                        }
+                        self.chain_depth += 1;
+                        self.next_must(&chain)?;
+                    } else {
+                        self.enter()?;
+                        let (else_list, next) = self.item_list()?;
+                        self.leave();
+                        if *next.typ() != NodeType::End {
+                            return self.error(&format!("expected end; found {}", next));
+                        }
+                        break Some(else_list);
                     }
-                    Some(else_list)
                 }
+                _ => return self.error(&format!("expected end; found {}", next)),
             }
-            _ => return self.error(&format!("expected end; found {}", next)),
         };
+        self.chain_depth -= links.len() - 1;
         self.tree.as_mut().map(|t| t.pop_vars(vars_len));
+        // Wrap the chain up from its end, each link going into the else list of the one
+        // before it.
+        let (mut pipe, mut list, _) = links.pop().ok_or("no control")?;
+        while let Some((prev_pipe, prev_list, else_pos)) = links.pop() {
+            let pos = pipe.pos();
+            let chained = if context == "if" {
+                Nodes::If(IfNode::new_if(self.tree_id, pos, pipe, list, else_list))
+            } else {
+                Nodes::With(WithNode::new_with(self.tree_id, pos, pipe, list, else_list))
+            };
+            let mut chained_list = ListNode::new(self.tree_id, else_pos);
+            chained_list.append(chained);
+            else_list = Some(chained_list);
+            pipe = prev_pipe;
+            list = prev_list;
+        }
         Ok((pipe.pos(), pipe, list, else_list))
     }
 
     fn if_control(&mut self) -> Result<Nodes, String> {
-        let (pos, pipe, list, else_list) = self.parse_control(true, "if")?;
+        let (pos, pipe, list, else_list) = self.parse_control("if")?;
         Ok(Nodes::If(IfNode::new_if(
             self.tree_id,
             pos,
@@ -398,7 +501,7 @@ impl<'a> Parser<'a> {
     }
 
     fn range_control(&mut self) -> Result<Nodes, String> {
-        let (pos, pipe, list, else_list) = self.parse_control(false, "range")?;
+        let (pos, pipe, list, else_list) = self.parse_control("range")?;
         Ok(Nodes::Range(RangeNode::new_range(
             self.tree_id,
             pos,
@@ -409,7 +512,7 @@ impl<'a> Parser<'a> {
     }
 
     fn with_control(&mut self) -> Result<Nodes, String> {
-        let (pos, pipe, list, else_list) = self.parse_control(false, "with")?;
+        let (pos, pipe, list, else_list) = self.parse_control("with")?;
         Ok(Nodes::With(WithNode::new_with(
             self.tree_id,
             pos,
@@ -426,9 +529,25 @@ impl<'a> Parser<'a> {
         )))
     }
 
+    fn break_control(&mut self) -> Result<Nodes, String> {
+        let pos = self.expect(&ItemType::ItemRightDelim, "break")?.pos;
+        if self.range_depth == 0 {
+            return self.error("{{break}} outside {{range}}");
+        }
+        Ok(Nodes::Break(BreakNode::new(self.tree_id, pos)))
+    }
+
+    fn continue_control(&mut self) -> Result<Nodes, String> {
+        let pos = self.expect(&ItemType::ItemRightDelim, "continue")?.pos;
+        if self.range_depth == 0 {
+            return self.error("{{continue}} outside {{range}}");
+        }
+        Ok(Nodes::Continue(ContinueNode::new(self.tree_id, pos)))
+    }
+
     fn else_control(&mut self) -> Result<Nodes, String> {
-        if self.peek_non_space_must("else")?.typ == ItemType::ItemIf {
-            let peek = self.peek_non_space_must("else")?;
+        let peek = self.peek_non_space_must("else")?;
+        if peek.typ == ItemType::ItemIf || peek.typ == ItemType::ItemWith {
             return Ok(Nodes::Else(ElseNode::new(peek.pos, peek.line)));
         }
         let token = self.expect(&ItemType::ItemRightDelim, "else")?;
@@ -443,9 +562,16 @@ impl<'a> Parser<'a> {
 
         self.max_tree_id += 1;
         let tree_id = self.max_tree_id;
-        let parse_name = self.name;
+        let parse_name = self.name.clone();
         self.start_parse(name.clone(), tree_id, parse_name);
-        let (root, end) = self.item_list()?;
+        self.enter()?;
+        // The block is a template of its own, so a surrounding range doesn't allow
+        // `{{break}}` or `{{continue}}` in it.
+        let range_depth = mem::replace(&mut self.range_depth, 0);
+        let items = self.item_list();
+        self.range_depth = range_depth;
+        let (root, end) = items?;
+        self.leave();
         self.tree.as_mut().map(|t| t.root = Some(Nodes::List(root)));
         if end.typ() != &NodeType::End {
             return self.error(&format!("unexpected {} in {}", end, context));
@@ -483,6 +609,7 @@ impl<'a> Parser<'a> {
 
     fn pipeline(&mut self, context: &str) -> Result<PipeNode, String> {
         let mut decl = vec![];
+        let mut is_assign = false;
         let mut token = self.next_non_space_must("pipeline")?;
         let pos = token.pos;
         // TODO: test this hard!
@@ -491,7 +618,7 @@ impl<'a> Parser<'a> {
                 let token_after_var = self.next_must("variable")?;
                 let next = if token_after_var.typ == ItemType::ItemSpace {
                     let next = self.next_non_space_must("variable")?;
-                    if next.typ != ItemType::ItemColonEquals
+                    if next.typ != ItemType::ItemColonEquals && next.typ != ItemType::ItemAssign
                         && !(next.typ == ItemType::ItemChar && next.val == ",")
                     {
                         self.backup3(token, token_after_var, next);
@@ -501,12 +628,11 @@ impl<'a> Parser<'a> {
                 } else {
                     token_after_var
                 };
-                if next.typ == ItemType::ItemColonEquals
+                if next.typ == ItemType::ItemColonEquals || next.typ == ItemType::ItemAssign
                     || (next.typ == ItemType::ItemChar && next.val == ",")
                 {
-                    let variable = VariableNode::new(self.tree_id, token.pos, &token.val);
-                    self.add_var(token.val.clone())?;
-                    decl.push(variable);
+                    is_assign = next.typ == ItemType::ItemAssign;
+                    decl.push(VariableNode::new(self.tree_id, token.pos, &token.val));
                     if next.typ == ItemType::ItemChar && next.val == "," {
                         if context == "range" && decl.len() < 2 {
                             token = self.next_non_space_must("variable")?;
@@ -522,7 +648,20 @@ impl<'a> Parser<'a> {
         } else {
             self.backup(token);
         }
+        for var in &decl {
+            // `$` always refers to the data passed to the template.
+            if var.ident[0] == "$" {
+                return self.error("can't declare or assign to $");
+            }
+            // Assignments need an existing variable, declarations introduce a new one.
+            if is_assign {
+                self.use_var(self.tree_id, var.pos(), &var.ident[0])?;
+            } else {
+                self.add_var(var.ident[0].clone())?;
+            }
+        }
         let mut pipe = PipeNode::new(self.tree_id, pos, decl);
+        pipe.is_assign = is_assign;
         let mut token = self.next_non_space_must("pipeline")?;
         loop {
             match token.typ {
@@ -689,7 +828,9 @@ impl<'a> Parser<'a> {
                 }
             }
             ItemType::ItemLeftParen => {
+                self.enter()?;
                 let pipe = self.pipeline("parenthesized pipeline")?;
+                self.leave();
                 let next = self.next_must("parenthesized pipeline")?;
                 if next.typ != ItemType::ItemRightParen {
                     return self.error(&format!("unclosed right paren: unexpected {}", next));
@@ -793,10 +934,10 @@ mod tests_mocked {
     }
 
     fn make_parser_with_funcs<'a>(s: &str, funcs: &[(&'a str, Func)]) -> Parser<'a> {
-        let lex = Lexer::new(s.to_owned());
+        let lex = Lexer::new(s.to_owned(), "", "");
         Parser {
-            name: "foo",
-            text: "nope",
+            name: String::from("foo"),
+            text: Arc::from("nope"),
             funcs: funcs.iter().map(|x| *x).collect(),
             lex: Some(lex),
             line: 0,
@@ -808,6 +949,9 @@ mod tests_mocked {
             tree: None,
             tree_stack: VecDeque::new(),
             max_tree_id: 0,
+            range_depth: 0,
+            depth: 0,
+            chain_depth: 0,
         }
     }
 
@@ -892,6 +1036,40 @@ mod tests_mocked {
         assert!(r.is_ok());
     }
 
+    #[test]
+    fn test_else_if_nesting() {
+        let mut p = make_parser_with(r#"{{ if .a }}a{{ else if .b }}b{{ else }}c{{ end }}"#);
+        assert!(p.parse_tree().is_ok());
+        let list = match p.tree_set["foo"].root {
+            Some(Nodes::List(ref l)) => l,
+            _ => panic!("expected list"),
+        };
+        assert_eq!(list.nodes.len(), 1);
+        let else_list = match list.nodes[0] {
+            Nodes::If(ref n) => n.else_list.as_ref().unwrap(),
+            _ => panic!("expected if"),
+        };
+        assert_eq!(else_list.nodes.len(), 1);
+        match else_list.nodes[0] {
+            Nodes::If(ref n) => assert!(n.else_list.is_some()),
+            _ => panic!("expected nested if"),
+        }
+    }
+
+    #[test]
+    fn test_trees_share_text() {
+        let text = "a\n{{ define \"x\" }}\n{{ .x }}{{ end }}{{ .a }}";
+        let p = parse("foo", text, "", "", HashMap::new()).unwrap();
+        let (foo, x) = (&p.tree_set["foo"], &p.tree_set["x"]);
+        assert!(Arc::ptr_eq(&foo.text, &x.text));
+        assert_eq!(&*foo.text, text);
+        let node = match x.root {
+            Some(Nodes::List(ref l)) => &l.nodes[1],
+            _ => panic!("expected list"),
+        };
+        assert_eq!(x.error_context(node), "foo:3");
+    }
+
     #[test]
     fn test_pipeline_simple() {
         let mut p = make_parser_with(r#" $foo, $bar := yay | blub "2000" }}"#);
@@ -2,6 +2,7 @@ use std::char;
 use std::fmt;
 
 use printf::{params_to_chars, FormatParams};
+use utils::{quote_str, to_go_string};
 
 use gtmpl_value::Value;
 
@@ -27,6 +28,10 @@ pub fn print(p: &FormatParams, typ: char, val: &Value) -> Result<String, String>
                 'x' => printf_x(p, u),
                 'X' => printf_xx(p, u),
                 'U' => printf_generic(p, format!("U+{:X}", u)),
+                // Integral floats are stored as integers.
+                'e' => printf_e(p, u as f64),
+                'E' => printf_ee(p, u as f64),
+                'f' | 'F' => printf_f(p, u as f64),
                 _ => return Err(format!("unable to format {} as %{}", val, typ)),
             })
         }
@@ -34,7 +39,7 @@ pub fn print(p: &FormatParams, typ: char, val: &Value) -> Result<String, String>
             let i = n.as_i64().unwrap();
             Ok(match typ {
                 'b' => printf_b(p, i),
-                'd' => printf_generic(p, i),
+                'd' | 'v' => printf_generic(p, i),
                 'o' => printf_o(p, i),
                 'c' => {
                     let c = char::from_u32(i as u32)
@@ -49,6 +54,10 @@ pub fn print(p: &FormatParams, typ: char, val: &Value) -> Result<String, String>
                 'x' => printf_x(p, i),
                 'X' => printf_xx(p, i),
                 'U' => printf_generic(p, format!("U+{:X}", i)),
+                // Integral floats are stored as integers.
+                'e' => printf_e(p, i as f64),
+                'E' => printf_ee(p, i as f64),
+                'f' | 'F' => printf_f(p, i as f64),
                 _ => return Err(format!("unable to format {} as %{}", val, typ)),
             })
         }
@@ -57,7 +66,8 @@ pub fn print(p: &FormatParams, typ: char, val: &Value) -> Result<String, String>
             Ok(match typ {
                 'e' => printf_e(p, f),
                 'E' => printf_ee(p, f),
-                'f' | 'F' => printf_generic(p, f),
+                'f' | 'F' => printf_f(p, f),
+                'v' => printf_generic(p, f),
                 _ => return Err(format!("unable to format {} as %{}", val, typ)),
             })
         }
@@ -69,14 +79,13 @@ pub fn print(p: &FormatParams, typ: char, val: &Value) -> Result<String, String>
             's' | 'v' => printf_generic(p, s),
             'x' => printf_x(p, Hexer::from(s.as_str())),
             'X' => printf_xx(p, Hexer::from(s.as_str())),
-            'q' => {
-                let s = s.chars()
-                    .map(|c| c.escape_default().to_string())
-                    .collect::<String>();
-                printf_generic(p, s)
-            }
+            'q' => printf_generic(p, quote_str(s)),
             _ => return Err(format!("unable to format {} as %{}", val, typ)),
         }),
+        // Collections are formatted recursively, as when they are printed.
+        Value::Array(_) | Value::Map(_) | Value::Object(_) if typ == 'v' => {
+            Ok(printf_generic(p, to_go_string(val)))
+        }
         _ => Err(format!("unable to format {} as %{}", val, typ)),
     }
 }
@@ -95,6 +104,8 @@ fn printf_b<B: fmt::Binary>(p: &FormatParams, u: B) -> String {
         ('#', '0', '+', '-', _) => format!("{:<+#0width$b}", u, width = p.width),
         ('_', '0', '+', '-', _) => format!("{:<+0width$b}", u, width = p.width),
         ('#', '0', '_', '-', _) => format!("{:<#0width$b}", u, width = p.width),
+        ('_', '0', '_', '_', _) => format!("{:0width$b}", u, width = p.width),
+        ('_', '_', '_', '-', _) => format!("{:<width$b}", u, width = p.width),
         (_, _, _, _, _) => format!("{:width$b}", u, width = p.width),
     }
 }
@@ -113,6 +124,8 @@ fn printf_o<B: fmt::Octal>(p: &FormatParams, u: B) -> String {
         ('#', '0', '+', '-', _) => format!("{:<+#0width$o}", u, width = p.width),
         ('_', '0', '+', '-', _) => format!("{:<+0width$o}", u, width = p.width),
         ('#', '0', '_', '-', _) => format!("{:<#0width$o}", u, width = p.width),
+        ('_', '0', '_', '_', _) => format!("{:0width$o}", u, width = p.width),
+        ('_', '_', '_', '-', _) => format!("{:<width$o}", u, width = p.width),
         (_, _, _, _, _) => format!("{:width$o}", u, width = p.width),
     }
 }
@@ -131,6 +144,8 @@ fn printf_x<B: fmt::LowerHex>(p: &FormatParams, u: B) -> String {
         ('#', '0', '+', '-', _) => format!("{:<+#0width$x}", u, width = p.width),
         ('_', '0', '+', '-', _) => format!("{:<+0width$x}", u, width = p.width),
         ('#', '0', '_', '-', _) => format!("{:<#0width$x}", u, width = p.width),
+        ('_', '0', '_', '_', _) => format!("{:0width$x}", u, width = p.width),
+        ('_', '_', '_', '-', _) => format!("{:<width$x}", u, width = p.width),
         (_, _, _, _, _) => format!("{:width$x}", u, width = p.width),
     }
 }
@@ -149,6 +164,8 @@ fn printf_xx<B: fmt::UpperHex>(p: &FormatParams, u: B) -> String {
         ('#', '0', '+', '-', _) => format!("{:<+#0width$X}", u, width = p.width),
         ('_', '0', '+', '-', _) => format!("{:<+0width$X}", u, width = p.width),
         ('#', '0', '_', '-', _) => format!("{:<#0width$X}", u, width = p.width),
+        ('_', '0', '_', '_', _) => format!("{:0width$X}", u, width = p.width),
+        ('_', '_', '_', '-', _) => format!("{:<width$X}", u, width = p.width),
         (_, _, _, _, _) => format!("{:width$X}", u, width = p.width),
     }
 }
@@ -168,7 +185,9 @@ fn printf_generic<D: fmt::Display>(p: &FormatParams, c: D) -> String {
             ('#', '0', '+', '-', _) => format!("{:<+#0width$.pr$}", c, width = p.width, pr = pr),
             ('_', '0', '+', '-', _) => format!("{:<+0width$.pr$}", c, width = p.width, pr = pr),
             ('#', '0', '_', '-', _) => format!("{:<#0width$.pr$}", c, width = p.width, pr = pr),
-            (_, _, _, _, _) => format!("{:width$.pr$}", c, width = p.width, pr = pr),
+            ('_', '0', '_', '_', _) => format!("{:0width$.pr$}", c, width = p.width, pr = pr),
+            ('_', '_', '_', '-', _) => format!("{:<width$.pr$}", c, width = p.width, pr = pr),
+            (_, _, _, _, _) => format!("{:>width$.pr$}", c, width = p.width, pr = pr),
         }
     } else {
         match params_to_chars(p) {
@@ -184,11 +203,22 @@ fn printf_generic<D: fmt::Display>(p: &FormatParams, c: D) -> String {
             ('#', '0', '+', '-', _) => format!("{:<+#0width$}", c, width = p.width),
             ('_', '0', '+', '-', _) => format!("{:<+0width$}", c, width = p.width),
             ('#', '0', '_', '-', _) => format!("{:<#0width$}", c, width = p.width),
-            (_, _, _, _, _) => format!("{:width$}", c, width = p.width),
+            ('_', '0', '_', '_', _) => format!("{:0width$}", c, width = p.width),
+            ('_', '_', '_', '-', _) => format!("{:<width$}", c, width = p.width),
+            (_, _, _, _, _) => format!("{:>width$}", c, width = p.width),
         }
     }
 }
 
+// Golang defaults to a precision of 6 for %f.
+fn printf_f(p: &FormatParams, f: f64) -> String {
+    let p = FormatParams {
+        precision: p.precision.or(Some(6)),
+        ..*p
+    };
+    printf_generic(&p, f)
+}
+
 fn printf_e<E: fmt::LowerExp>(p: &FormatParams, f: E) -> String {
     if let Some(pr) = p.precision {
         match params_to_chars(p) {
@@ -204,6 +234,8 @@ fn printf_e<E: fmt::LowerExp>(p: &FormatParams, f: E) -> String {
             ('#', '0', '+', '-', _) => format!("{:<+#0width$.pr$e}", f, width = p.width, pr = pr),
             ('_', '0', '+', '-', _) => format!("{:<+0width$.pr$e}", f, width = p.width, pr = pr),
             ('#', '0', '_', '-', _) => format!("{:<#0width$.pr$e}", f, width = p.width, pr = pr),
+            ('_', '0', '_', '_', _) => format!("{:0width$.pr$e}", f, width = p.width, pr = pr),
+            ('_', '_', '_', '-', _) => format!("{:<width$.pr$e}", f, width = p.width, pr = pr),
             (_, _, _, _, _) => format!("{:width$.pr$e}", f, width = p.width, pr = pr),
         }
     } else {
@@ -220,6 +252,8 @@ fn printf_e<E: fmt::LowerExp>(p: &FormatParams, f: E) -> String {
             ('#', '0', '+', '-', _) => format!("{:<+#0width$e}", f, width = p.width),
             ('_', '0', '+', '-', _) => format!("{:<+0width$e}", f, width = p.width),
             ('#', '0', '_', '-', _) => format!("{:<#0width$e}", f, width = p.width),
+            ('_', '0', '_', '_', _) => format!("{:0width$e}", f, width = p.width),
+            ('_', '_', '_', '-', _) => format!("{:<width$e}", f, width = p.width),
             (_, _, _, _, _) => format!("{:width$e}", f, width = p.width),
         }
     }
@@ -240,6 +274,8 @@ fn printf_ee<E: fmt::UpperExp>(p: &FormatParams, f: E) -> String {
             ('#', '0', '+', '-', _) => format!("{:<+#0width$.pr$E}", f, width = p.width, pr = pr),
             ('_', '0', '+', '-', _) => format!("{:<+0width$.pr$E}", f, width = p.width, pr = pr),
             ('#', '0', '_', '-', _) => format!("{:<#0width$.pr$E}", f, width = p.width, pr = pr),
+            ('_', '0', '_', '_', _) => format!("{:0width$.pr$E}", f, width = p.width, pr = pr),
+            ('_', '_', '_', '-', _) => format!("{:<width$.pr$E}", f, width = p.width, pr = pr),
             (_, _, _, _, _) => format!("{:width$.pr$E}", f, width = p.width, pr = pr),
         }
     } else {
@@ -256,6 +292,8 @@ fn printf_ee<E: fmt::UpperExp>(p: &FormatParams, f: E) -> String {
             ('#', '0', '+', '-', _) => format!("{:<+#0width$E}", f, width = p.width),
             ('_', '0', '+', '-', _) => format!("{:<+0width$E}", f, width = p.width),
             ('#', '0', '_', '-', _) => format!("{:<#0width$E}", f, width = p.width),
+            ('_', '0', '_', '_', _) => format!("{:0width$E}", f, width = p.width),
+            ('_', '_', '_', '-', _) => format!("{:<width$E}", f, width = p.width),
             (_, _, _, _, _) => format!("{:width$E}", f, width = p.width),
         }
     }
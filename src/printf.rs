@@ -151,10 +151,13 @@ fn process_verb(
 fn parse_index(s: &str) -> Result<Option<(usize, usize)>, String> {
     if s.starts_with('[') {
         let till = s.find(']').ok_or_else(|| format!("missing ] in {}", s))?;
-        s[1..till]
+        let u = s[1..till]
             .parse::<usize>()
-            .map(|u| Some((u - 1, till + 1)))
-            .map_err(|e| format!("unable to parse index: {}", e))
+            .map_err(|e| format!("unable to parse index: {}", e))?;
+        // Argument indexes are 1-based, as in Go.
+        u.checked_sub(1)
+            .map(|i| Some((i, till + 1)))
+            .ok_or_else(|| format!("invalid argument index: {}", &s[..till + 1]))
     } else {
         Ok(None)
     }
@@ -218,6 +221,7 @@ pub fn params_to_chars(params: &FormatParams) -> (char, char, char, char, char)
 #[cfg(test)]
 mod test {
     use super::*;
+    use std::collections::HashMap;
 
     #[test]
     fn test_printintf_to_format() {
@@ -283,6 +287,19 @@ mod test {
         assert!(s.is_ok());
         let s = s.unwrap();
         assert_eq!(s, r"wtf golang! wtf!");
+
+        let s = sprintf("%[1]s %[1]s", &[&"foo".into()]);
+        assert_eq!(s, Ok(String::from("foo foo")));
+
+        let s = sprintf("%[2]d %[1]q %d", &[&"a".into(), &1.into()]);
+        assert_eq!(s, Ok(String::from(r#"1 "a" 1"#)));
+
+        let s = sprintf("%[3]*.[2]*[1]f|", &[&12.0.into(), &2.into(), &6.into()]);
+        assert_eq!(s, Ok(String::from(" 12.00|")));
+
+        assert!(sprintf("%[0]s", &[&"foo".into()]).is_err());
+        assert!(sprintf("%[2]s", &[&"foo".into()]).is_err());
+        assert!(sprintf("%[x]s", &[&"foo".into()]).is_err());
     }
 
     #[test]
@@ -303,6 +320,116 @@ mod test {
         assert_eq!(s, r"+101");
     }
 
+    #[test]
+    fn test_sprintf_signed() {
+        let s = sprintf("%d %v", &[&(-23).into(), &(-42).into()]);
+        assert!(s.is_ok());
+        let s = s.unwrap();
+        assert_eq!(s, r"-23 -42");
+
+        let s = sprintf("%5d|%-5d|", &[&(-1).into(), &(-1).into()]);
+        assert!(s.is_ok());
+        let s = s.unwrap();
+        assert_eq!(s, r"   -1|-1   |");
+    }
+
+    #[test]
+    fn test_sprintf_float() {
+        let s = sprintf("%5.2f", &[&1.23456.into()]);
+        assert!(s.is_ok());
+        let s = s.unwrap();
+        assert_eq!(s, r" 1.23");
+
+        let s = sprintf("%f", &[&3.5.into()]);
+        assert!(s.is_ok());
+        let s = s.unwrap();
+        assert_eq!(s, r"3.500000");
+
+        let s = sprintf("%v", &[&23.42.into()]);
+        assert!(s.is_ok());
+        let s = s.unwrap();
+        assert_eq!(s, r"23.42");
+
+        let s = sprintf("%.1f", &[&2.0.into()]);
+        assert!(s.is_ok());
+        let s = s.unwrap();
+        assert_eq!(s, r"2.0");
+
+        let s = sprintf("%v %.2f %v", &[&(-2.5).into(), &(-1.255).into(), &(-1).into()]);
+        assert!(s.is_ok());
+        let s = s.unwrap();
+        assert_eq!(s, r"-2.5 -1.25 -1");
+    }
+
+    #[test]
+    fn test_sprintf_bool() {
+        let s = sprintf("%t %v", &[&true.into(), &false.into()]);
+        assert!(s.is_ok());
+        let s = s.unwrap();
+        assert_eq!(s, r"true false");
+
+        let s = sprintf("%6t", &[&true.into()]);
+        assert!(s.is_ok());
+        let s = s.unwrap();
+        assert_eq!(s, r"  true");
+    }
+
+    #[test]
+    fn test_sprintf_nested() {
+        let mut inner = HashMap::new();
+        inner.insert("b".to_owned(), Value::from(vec![1, 2]));
+        inner.insert("a".to_owned(), Value::from("x"));
+        let mut outer = HashMap::new();
+        outer.insert("m".to_owned(), Value::Map(inner));
+        outer.insert("f".to_owned(), Value::from(1.5));
+        let val = Value::Map(outer);
+        let s = sprintf("%v", &[&val]);
+        assert_eq!(s, Ok(String::from("map[f:1.5 m:map[a:x b:[1 2]]]")));
+
+        let arr = Value::Array(vec![
+            Value::from(vec!["a", "b"]),
+            Value::Array(vec![]),
+            Value::from(true),
+        ]);
+        let s = sprintf("%v|%-12v|", &[&arr, &Value::from(vec![1, 2])]);
+        assert_eq!(s, Ok(String::from("[[a b] [] true]|[1 2]       |")));
+
+        assert!(sprintf("%d", &[&arr]).is_err());
+    }
+
+    #[test]
+    fn test_sprintf_string() {
+        let s = sprintf("%s items at %5s", &[&"23".into(), &"foo".into()]);
+        assert!(s.is_ok());
+        let s = s.unwrap();
+        assert_eq!(s, r"23 items at   foo");
+
+        let s = sprintf("%q", &[&"foo\tbar".into()]);
+        assert!(s.is_ok());
+        let s = s.unwrap();
+        assert_eq!(s, r#""foo\tbar""#);
+
+        let s = sprintf("%q", &[&"'é'".into()]);
+        assert!(s.is_ok());
+        let s = s.unwrap();
+        assert_eq!(s, r#""'é'""#);
+    }
+
+    #[test]
+    fn test_sprintf_mismatch() {
+        let s = sprintf("%d", &[&"foo".into()]);
+        assert!(s.is_err());
+
+        let s = sprintf("%t", &[&1.into()]);
+        assert!(s.is_err());
+
+        let s = sprintf("%s", &[&true.into()]);
+        assert!(s.is_err());
+
+        let s = sprintf("%d %d", &[&1.into()]);
+        assert!(s.is_err());
+    }
+
     #[test]
     fn test_tokenize() {
         let t = tokenize("foobar%6.2ffoobar");
@@ -1,19 +1,61 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+use glob::glob;
 
 use parse::{parse, Parser, Tree};
 use funcs::BUILTINS;
+use lexer::{LEFT_DELIM, RIGHT_DELIM};
 use node::TreeId;
 
 use gtmpl_value::Func;
 
 /// The main template structure.
-#[derive(Default)]
+///
+/// Cloning a template copies its functions and parsed templates, so a base
+/// template can be set up once and then extended differently by each clone.
+///
+/// ## Example
+///
+/// ```rust
+/// use gtmpl::Context;
+///
+/// let mut base = gtmpl::Template::default();
+/// base.parse(r#"{{ define "greeting" }}Hello{{ end }}"#).unwrap();
+/// let mut page = base.clone();
+/// page.parse(r#"{{ template "greeting" }} World!"#).unwrap();
+/// assert_eq!(&page.render(&Context::empty()).unwrap(), "Hello World!");
+/// assert_eq!(&base.render(&Context::empty()).unwrap(), "");
+/// ```
+#[derive(Clone, Default)]
 pub struct Template<'a> {
     pub name: &'a str,
     pub text: &'a str,
     pub funcs: HashMap<&'a str, Func>,
     pub tree_ids: HashMap<TreeId, String>,
-    pub tree_set: HashMap<String, Tree<'a>>,
+    pub tree_set: HashMap<String, Tree>,
+    pub(crate) left_delim: String,
+    pub(crate) right_delim: String,
+    pub(crate) missing_key: MissingKey,
+    // Names registered with `add_func` or `add_funcs`. Builtins evaluated by the executor
+    // itself, like `and`, are only called as plain functions once overridden.
+    pub(crate) custom_funcs: HashSet<&'a str>,
+    // Maximum nesting of `{{template}}` calls, `None` stands for the default.
+    pub(crate) max_depth: Option<usize>,
+}
+
+/// Controls what happens when a map is indexed with a key that is not present.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub(crate) enum MissingKey {
+    /// Returns `Value::NoValue`, printed as `<no value>`.
+    #[default]
+    Invalid,
+    /// Returns the zero value shared by the map's values, or `""`.
+    Zero,
+    /// Stops execution with an error.
+    Error,
 }
 
 impl<'a> Template<'a> {
@@ -25,10 +67,41 @@ impl<'a> Template<'a> {
             funcs: HashMap::default(),
             tree_ids: HashMap::default(),
             tree_set: HashMap::default(),
+            left_delim: String::default(),
+            right_delim: String::default(),
+            missing_key: MissingKey::default(),
+            custom_funcs: HashSet::default(),
+            max_depth: None,
         }
     }
 
-    /// Adds a single custom function to the template.
+    /// Returns the name of the template.
+    pub fn name(&self) -> &str {
+        self.name
+    }
+
+    /// Sets the name of the template, as used in error messages. Text parsed
+    /// afterwards defines the template of this name, while templates parsed
+    /// before keep their names.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// use gtmpl::Context;
+    ///
+    /// let mut tmpl = gtmpl::Template::default();
+    /// tmpl.set_name("page").parse("{{ len 1 }}").unwrap();
+    /// assert_eq!(tmpl.name(), "page");
+    /// let err = tmpl.render(&Context::empty()).unwrap_err();
+    /// assert!(err.starts_with("template: page:1:"));
+    /// ```
+    pub fn set_name(&mut self, name: &'a str) -> &mut Template<'a> {
+        self.name = name;
+        self
+    }
+
+    /// Adds a single custom function to the template. Registering a name that is
+    /// already used by a builtin function overrides the builtin.
     ///
     /// ## Example
     ///
@@ -48,11 +121,14 @@ impl<'a> Template<'a> {
     /// let output = tmpl.render(&Context::empty());
     /// assert_eq!(&output.unwrap(), "Hello World!");
     /// ```
-    pub fn add_func(&mut self, name: &'a str, func: Func) {
+    pub fn add_func(&mut self, name: &'a str, func: Func) -> &mut Template<'a> {
         self.funcs.insert(name, func);
+        self.custom_funcs.insert(name);
+        self
     }
 
-    /// Adds custom functions to the template.
+    /// Adds custom functions to the template. Like with `add_func`, builtin
+    /// functions with the same name are overridden.
     ///
     /// ## Example
     ///
@@ -74,11 +150,137 @@ impl<'a> Template<'a> {
     /// let output = tmpl.render(&Context::empty());
     /// assert_eq!(&output.unwrap(), "Hello World!");
     /// ```
-    pub fn add_funcs(&mut self, funcs: &[(&'a str, Func)]) {
+    pub fn add_funcs(&mut self, funcs: &[(&'a str, Func)]) -> &mut Template<'a> {
         self.funcs.extend(funcs.iter().cloned());
+        self.custom_funcs.extend(funcs.iter().map(|&(name, _)| name));
+        self
+    }
+
+    /// Sets the action delimiters to the specified strings, to be used in
+    /// subsequent calls to `parse`. An empty delimiter stands for the
+    /// corresponding default: `{{` or `}}`. Delimiters that are equal, or where
+    /// one starts with the other, can't be told apart and make `parse` fail.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// use gtmpl::Context;
+    ///
+    /// let mut tmpl = gtmpl::Template::default();
+    /// tmpl.delims("[[", "]]");
+    /// tmpl.parse("{{ [[ . ]] }}").unwrap();
+    /// let output = tmpl.render(&Context::from("gtmpl"));
+    /// assert_eq!(&output.unwrap(), "{{ gtmpl }}");
+    /// ```
+    pub fn delims(&mut self, left: &str, right: &str) -> &mut Template<'a> {
+        self.left_delim = left.to_owned();
+        self.right_delim = right.to_owned();
+        self
+    }
+
+    /// Returns the left and right action delimiters used by `parse`, with the
+    /// defaults filled in for delimiters that aren't set.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// let mut tmpl = gtmpl::Template::default();
+    /// assert_eq!(tmpl.delimiters(), ("{{", "}}"));
+    /// tmpl.delims("[[", "");
+    /// assert_eq!(tmpl.delimiters(), ("[[", "}}"));
+    /// ```
+    pub fn delimiters(&self) -> (&str, &str) {
+        let left = if self.left_delim.is_empty() {
+            LEFT_DELIM
+        } else {
+            &self.left_delim
+        };
+        let right = if self.right_delim.is_empty() {
+            RIGHT_DELIM
+        } else {
+            &self.right_delim
+        };
+        (left, right)
+    }
+
+    fn check_delims(&self) -> Result<(), String> {
+        let (left, right) = self.delimiters();
+        if left.starts_with(right) || right.starts_with(left) {
+            return Err(format!("overlapping delimiters: {:?} and {:?}", left, right));
+        }
+        Ok(())
+    }
+
+    /// Sets the maximum nesting depth of `{{template}}` calls during execution.
+    /// Exceeding it stops execution with an error instead of overflowing the
+    /// stack, e.g. for a template invoking itself. Defaults to 100. Raising it
+    /// for deeply recursive templates may require running on a larger stack.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// use gtmpl::Context;
+    ///
+    /// let mut tmpl = gtmpl::Template::default();
+    /// tmpl.max_depth(10);
+    /// tmpl.parse(r#"{{ define "a" }}{{ template "a" }}{{ end }}{{ template "a" }}"#)
+    ///     .unwrap();
+    /// assert!(tmpl.render(&Context::empty()).is_err());
+    /// ```
+    pub fn max_depth(&mut self, depth: usize) -> &mut Template<'a> {
+        self.max_depth = Some(depth);
+        self
+    }
+
+    /// Sets options for the template. Options are described by strings of
+    /// the form `key=value`. Known options:
+    ///
+    /// `missingkey`: Controls the behavior during execution if a map is
+    /// indexed with a key that is not present in the map.
+    /// * `missingkey=default` or `missingkey=invalid`: The default behavior: Do
+    ///   nothing and continue execution. If printed, the result of the index
+    ///   operation is `<no value>`.
+    /// * `missingkey=zero`: The operation returns the zero value of the map's
+    ///   values: `""`, `0`, `false`, `[]` or `map[]` if all values are strings,
+    ///   numbers, bools, arrays or maps respectively. As maps of `Value`s have no
+    ///   element type, the result is `""` for empty maps and maps mixing values of
+    ///   different kinds.
+    /// * `missingkey=error`: Execution stops immediately with an error.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// use std::collections::HashMap;
+    /// use gtmpl::Context;
+    ///
+    /// let mut map = HashMap::new();
+    /// map.insert("foo".to_owned(), "bar");
+    /// let mut tmpl = gtmpl::Template::default();
+    /// tmpl.option("missingkey=error").unwrap();
+    /// tmpl.parse("{{ .missing }}").unwrap();
+    /// assert!(tmpl.render(&Context::from(map)).is_err());
+    /// ```
+    pub fn option(&mut self, opt: &str) -> Result<(), String> {
+        let mut kv = opt.splitn(2, '=');
+        match (kv.next(), kv.next()) {
+            (Some("missingkey"), Some(v)) => {
+                self.missing_key = match v {
+                    "default" | "invalid" => MissingKey::Invalid,
+                    "zero" => MissingKey::Zero,
+                    "error" => MissingKey::Error,
+                    _ => return Err(format!("unrecognized option: {}", opt)),
+                };
+                Ok(())
+            }
+            _ => Err(format!("unrecognized option: {}", opt)),
+        }
     }
 
-    /// Parse the given `text` as template body.
+    /// Parse the given `text` as template body. Templates defined in `text`
+    /// replace existing templates of the same name, unless they are empty.
+    /// This allows to override the default of a `{{block}}` by parsing a
+    /// `{{define}}` later on. Control structures and parenthesized pipelines
+    /// can be nested at most 100 levels deep.
     ///
     /// ## Example
     ///
@@ -87,10 +289,11 @@ impl<'a> Template<'a> {
     /// tmpl.parse("Hello World!").unwrap();
     /// ```
     pub fn parse(&mut self, text: &'a str) -> Result<(), String> {
+        self.check_delims()?;
         let mut funcs = HashMap::new();
         funcs.extend(BUILTINS.iter().cloned());
         funcs.extend(&self.funcs);
-        let parser = parse(self.name, text, funcs)?;
+        let parser = parse(self.name, text, &self.left_delim, &self.right_delim, funcs)?;
         match parser {
             Parser {
                 funcs,
@@ -99,17 +302,175 @@ impl<'a> Template<'a> {
                 ..
             } => {
                 self.funcs = funcs;
-                self.tree_set = tree_set;
-                self.tree_ids = tree_ids;
+                self.add_trees(tree_set)?;
+                self.tree_ids.extend(tree_ids);
             }
         }
         Ok(())
     }
+
+    /// Parses the given `text` like `parse`, but panics if parsing fails. This
+    /// is meant for setup code where a broken template is a bug.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// use gtmpl::{Context, Template};
+    ///
+    /// let tmpl = Template::default().must_parse("Hello {{ . }}!");
+    /// let output = tmpl.render(&Context::from("World"));
+    /// assert_eq!(&output.unwrap(), "Hello World!");
+    /// ```
+    pub fn must_parse(mut self, text: &'a str) -> Template<'a> {
+        if let Err(e) = self.parse(text) {
+            panic!("{}", e);
+        }
+        self
+    }
+
+    /// Returns the associated template with the given `name` or `None` if
+    /// there is no such template. Its nodes can be walked to inspect the template
+    /// without executing it.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// use gtmpl::node::Nodes;
+    ///
+    /// let mut tmpl = gtmpl::Template::with_name("foo");
+    /// tmpl.parse(r#"Hello {{ . }}!{{ define "bar" }}bar{{ end }}"#).unwrap();
+    /// assert!(tmpl.lookup("bar").is_some());
+    /// assert!(tmpl.lookup("baz").is_none());
+    ///
+    /// let tree = tmpl.lookup("foo").unwrap();
+    /// match tree.root {
+    ///     Some(Nodes::List(ref list)) => assert_eq!(list.nodes.len(), 3),
+    ///     _ => panic!("expected a list"),
+    /// }
+    /// ```
+    pub fn lookup(&self, name: &str) -> Option<&Tree> {
+        self.tree_set.get(name)
+    }
+
+    /// Returns the names of all templates associated with this template,
+    /// including itself. The order is arbitrary.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// let mut tmpl = gtmpl::Template::with_name("foo");
+    /// tmpl.parse(r#"{{ define "bar" }}{{ end }}"#).unwrap();
+    /// let mut names = tmpl.templates();
+    /// names.sort();
+    /// assert_eq!(names, vec!["bar", "foo"]);
+    /// ```
+    pub fn templates(&self) -> Vec<&str> {
+        self.tree_set.keys().map(|k| k.as_str()).collect()
+    }
+
+    /// Parses the given files as associated templates. Each template is named
+    /// after the stem of its file and can be invoked via `{{template "name"}}`.
+    /// A file with the same name as a previous one overrides it. If the
+    /// template has no body yet, the file named like the template, or else the
+    /// first file, becomes its body.
+    ///
+    /// ## Example
+    ///
+    /// ```rust,no_run
+    /// use std::path::Path;
+    ///
+    /// let mut tmpl = gtmpl::Template::default();
+    /// tmpl.parse_files(&[Path::new("templates/index.tmpl")]).unwrap();
+    /// ```
+    pub fn parse_files<P: AsRef<Path>>(&mut self, paths: &[P]) -> Result<(), String> {
+        if paths.is_empty() {
+            return Err(String::from("no files named in call to parse_files"));
+        }
+        let mut parsed = vec![];
+        let mut errors = vec![];
+        for path in paths {
+            match self.parse_file(path.as_ref()) {
+                Ok(p) => parsed.push(p),
+                Err(e) => errors.push(e),
+            }
+        }
+        if !errors.is_empty() {
+            return Err(errors.join("\n"));
+        }
+        let mut first = None;
+        for (name, parser) in parsed {
+            first = first.or_else(|| Some(name.clone()));
+            self.funcs = parser.funcs;
+            self.add_trees(parser.tree_set)?;
+        }
+        if self.tree_set.contains_key(self.name) {
+            self.tree_ids.insert(1, self.name.to_owned());
+        } else if let Some(first) = first {
+            self.tree_ids.entry(1).or_insert(first);
+        }
+        Ok(())
+    }
+
+    /// Parses all files matching the glob `pattern` by calling `parse_files`.
+    /// It is an error if the pattern does not match any file.
+    ///
+    /// ## Example
+    ///
+    /// ```rust,no_run
+    /// let mut tmpl = gtmpl::Template::with_name("index");
+    /// tmpl.parse_glob("templates/*.tmpl").unwrap();
+    /// ```
+    pub fn parse_glob(&mut self, pattern: &str) -> Result<(), String> {
+        let paths = glob(pattern)
+            .map_err(|e| format!("invalid pattern {}: {}", pattern, e))?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| format!("unable to read {}", e))?;
+        if paths.is_empty() {
+            return Err(format!("pattern matches no files: {}", pattern));
+        }
+        self.parse_files(&paths)
+    }
+
+    // Adds parsed trees to the template. A non-empty tree replaces an existing
+    // one, an empty tree never does.
+    fn add_trees(&mut self, trees: HashMap<String, Tree>) -> Result<(), String> {
+        for (name, tree) in trees {
+            if self.tree_set.contains_key(&name) && tree.is_empty()? {
+                continue;
+            }
+            self.tree_set.insert(name, tree);
+        }
+        Ok(())
+    }
+
+    fn parse_file(&self, path: &Path) -> Result<(String, Parser<'a>), String> {
+        let name = path.file_stem()
+            .and_then(|s| s.to_str())
+            .ok_or_else(|| format!("invalid template file name {}", path.display()))?;
+        self.check_delims()?;
+        let mut text = String::new();
+        File::open(path)
+            .and_then(|mut f| f.read_to_string(&mut text))
+            .map_err(|e| format!("unable to read {}: {}", path.display(), e))?;
+        let mut funcs = HashMap::new();
+        funcs.extend(BUILTINS.iter().cloned());
+        funcs.extend(&self.funcs);
+        let parser = parse(name, &text, &self.left_delim, &self.right_delim, funcs)?;
+        Ok((name.to_owned(), parser))
+    }
 }
 
 #[cfg(test)]
 mod tests_mocked {
     use super::*;
+    use std::any::Any;
+    use std::env;
+    use std::fs;
+    use std::path::PathBuf;
+    use std::process;
+    use std::sync::Arc;
+    use exec::Context;
+    use gtmpl_value::{FromValue, Value};
 
     #[test]
     fn test_parse() {
@@ -118,4 +479,308 @@ mod tests_mocked {
         assert!(t.tree_set.contains_key("foo"));
         assert!(t.tree_ids.contains_key(&1usize));
     }
+
+    #[test]
+    fn test_add_func() {
+        fn double(args: &[Arc<Any>]) -> Result<Arc<Any>, String> {
+            args.first()
+                .and_then(|a| a.downcast_ref::<Value>())
+                .and_then(i64::from_value)
+                .map(|i| Arc::new(Value::from(i * 2)) as Arc<Any>)
+                .ok_or_else(|| String::from("double requires a number"))
+        }
+        fn shout(args: &[Arc<Any>]) -> Result<Arc<Any>, String> {
+            args.first()
+                .and_then(|a| a.downcast_ref::<Value>())
+                .and_then(String::from_value)
+                .map(|s| Arc::new(Value::from(s.to_uppercase())) as Arc<Any>)
+                .ok_or_else(|| String::from("shout requires a string"))
+        }
+
+        let mut t = Template::default();
+        t.add_func("double", double).add_func("len", double);
+        assert!(t.parse(r#"{{ double . }} {{ len . }}"#).is_ok());
+        let output = t.render(&Context::from(21));
+        assert_eq!(output, Ok(String::from("42 42")));
+
+        let mut t = Template::default();
+        t.add_funcs(&[("double", double as Func), ("shout", shout as Func)])
+            .delims("[[", "]]");
+        assert!(t.parse(r#"[[ double 2 ]] [[ shout "foo" ]]"#).is_ok());
+        let output = t.render(&Context::empty());
+        assert_eq!(output, Ok(String::from("4 FOO")));
+    }
+
+    #[test]
+    fn test_must_parse() {
+        let t = Template::with_name("foo").must_parse(r#"{{ define "bar" }}1{{ end }}2"#);
+        assert!(t.lookup("foo").is_some());
+        assert!(t.lookup("bar").is_some());
+        assert_eq!(t.render(&Context::empty()), Ok(String::from("2")));
+    }
+
+    #[test]
+    #[should_panic(expected = "unclosed action")]
+    fn test_must_parse_panics() {
+        Template::default().must_parse("{{ . ");
+    }
+
+    #[test]
+    fn test_name() {
+        let mut t = Template::with_name("foo");
+        assert_eq!(t.name(), "foo");
+        t.set_name("bar");
+        assert_eq!(t.name(), "bar");
+        assert!(t.parse("{{ .missing }}").is_ok());
+        assert!(t.option("missingkey=error").is_ok());
+        let err = t.render(&Context::from(Value::Map(HashMap::new())))
+            .unwrap_err();
+        assert!(err.starts_with("template: bar:1: "), "{}", err);
+        assert!(t.lookup("bar").is_some());
+        assert!(t.lookup("foo").is_none());
+
+        let mut t = Template::default();
+        t.set_name("empty");
+        let err = t.render(&Context::empty()).unwrap_err();
+        assert!(err.starts_with("empty "), "{}", err);
+    }
+
+    #[test]
+    fn test_lookup() {
+        let mut t = Template::with_name("foo");
+        assert!(t.lookup("foo").is_none());
+        assert!(
+            t.parse(r#"{{ define "bar" }}1{{ end }}{{ define "baz" }}{{ end }}2000"#)
+                .is_ok()
+        );
+        assert!(t.lookup("foo").is_some());
+        assert!(t.lookup("bar").is_some());
+        assert!(t.lookup("baz").is_some());
+        assert!(t.lookup("qux").is_none());
+        assert!(t.lookup("").is_none());
+    }
+
+    #[test]
+    fn test_lookup_tree() {
+        use node::Nodes;
+
+        fn count_actions(node: &Nodes) -> usize {
+            match *node {
+                Nodes::Action(_) => 1,
+                Nodes::List(ref l) => l.nodes.iter().map(count_actions).sum(),
+                Nodes::If(ref b) | Nodes::With(ref b) | Nodes::Range(ref b) => {
+                    b.list.nodes.iter().map(count_actions).sum::<usize>()
+                        + b.else_list
+                            .iter()
+                            .flat_map(|l| l.nodes.iter())
+                            .map(count_actions)
+                            .sum::<usize>()
+                }
+                _ => 0,
+            }
+        }
+
+        let mut t = Template::with_name("foo");
+        assert!(t.lookup("foo").is_none());
+        assert!(
+            t.parse(
+                r#"{{ define "bar" }}{{ . }}{{ end }}{{ .a }}{{ if .b }}{{ .c }}{{ else }}{{ range . }}{{ . }}{{ end }}{{ end }}{{ template "bar" }}"#
+            ).is_ok()
+        );
+        let tree = t.lookup("foo").unwrap();
+        assert_eq!(tree.name(), "foo");
+        assert_eq!(tree.root.as_ref().map(count_actions), Some(3));
+        let tree = t.lookup("bar").unwrap();
+        assert_eq!(tree.root.as_ref().map(count_actions), Some(1));
+    }
+
+    #[test]
+    fn test_templates() {
+        let mut t = Template::with_name("root");
+        assert!(t.templates().is_empty());
+        assert!(
+            t.parse(
+                r#"{{ define "a" }}1{{ end }}{{ define "b" }}2{{ end }}{{ define "c" }}{{ end }}"#
+            ).is_ok()
+        );
+        let mut names = t.templates();
+        names.sort();
+        assert_eq!(names, vec!["a", "b", "c", "root"]);
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("gtmpl-{}-{}", name, process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_clone() {
+        fn double(args: &[Arc<Any>]) -> Result<Arc<Any>, String> {
+            args.first()
+                .and_then(|a| a.downcast_ref::<Value>())
+                .and_then(i64::from_value)
+                .map(|i| Arc::new(Value::from(i * 2)) as Arc<Any>)
+                .ok_or_else(|| String::from("double requires a number"))
+        }
+
+        let mut base = Template::with_name("base");
+        assert!(
+            base.parse(r#"{{ define "header" }}H{{ end }}{{ template "header" }}"#)
+                .is_ok()
+        );
+        let mut clone = base.clone();
+        clone.add_func("double", double);
+        assert!(
+            clone
+                .parse(r#"{{ define "partial" }}{{ double . }}{{ end }}{{ template "partial" . }}"#)
+                .is_ok()
+        );
+        assert!(clone.lookup("header").is_some());
+        assert!(clone.lookup("partial").is_some());
+        assert!(base.lookup("partial").is_none());
+        assert!(!base.funcs.contains_key("double"));
+        assert_eq!(clone.render(&Context::from(2)), Ok(String::from("4")));
+        assert_eq!(base.render(&Context::from(2)), Ok(String::from("H")));
+    }
+
+    #[test]
+    fn test_parse_files() {
+        let dir = temp_dir("parse_files");
+        let index = dir.join("index.tmpl");
+        let partial = dir.join("partial.tmpl");
+        let other = dir.join("other").join("partial.tmpl");
+        fs::create_dir_all(other.parent().unwrap()).unwrap();
+        fs::write(&index, r#"Hello {{ template "partial" . }}!"#).unwrap();
+        fs::write(&partial, r#"{{ . }}"#).unwrap();
+        fs::write(&other, r#"{{ . }} and more"#).unwrap();
+
+        let mut t = Template::default();
+        assert!(t.parse_files(&[index.as_path(), partial.as_path()]).is_ok());
+        assert!(t.tree_set.contains_key("index"));
+        assert!(t.tree_set.contains_key("partial"));
+        let output = t.render(&Context::from("gtmpl"));
+        assert_eq!(output, Ok(String::from("Hello gtmpl!")));
+
+        let mut t = Template::default();
+        assert!(t.parse_files(&[&index, &partial, &other]).is_ok());
+        let output = t.render(&Context::from("gtmpl"));
+        assert_eq!(output, Ok(String::from("Hello gtmpl and more!")));
+
+        let mut t = Template::with_name("partial");
+        assert!(t.parse_files(&[&index, &partial]).is_ok());
+        let output = t.render(&Context::from("gtmpl"));
+        assert_eq!(output, Ok(String::from("gtmpl")));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_parse_files_err() {
+        let dir = temp_dir("parse_files_err");
+        let broken = dir.join("broken.tmpl");
+        let missing = dir.join("missing.tmpl");
+        fs::write(&broken, r#"{{ if }}"#).unwrap();
+
+        let mut t = Template::default();
+        let paths: &[&Path] = &[];
+        assert!(t.parse_files(paths).is_err());
+
+        let mut t = Template::default();
+        let err = t.parse_files(&[&broken, &missing]).unwrap_err();
+        assert!(err.contains("broken"));
+        assert!(err.contains("missing.tmpl"));
+        assert!(t.tree_set.is_empty());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_parse_glob() {
+        let dir = temp_dir("parse_glob");
+        fs::write(dir.join("index.tmpl"), r#"{{ template "header" }} {{ . }}"#).unwrap();
+        fs::write(dir.join("header.tmpl"), r#"Hello"#).unwrap();
+        fs::write(dir.join("footer.tmpl"), r#"Bye"#).unwrap();
+        fs::write(dir.join("ignored.txt"), r#"{{ if }}"#).unwrap();
+
+        let pattern = format!("{}/*.tmpl", dir.display());
+        let mut t = Template::with_name("index");
+        assert!(t.parse_glob(&pattern).is_ok());
+        assert_eq!(t.tree_set.len(), 3);
+        assert!(!t.tree_set.contains_key("ignored"));
+        let output = t.render(&Context::from("gtmpl"));
+        assert_eq!(output, Ok(String::from("Hello gtmpl")));
+
+        let pattern = format!("{}/*.nope", dir.display());
+        let mut t = Template::with_name("index");
+        assert!(t.parse_glob(&pattern).is_err());
+
+        let mut t = Template::with_name("index");
+        assert!(t.parse_glob("[").is_err());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_option() {
+        let mut t = Template::default();
+        assert_eq!(t.missing_key, MissingKey::Invalid);
+        assert!(t.option("missingkey=zero").is_ok());
+        assert_eq!(t.missing_key, MissingKey::Zero);
+        assert!(t.option("missingkey=error").is_ok());
+        assert_eq!(t.missing_key, MissingKey::Error);
+        assert!(t.option("missingkey=default").is_ok());
+        assert_eq!(t.missing_key, MissingKey::Invalid);
+        assert!(t.option("missingkey=invalid").is_ok());
+        assert_eq!(t.missing_key, MissingKey::Invalid);
+        assert!(t.option("missingkey=foo").is_err());
+        assert!(t.option("missingkey").is_err());
+        assert!(t.option("foo=bar").is_err());
+    }
+
+    #[test]
+    fn test_delims() {
+        let mut t = Template::with_name("foo");
+        t.delims("[[", "]]");
+        assert!(t.parse(r#"{{ .foo }} [[- if eq "bar" "bar" ]] 2000 [[ end ]]"#).is_ok());
+        let output = t.render(&Context::empty());
+        assert_eq!(output, Ok(String::from("{{ .foo }} 2000 ")));
+
+        let mut t = Template::with_name("foo");
+        t.delims("<%", "%>");
+        assert!(t.parse(r#"<%/* comment */%><%- . -%> }}"#).is_ok());
+        let output = t.render(&Context::from(1));
+        assert_eq!(output, Ok(String::from("1}}")));
+
+        let mut t = Template::with_name("foo");
+        t.delims("[[", "]]").delims("", "");
+        assert!(t.parse(r#"{{ . }} [[ . ]]"#).is_ok());
+        let output = t.render(&Context::from(1));
+        assert_eq!(output, Ok(String::from("1 [[ . ]]")));
+    }
+
+    #[test]
+    fn test_delimiters() {
+        let mut t = Template::default();
+        assert_eq!(t.delimiters(), ("{{", "}}"));
+        t.delims("<<", ">>");
+        assert_eq!(t.delimiters(), ("<<", ">>"));
+        assert!(t.parse("<< . >>").is_ok());
+        assert_eq!(t.render(&Context::from(1)), Ok(String::from("1")));
+        t.delims("", "]]");
+        assert_eq!(t.delimiters(), ("{{", "]]"));
+
+        let mut t = Template::default();
+        t.delims("{{", "{{");
+        assert_eq!(
+            t.parse("{{ . {{"),
+            Err(String::from(r#"overlapping delimiters: "{{" and "{{""#))
+        );
+        t.delims("", "{{");
+        assert!(t.parse("{{ . {{").is_err());
+        t.delims("<", "<%");
+        assert!(t.parse("< . <%").is_err());
+        t.delims("<%", "%>");
+        assert!(t.parse("<% . %>").is_ok());
+    }
 }
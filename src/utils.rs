@@ -2,6 +2,7 @@ use std::any::Any;
 use std::char;
 use std::sync::Arc;
 
+use itertools::Itertools;
 use gtmpl_value::Value;
 
 pub fn unquote_char(s: &str, quote: char) -> Option<char> {
@@ -29,8 +30,12 @@ pub fn unquote_str(s: &str) -> Option<String> {
     if !s.ends_with(quote) {
         return None;
     }
-    let mut r = String::new();
     let raw = &s[1..s.len() - 1];
+    // Raw strings are taken verbatim, except for carriage returns, as in Go.
+    if quote == "`" {
+        return Some(raw.chars().filter(|&c| c != '\r').collect());
+    }
+    let mut r = String::new();
     let mut i = 0;
     while i < raw.len() {
         match unqote(&raw[i..]) {
@@ -46,15 +51,17 @@ pub fn unquote_str(s: &str) -> Option<String> {
 
 fn unqote(raw: &str) -> Option<(String, usize)> {
     if raw.starts_with('\\') {
-        match &raw[..2] {
+        match raw.get(..2)? {
             r"\x" => extract_bytes_x(raw),
             r"\U" => extract_bytes_u32(raw),
             r"\u" => extract_bytes_u16(raw),
+            r"\a" => Some(('\u{0007}'.to_string(), 2)),
             r"\b" => Some(('\u{0008}'.to_string(), 2)),
             r"\f" => Some(('\u{000C}'.to_string(), 2)),
             r"\n" => Some(('\n'.to_string(), 2)),
             r"\r" => Some(('\r'.to_string(), 2)),
             r"\t" => Some(('\t'.to_string(), 2)),
+            r"\v" => Some(('\u{000B}'.to_string(), 2)),
             r"\'" => Some(('\''.to_string(), 2)),
             r#"\""# => Some(('\"'.to_string(), 2)),
             r#"\\"# => Some(('\\'.to_string(), 2)),
@@ -72,10 +79,7 @@ fn get_char(s: &str) -> Option<(String, usize)> {
 }
 
 fn extract_bytes_u32(s: &str) -> Option<(String, usize)> {
-    if s.len() != 10 {
-        return None;
-    }
-    u32::from_str_radix(&s[2..10], 16)
+    u32::from_str_radix(s.get(2..10)?, 16)
         .ok()
         .and_then(char::from_u32)
         .map(|c| (c.to_string(), 10))
@@ -84,9 +88,9 @@ fn extract_bytes_u32(s: &str) -> Option<(String, usize)> {
 fn extract_bytes_u16(s: &str) -> Option<(String, usize)> {
     let mut bytes = vec![];
     let mut i = 0;
-    while s.len() > i && s.starts_with(r"\u") && s[i..].len() >= 6 {
-        match u16::from_str_radix(&s[(i + 2)..(i + 6)], 16) {
-            Ok(x) => bytes.push(x),
+    while s[i..].starts_with(r"\u") {
+        match s.get((i + 2)..(i + 6)).map(|h| u16::from_str_radix(h, 16)) {
+            Some(Ok(x)) => bytes.push(x),
             _ => {
                 return None;
             }
@@ -99,9 +103,9 @@ fn extract_bytes_u16(s: &str) -> Option<(String, usize)> {
 fn extract_bytes_x(s: &str) -> Option<(String, usize)> {
     let mut bytes = vec![];
     let mut i = 0;
-    while s.len() > i && s.starts_with(r"\x") && s[i..].len() >= 4 {
-        match u8::from_str_radix(&s[(i + 2)..(i + 4)], 16) {
-            Ok(x) => bytes.push(x),
+    while s[i..].starts_with(r"\x") {
+        match s.get((i + 2)..(i + 4)).map(|h| u8::from_str_radix(h, 16)) {
+            Some(Ok(x)) => bytes.push(x),
             _ => {
                 return None;
             }
@@ -111,9 +115,92 @@ fn extract_bytes_x(s: &str) -> Option<(String, usize)> {
     String::from_utf8(bytes).ok().map(|s| (s, i))
 }
 
-/// Returns
-pub fn is_true(val: &Arc<Any>) -> bool {
+/// Quotes a string like Go's `strconv.Quote`. Quotes, backslashes and control
+/// characters are escaped, other characters are kept as they are.
+pub fn quote_str(s: &str) -> String {
+    let mut q = String::with_capacity(s.len() + 2);
+    q.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => q.push_str("\\\""),
+            '\\' => q.push_str("\\\\"),
+            '\u{7}' => q.push_str("\\a"),
+            '\u{8}' => q.push_str("\\b"),
+            '\u{c}' => q.push_str("\\f"),
+            '\n' => q.push_str("\\n"),
+            '\r' => q.push_str("\\r"),
+            '\t' => q.push_str("\\t"),
+            '\u{b}' => q.push_str("\\v"),
+            _ if c.is_control() && c < '\u{80}' => q.push_str(&format!("\\x{:02x}", c as u32)),
+            _ if c.is_control() => q.push_str(&format!("\\u{:04x}", c as u32)),
+            _ => q.push(c),
+        }
+    }
+    q.push('"');
+    q
+}
+
+/// Returns the `Value` stored in `val`, looking through values that are wrapped in
+/// another `Arc`, as custom functions may return them.
+pub fn indirect(val: &Any) -> Option<&Value> {
     if let Some(v) = val.downcast_ref::<Value>() {
+        return Some(v);
+    }
+    if let Some(v) = val.downcast_ref::<Arc<Value>>() {
+        return Some(v);
+    }
+    val.downcast_ref::<Arc<Any>>().and_then(|v| indirect(&**v))
+}
+
+/// Names the type stored in `val` for error messages, as far as it is known.
+pub fn type_name(val: &Any) -> &'static str {
+    macro_rules! names {
+        ($($typ:ty),*) => {
+            $(
+                if val.is::<$typ>() {
+                    return stringify!($typ);
+                }
+            )*
+        }
+    }
+    names!(
+        Value, Arc<Value>, Arc<Any>, String, &'static str, bool, char, u8, u16, u32, u64,
+        usize, i8, i16, i32, i64, isize, f32, f64
+    );
+    "unknown type"
+}
+
+/// Names the kind of a `Value` for error messages that shouldn't print the value.
+pub fn kind_name(val: &Value) -> &'static str {
+    match *val {
+        Value::NoValue => "no value",
+        Value::Nil => "nil",
+        Value::Bool(_) => "bool",
+        Value::String(_) => "string",
+        Value::Object(_) => "object",
+        Value::Map(_) => "map",
+        Value::Array(_) => "array",
+        Value::Function(_) => "function",
+        Value::Number(_) => "number",
+    }
+}
+
+/// Returns the truthiness of a value as used by `if`, `with`, `and`, `or` and `not`.
+///
+/// | value                        | truth        |
+/// |------------------------------|--------------|
+/// | `Bool(b)`                    | `b`          |
+/// | `Number(n)`                  | `n != 0`     |
+/// | `String(s)`                  | non-empty    |
+/// | `Array(a)`                   | non-empty    |
+/// | `Map(m)`, `Object(o)`        | non-empty    |
+/// | `Function(_)`                | `true`       |
+/// | `Nil`, `NoValue`             | `false`      |
+///
+/// Zero is false for floats as well, including `-0.0`, while `NaN` is true as in
+/// Go. Anything that is not a `Value` is false.
+pub fn is_true(val: &Arc<Any>) -> bool {
+    if let Some(v) = indirect(&**val) {
         return match *v {
             Value::Bool(ref b) => *b,
             Value::String(ref s) => !s.is_empty(),
@@ -122,16 +209,39 @@ pub fn is_true(val: &Arc<Any>) -> bool {
             Value::Map(ref m) => !m.is_empty(),
             Value::Function(_) => true,
             Value::NoValue | Value::Nil => false,
-            Value::Number(ref n) => n.as_u64().map(|u| u != 0).unwrap_or_else(|| true),
+            // Only integers too large for an exact `f64` have none, and they aren't 0.
+            Value::Number(ref n) => n.as_f64().map(|f| f != 0.0).unwrap_or(true),
         };
     }
 
     false
 }
 
+/// Formats a value like Go's `%v`. Arrays print as `[a b c]`, maps and objects as
+/// `map[k:v k2:v2]` with sorted keys. A missing value prints as `<no value>`.
+pub fn to_go_string(val: &Value) -> String {
+    match *val {
+        Value::String(ref s) => s.clone(),
+        Value::NoValue => String::from("<no value>"),
+        Value::Array(ref a) => format!("[{}]", a.iter().map(to_go_string).join(" ")),
+        Value::Object(ref o) | Value::Map(ref o) => {
+            let mut keys: Vec<&String> = o.keys().collect();
+            keys.sort();
+            format!(
+                "map[{}]",
+                keys.iter()
+                    .map(|k| format!("{}:{}", k, to_go_string(&o[*k])))
+                    .join(" ")
+            )
+        }
+        _ => val.to_string(),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::collections::HashMap;
 
     #[test]
     fn test_unquote_char() {
@@ -181,6 +291,47 @@ mod tests {
         let s = r#""Fran & Freddie's Diner\t\u263a""#;
         let u = unquote_str(s);
         assert_eq!(u, Some("Fran & Freddie's Diner\t☺".to_owned()));
+        let s = r#""\u263a\n\x41\xe2\x98\xbaB\U0001F4A9\"\\\a\v""#;
+        let u = unquote_str(s);
+        assert_eq!(u, Some("☺\nA☺B💩\"\\\u{7}\u{b}".to_owned()));
+        let s = r#""\u26""#;
+        assert_eq!(unquote_str(s), None);
+        let s = r#""\é""#;
+        assert_eq!(unquote_str(s), None);
+        let s = r#""a\qb""#;
+        assert_eq!(unquote_str(s), None);
+    }
+
+    #[test]
+    fn test_unquote_raw_str() {
+        let s = r#"`a\tb\u263a"`"#;
+        let u = unquote_str(s);
+        assert_eq!(u, Some(r#"a\tb\u263a""#.to_owned()));
+        let s = "`a\r\nb`";
+        let u = unquote_str(s);
+        assert_eq!(u, Some("a\nb".to_owned()));
+    }
+
+    #[test]
+    fn test_indirect() {
+        let v: Arc<Any> = Arc::new(Value::from(1));
+        assert_eq!(indirect(&*v), Some(&Value::from(1)));
+        let v: Arc<Any> = Arc::new(Arc::new(Value::from(1)));
+        assert_eq!(indirect(&*v), Some(&Value::from(1)));
+        let inner: Arc<Any> = Arc::new(Value::from(1));
+        assert_eq!(indirect(&inner), Some(&Value::from(1)));
+        let v: Arc<Any> = Arc::new(1);
+        assert_eq!(indirect(&*v), None);
+    }
+
+    #[test]
+    fn test_type_name() {
+        let v: Arc<Any> = Arc::new(Value::from(1));
+        assert_eq!(type_name(&*v), "Value");
+        let v: Arc<Any> = Arc::new(1u8);
+        assert_eq!(type_name(&*v), "u8");
+        let v: Arc<Any> = Arc::new(vec![1]);
+        assert_eq!(type_name(&*v), "unknown type");
     }
 
     #[test]
@@ -189,5 +340,58 @@ mod tests {
         assert_eq!(is_true(&t), true);
         let t: Arc<Any> = Arc::new(Value::from(0u32));
         assert_eq!(is_true(&t), false);
+
+        let truthy = vec![
+            Value::from(true),
+            Value::from(-1),
+            Value::from(u64::max_value()),
+            Value::from(0.5),
+            Value::from(::std::f64::NAN),
+            Value::from(" "),
+            Value::from(vec![0]),
+            Value::Map(vec![("a".to_owned(), Value::Nil)].into_iter().collect()),
+            Value::Object(vec![("a".to_owned(), Value::Nil)].into_iter().collect()),
+        ];
+        for v in truthy {
+            let t: Arc<Any> = Arc::new(v.clone());
+            assert!(is_true(&t), "{:?} should be true", v);
+        }
+
+        let falsy = vec![
+            Value::from(false),
+            Value::from(0),
+            Value::from(0.0),
+            Value::from(-0.0),
+            Value::from(""),
+            Value::Array(vec![]),
+            Value::Map(HashMap::new()),
+            Value::Object(HashMap::new()),
+            Value::Nil,
+            Value::NoValue,
+        ];
+        for v in falsy {
+            let t: Arc<Any> = Arc::new(v.clone());
+            assert!(!is_true(&t), "{:?} should be false", v);
+        }
+
+        let t: Arc<Any> = Arc::new(Arc::new(Value::from(1)));
+        assert_eq!(is_true(&t), true);
+        let t: Arc<Any> = Arc::new(1);
+        assert_eq!(is_true(&t), false);
+    }
+
+    #[test]
+    fn test_to_go_string() {
+        let v = Value::from(vec![1, 2, 3]);
+        assert_eq!(to_go_string(&v), "[1 2 3]");
+
+        let v = Value::Array(vec![Value::from("a"), Value::from(vec![true, false])]);
+        assert_eq!(to_go_string(&v), "[a [true false]]");
+
+        let mut m = HashMap::new();
+        m.insert("b".to_owned(), Value::from(2));
+        m.insert("a".to_owned(), Value::from("x"));
+        assert_eq!(to_go_string(&Value::Map(m)), "map[a:x b:2]");
+        assert_eq!(to_go_string(&Value::Map(HashMap::new())), "map[]");
     }
 }
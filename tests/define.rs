@@ -14,3 +14,166 @@ fn simple_define() {
     assert!(output.is_ok());
     assert_eq!(output.unwrap(), "there is some template".to_string());
 }
+
+#[test]
+fn empty_define() {
+    let mut template = Template::default();
+    template
+        .parse(r#"{{ define "tmpl"}}{{ end -}} there is {{- template "tmpl" -}} template"#)
+        .unwrap();
+
+    let context = Context::empty();
+
+    let output = template.render(&context);
+    assert!(output.is_ok());
+    assert_eq!(output.unwrap(), "there istemplate".to_string());
+}
+
+#[test]
+fn missing_define() {
+    let mut template = Template::default();
+    template
+        .parse(r#"there is {{- template "tmpl" -}} template"#)
+        .unwrap();
+
+    let context = Context::empty();
+
+    let output = template.render(&context);
+    assert!(output.is_err());
+    assert!(output.unwrap_err().contains(r#"no template "tmpl""#));
+}
+
+#[test]
+fn define_with_pipeline() {
+    use std::collections::HashMap;
+    let mut sub = HashMap::new();
+    sub.insert("name".to_owned(), "sub");
+    let mut map = HashMap::new();
+    map.insert("name".to_owned(), gtmpl::Value::from("parent"));
+    map.insert("sub".to_owned(), gtmpl::Value::from(sub));
+
+    let mut template = Template::default();
+    template
+        .parse(r#"{{ define "tmpl"}}{{ .name }}{{ end -}} {{ .name }} {{ template "tmpl" .sub }}"#)
+        .unwrap();
+
+    let context = Context::from(map);
+
+    let output = template.render(&context);
+    assert!(output.is_ok());
+    assert_eq!(output.unwrap(), "parent sub".to_string());
+}
+
+#[test]
+fn forward_define() {
+    let mut template = Template::default();
+    template
+        .parse(r#"{{ template "a" . }}{{ define "a" }}a{{ template "b" . }}{{ end }}{{ define "b" }}b{{ . }}{{ end }}"#)
+        .unwrap();
+
+    let context = Context::from(1);
+
+    let output = template.render(&context);
+    assert!(output.is_ok());
+    assert_eq!(output.unwrap(), "ab1".to_string());
+}
+
+#[test]
+fn define_before_use() {
+    let mut template = Template::default();
+    template
+        .parse(r#"{{ define "b" }}b{{ end }}{{ define "a" }}a{{ template "b" }}a{{ end }}{{ template "a" }}"#)
+        .unwrap();
+
+    let output = template.render(&Context::empty());
+    assert!(output.is_ok());
+    assert_eq!(output.unwrap(), "aba".to_string());
+}
+
+#[test]
+fn redefine() {
+    let mut template = Template::default();
+    assert!(template
+        .parse(r#"{{ define "a" }}a{{ end }}{{ define "a" }}b{{ end }}"#)
+        .is_err());
+
+    let mut template = Template::default();
+    template
+        .parse(r#"{{ define "a" }}a{{ end }}{{ define "a" }} {{ end }}{{ template "a" }}"#)
+        .unwrap();
+
+    let output = template.render(&Context::empty());
+    assert!(output.is_ok());
+    assert_eq!(output.unwrap(), "a".to_string());
+}
+
+#[test]
+fn nested_define() {
+    let mut template = Template::default();
+    assert!(template
+        .parse(r#"{{ define "a" }}{{ define "b" }}b{{ end }}{{ end }}"#)
+        .is_err());
+}
+
+#[test]
+fn block() {
+    let mut template = Template::default();
+    template
+        .parse(r#"<{{ block "content" .name }}default {{ . }}{{ end }}>"#)
+        .unwrap();
+
+    let mut map = ::std::collections::HashMap::new();
+    map.insert("name".to_owned(), "gtmpl");
+    let context = Context::from(map);
+
+    let output = template.render(&context);
+    assert!(output.is_ok());
+    assert_eq!(output.unwrap(), "<default gtmpl>".to_string());
+
+    template
+        .parse(r#"{{ define "content" }}override {{ . }}{{ end }}"#)
+        .unwrap();
+
+    let output = template.render(&context);
+    assert!(output.is_ok());
+    assert_eq!(output.unwrap(), "<override gtmpl>".to_string());
+}
+
+#[test]
+fn block_redefined_in_same_parse() {
+    let mut template = Template::default();
+    assert!(template
+        .parse(r#"{{ block "a" . }}a{{ end }}{{ define "a" }}b{{ end }}"#)
+        .is_err());
+}
+
+#[test]
+fn trim_around_template_actions() {
+    let mut template = Template::default();
+    template
+        .parse(
+            "{{- define \"item\" -}}\n  <li>{{ . }}</li>\n{{- end -}}\n\n<ul>\n  {{- range . }}\n    {{- template \"item\" . -}}\n  {{ end -}}\n</ul>\n\t{{- block \"footer\" . -}}\n  <p>{{ len . }}</p>\n{{- end }}",
+        )
+        .unwrap();
+
+    let context = Context::from(vec![1, 2]);
+
+    let output = template.render(&context);
+    assert!(output.is_ok());
+    assert_eq!(
+        output.unwrap(),
+        "<ul><li>1</li><li>2</li></ul><p>2</p>".to_string()
+    );
+}
+
+#[test]
+fn trim_keeps_untrimmed_side() {
+    let mut template = Template::default();
+    template
+        .parse("{{ define \"x\" }} x {{ end }}a \n{{- template \"x\" }} b|a {{ template \"x\" -}} \n b")
+        .unwrap();
+
+    let output = template.render(&Context::empty());
+    assert!(output.is_ok());
+    assert_eq!(output.unwrap(), "a x  b|a  x b".to_string());
+}